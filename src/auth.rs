@@ -0,0 +1,375 @@
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION},
+    Request, Url,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::client::TransportOptions;
+use crate::config::is_default;
+use crate::HttpClient;
+
+/// A cached token is renewed when it expires in less than this.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+/// Credentials of a request, from the `auth:` block of a profile.
+///
+/// Secrets are usually written as `${ENV}` placeholders, so that they stay out of the
+/// config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    /// `authorization: Basic <base64 of username:password>`.
+    Basic {
+        #[serde(alias = "user")]
+        username: String,
+        #[serde(default)]
+        password: String,
+    },
+
+    /// `authorization: Bearer <token>`.
+    Bearer { token: String },
+
+    /// A key sent as a header or as a query param.
+    ApiKey {
+        name: String,
+        value: String,
+        #[serde(rename = "in", skip_serializing_if = "is_default", default)]
+        location: ApiKeyLocation,
+    },
+
+    /// A bearer token fetched from the token url, then cached for the run.
+    Oauth2ClientCredentials(OAuth2ClientCredentials),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OAuth2ClientCredentials {
+    pub token_url: Url,
+    pub client_id: String,
+    pub client_secret: String,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scope: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub audience: Option<String>,
+
+    /// Send the client credentials in the form body instead of a basic auth header.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub credentials_in_body: bool,
+}
+
+impl Auth {
+    /// Set the credentials known without a network call. OAuth2 is left to [`Auth::apply_oauth2`].
+    pub fn apply(&self, req: &mut Request) -> Result<()> {
+        match self {
+            Auth::Basic { username, password } => {
+                let credentials = STANDARD.encode(format!("{}:{}", username, password));
+                set_authorization(req, &format!("Basic {}", credentials))
+            }
+            Auth::Bearer { token } => set_authorization(req, &format!("Bearer {}", token)),
+            Auth::ApiKey {
+                name,
+                value,
+                location: ApiKeyLocation::Header,
+            } => {
+                req.headers_mut()
+                    .insert(HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+                Ok(())
+            }
+            Auth::ApiKey {
+                name,
+                value,
+                location: ApiKeyLocation::Query,
+            } => {
+                req.url_mut().query_pairs_mut().append_pair(name, value);
+                Ok(())
+            }
+            Auth::Oauth2ClientCredentials(_) => Ok(()),
+        }
+    }
+
+    /// Set the OAuth2 bearer token, fetching it if it is not cached or about to expire.
+    pub async fn apply_oauth2(&self, client: &HttpClient, req: &mut Request) -> Result<()> {
+        match self {
+            Auth::Oauth2ClientCredentials(oauth2) => {
+                let token = client.tokens().get(client, oauth2).await?;
+                set_authorization(req, &format!("Bearer {}", token))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn set_authorization(req: &mut Request, value: &str) -> Result<()> {
+    let mut value = HeaderValue::from_str(value)?;
+    value.set_sensitive(true);
+    req.headers_mut().insert(AUTHORIZATION, value);
+    Ok(())
+}
+
+/// OAuth2 tokens fetched during a run, shared by all the profiles.
+#[derive(Debug, Clone, Default)]
+pub struct TokenCache {
+    /// One slot per set of credentials, locked while its token is fetched.
+    tokens: Arc<Mutex<HashMap<OAuth2ClientCredentials, TokenSlot>>>,
+}
+
+type TokenSlot = Arc<tokio::sync::Mutex<Option<CachedToken>>>;
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    token: String,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl TokenCache {
+    pub async fn get(
+        &self,
+        client: &HttpClient,
+        oauth2: &OAuth2ClientCredentials,
+    ) -> Result<String> {
+        // the slot is locked while fetching, so that concurrent profiles share one token,
+        // without waiting for the tokens of other credentials
+        let slot = self.slot(oauth2);
+        let mut cached = slot.lock().await;
+
+        if let Some(cached) = cached.as_ref() {
+            let expired = cached
+                .expires_at
+                .is_some_and(|at| Instant::now() + EXPIRY_MARGIN >= at);
+            if !expired {
+                return Ok(cached.token.clone());
+            }
+        }
+
+        let token = fetch_token(client, oauth2).await?;
+        Ok(cached.insert(token).token.clone())
+    }
+
+    /// Forget the token of these credentials, e.g. when it was rejected before it expired.
+    pub async fn invalidate(&self, oauth2: &OAuth2ClientCredentials) {
+        *self.slot(oauth2).lock().await = None;
+    }
+
+    fn slot(&self, oauth2: &OAuth2ClientCredentials) -> TokenSlot {
+        let mut tokens = self.tokens.lock().expect("token cache poisoned");
+        tokens.entry(oauth2.clone()).or_default().clone()
+    }
+}
+
+async fn fetch_token(client: &HttpClient, oauth2: &OAuth2ClientCredentials) -> Result<CachedToken> {
+    let mut form = vec![("grant_type", "client_credentials")];
+    if let Some(scope) = &oauth2.scope {
+        form.push(("scope", scope));
+    }
+    if let Some(audience) = &oauth2.audience {
+        form.push(("audience", audience));
+    }
+
+    let mut builder = client.client().post(oauth2.token_url.clone());
+    if oauth2.credentials_in_body {
+        form.push(("client_id", &oauth2.client_id));
+        form.push(("client_secret", &oauth2.client_secret));
+    } else {
+        builder = builder.basic_auth(&oauth2.client_id, Some(&oauth2.client_secret));
+    }
+
+    let requested_at = Instant::now();
+    // sent with the timeouts and retry policy of the `client:` block
+    let req = builder.form(&form).build()?;
+    let res = client
+        .send(req, &TransportOptions::default())
+        .await
+        .with_context(|| format!("failed to fetch oauth2 token from {}", oauth2.token_url))?;
+
+    let status = res.status();
+    if !status.is_success() {
+        let body = res.text().await.unwrap_or_default();
+        return Err(anyhow!(
+            "failed to fetch oauth2 token from {}: {}\n{}",
+            oauth2.token_url,
+            status,
+            body
+        ));
+    }
+
+    let body = res.text().await?;
+    let token: TokenResponse = serde_json::from_str(&body)
+        .with_context(|| format!("invalid oauth2 token response from {}", oauth2.token_url))?;
+
+    Ok(CachedToken {
+        token: token.access_token,
+        expires_at: token
+            .expires_in
+            .map(|secs| requested_at + Duration::from_secs(secs)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(url: &str) -> Request {
+        reqwest::Client::new().get(url).build().unwrap()
+    }
+
+    #[test]
+    fn auth_should_parse_from_yaml() {
+        let auth: Auth =
+            serde_yaml::from_str("type: api_key\nname: key\nvalue: abc\nin: query").unwrap();
+        assert_eq!(
+            auth,
+            Auth::ApiKey {
+                name: "key".into(),
+                value: "abc".into(),
+                location: ApiKeyLocation::Query
+            }
+        );
+
+        let auth: Auth = serde_yaml::from_str(
+            "type: oauth2_client_credentials\ntoken_url: https://a.com/token\nclient_id: id\nclient_secret: secret",
+        )
+        .unwrap();
+        assert!(matches!(auth, Auth::Oauth2ClientCredentials(o) if o.client_id == "id"));
+    }
+
+    #[test]
+    fn static_auth_should_apply() {
+        let mut req = request("https://a.com/x?a=1");
+        let auth = Auth::Basic {
+            username: "user".into(),
+            password: "pass".into(),
+        };
+        auth.apply(&mut req).unwrap();
+        assert_eq!(req.headers()[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+
+        let auth = Auth::ApiKey {
+            name: "api_key".into(),
+            value: "a b".into(),
+            location: ApiKeyLocation::Query,
+        };
+        auth.apply(&mut req).unwrap();
+        assert_eq!(req.url().as_str(), "https://a.com/x?a=1&api_key=a+b");
+    }
+
+    #[tokio::test]
+    async fn oauth2_token_should_be_cached_and_refreshed() {
+        let mut server = mockito::Server::new_async().await;
+        let short = server
+            .mock("POST", "/token")
+            .match_header("authorization", "Basic aWQ6c2VjcmV0")
+            .match_body("grant_type=client_credentials&scope=read")
+            .with_body(r#"{"access_token": "t1", "token_type": "bearer", "expires_in": 5}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let oauth2 = OAuth2ClientCredentials {
+            token_url: format!("{}/token", server.url()).parse().unwrap(),
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            scope: Some("read".into()),
+            audience: None,
+            credentials_in_body: false,
+        };
+        let auth = Auth::Oauth2ClientCredentials(oauth2.clone());
+        let client = HttpClient::default();
+
+        // expires within the margin, so it is fetched again
+        let mut req = request("https://a.com/");
+        auth.apply_oauth2(&client, &mut req).await.unwrap();
+        auth.apply_oauth2(&client, &mut req).await.unwrap();
+        assert_eq!(req.headers()[AUTHORIZATION], "Bearer t1");
+        short.assert_async().await;
+        short.remove_async().await;
+
+        let long = server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token": "t2", "expires_in": 3600}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let tokens = TokenCache::default();
+        for _ in 0..3 {
+            assert_eq!(tokens.get(&client, &oauth2).await.unwrap(), "t2");
+        }
+        long.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn rejected_oauth2_token_should_be_fetched_again_once() {
+        let mut server = mockito::Server::new_async().await;
+        let oauth2 = OAuth2ClientCredentials {
+            token_url: format!("{}/token", server.url()).parse().unwrap(),
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            scope: None,
+            audience: None,
+            credentials_in_body: true,
+        };
+        let client = HttpClient::default();
+
+        let revoked = server
+            .mock("POST", "/token")
+            .match_body("grant_type=client_credentials&client_id=id&client_secret=secret")
+            .with_body(r#"{"access_token": "t1", "expires_in": 3600}"#)
+            .create_async()
+            .await;
+        client.tokens().get(&client, &oauth2).await.unwrap();
+        revoked.remove_async().await;
+
+        let token = server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token": "t2", "expires_in": 3600}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let rejected = server
+            .mock("GET", "/todo")
+            .match_header("authorization", "Bearer t1")
+            .with_status(401)
+            .expect(1)
+            .create_async()
+            .await;
+        let accepted = server
+            .mock("GET", "/todo")
+            .match_header("authorization", "Bearer t2")
+            .with_body("ok")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut profile: crate::RequestProfile = format!("{}/todo", server.url()).parse().unwrap();
+        profile.auth = Some(Auth::Oauth2ClientCredentials(oauth2));
+        let res = profile
+            .send(&client, &Default::default())
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(res.status(), 200);
+        token.assert_async().await;
+        rejected.assert_async().await;
+        accepted.assert_async().await;
+    }
+}
@@ -1,13 +1,23 @@
 use std::io::Write;
+use std::process::ExitCode;
 
-use anyhow::{Ok, Result};
-use clap::{Parser, Subcommand};
+use anyhow::{anyhow, Ok, Result};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use console::style;
 use dialoguer::{theme::ColorfulTheme, Input, MultiSelect};
-use serde_yaml;
+use regex::Regex;
+use reqwest::Method;
+use serde_json::{json, Value};
 use xdiff::{
     cli::{parse_key_value, KeyVal},
-    highlight_text, process_error_output, DiffConfig, DiffProfile, ExtraArgs, LoadConfig,
-    RequestProfile, ResponseProfile,
+    error_chain,
+    export::{export_request, ExportFormat},
+    highlight_text,
+    import::{import_har_diff, import_openapi_diff, parse_curl, HarFilter, Imported},
+    process_error_output, render_terminal, render_unified,
+    snapshot::{SnapshotStore, DEFAULT_SNAPSHOT_DIR},
+    DiffConfig, DiffProfile, DiffResult, ExtraArgs, HttpClient, LoadConfig, RequestProfile,
+    ResponseProfile,
 };
 
 /// Diff two http requests and compare the difference of the responses
@@ -24,44 +34,592 @@ enum Action {
     /// Diff two API response based on given profile.
     Run(RunArgs),
 
+    /// Record or verify snapshots of the `req1` responses.
+    Snapshot(SnapshotArgs),
+
     /// Parse URLs to generate a profile.
-    Parse,
+    Parse(ParseArgs),
+
+    /// Print the resolved requests of a profile as curl commands.
+    Curl(CurlArgs),
+
+    /// Generate profiles from recorded traffic.
+    Import(ImportArgs),
 }
 
 #[derive(Parser, Debug, Clone)]
-struct RunArgs {
+struct CurlArgs {
     /// The profile name
     #[clap(short, long, value_parser)]
     profile: String,
 
-    /// Overrides args. Could be used to override the query, headers and body of the request.
-    /// for query params, use `-e key=value`
-    /// for headers, use `-e %key=value`
-    /// for body, use `-e @key=value`
-    #[clap(short, long, value_parser = parse_key_value, number_of_values = 1)]
+    /// Only print this request of the profile. Both are printed by default.
+    #[clap(short, long, value_enum)]
+    req: Option<Side>,
+
+    /// Overrides args, see `xdiff run --help`.
+    #[clap(
+        short,
+        long,
+        value_parser = parse_key_value,
+        number_of_values = 1
+    )]
     extra_params: Vec<KeyVal>,
 
     /// Configuration to use
     #[clap(short, long, value_parser)]
     config: Option<String>,
+
+    /// Output format
+    #[clap(short, long, value_enum, default_value_t)]
+    format: ExportFormat,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Req1,
+    Req2,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct ImportArgs {
+    #[clap(subcommand)]
+    action: ImportAction,
 }
 
+#[derive(Subcommand, Debug, Clone)]
+enum ImportAction {
+    /// Generate diff profiles from a HAR 1.2 file.
+    Har(HarArgs),
+
+    /// Generate diff profiles comparing two servers of an OpenAPI 3 document.
+    Openapi(OpenapiArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+struct HarArgs {
+    /// The HAR file, as exported from the browser devtools
+    #[clap(value_parser)]
+    file: String,
+
+    /// Only import the entries whose url matches this regex.
+    #[clap(short, long, value_parser)]
+    url: Option<Regex>,
+
+    /// Only import the entries with this method.
+    #[clap(short, long, value_parser)]
+    method: Option<Method>,
+
+    /// Host the recorded requests are compared against, as `host[:port]` or a base url.
+    #[clap(long, value_parser)]
+    host: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct OpenapiArgs {
+    /// The OpenAPI document, in JSON or YAML
+    #[clap(value_parser)]
+    file: String,
+
+    /// Server of `req1`: the description, url or index of a server of the document,
+    /// or a base url.
+    #[clap(long, value_parser)]
+    server1: String,
+
+    /// Server of `req2`, selected like `--server1`.
+    #[clap(long, value_parser)]
+    server2: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct ParseArgs {
+    /// Ask for curl commands instead of URLs.
+    #[clap(long)]
+    curl: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[clap(group(ArgGroup::new("profiles").required(true).args(["profile", "all", "tag"])))]
+struct ProfileArgs {
+    /// The profile name
+    #[clap(short, long, value_parser)]
+    profile: Option<String>,
+
+    /// Run every profile in the config file.
+    #[clap(long)]
+    all: bool,
+
+    /// Run the profiles carrying this tag. Could be given multiple times.
+    #[clap(short, long, value_parser, number_of_values = 1)]
+    tag: Vec<String>,
+
+    /// Maximum number of profiles to run at the same time.
+    #[clap(short = 'j', long, value_parser, default_value_t = 4)]
+    parallel: usize,
+
+    /// Overrides args, for the query (`key=value`), headers (`%key=value`) or body (`@key=value`).
+    ///
+    /// Values are strings. Use `-e @key:=json` for numbers, booleans, arrays or objects.
+    /// Use `-e @key=@path` or `-e @key:=@path` to read a value from a file.
+    /// Query and body keys are paths, such as `-e @user.address.city=Paris` or `-e @items[0].qty:=3`.
+    /// Use `-e @tags[]=value` to append to an array.
+    /// Use `-e key!` to remove a query param, and `-e @-key` to remove a body field.
+    #[clap(
+        short,
+        long,
+        value_parser = parse_key_value,
+        number_of_values = 1
+    )]
+    extra_params: Vec<KeyVal>,
+
+    /// Configuration to use
+    #[clap(short, long, value_parser)]
+    config: Option<String>,
+
+    /// Report retried requests on stderr.
+    #[clap(short, long)]
+    verbose: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[clap(after_help = "Exit codes:
+  0  the responses are identical, or --check is not set
+  1  the responses differ (with --check or --quiet)
+  2  the config could not be loaded or a request failed")]
+struct RunArgs {
+    #[clap(flatten)]
+    profiles: ProfileArgs,
+
+    /// Exit with code 1 if the responses differ.
+    #[clap(long)]
+    check: bool,
+
+    /// Only print a one-line summary. Implies `--check`.
+    #[clap(short, long)]
+    quiet: bool,
+
+    /// Output format of the diff
+    #[clap(short, long, value_enum, default_value_t, conflicts_with = "quiet")]
+    format: DiffFormat,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum DiffFormat {
+    /// Colored diff for the terminal, followed by a summary table for several profiles.
+    #[default]
+    Terminal,
+
+    /// A plain `git diff` style patch, with one file per section of the responses.
+    Unified,
+
+    /// The hunks and summary of each profile as JSON.
+    Json,
+
+    /// The RFC 6902 operations turning the body of `req1` into the body of `req2`, for
+    /// each profile.
+    JsonPatch,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct SnapshotArgs {
+    #[clap(subcommand)]
+    action: SnapshotAction,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum SnapshotAction {
+    /// Save the normalized `req1` responses as the baseline.
+    Record(RecordArgs),
+
+    /// Diff the live `req1` responses against the recorded baseline.
+    Verify(VerifyArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+struct RecordArgs {
+    #[clap(flatten)]
+    profiles: ProfileArgs,
+
+    /// Directory holding the snapshots
+    #[clap(short, long, value_parser, default_value = DEFAULT_SNAPSHOT_DIR)]
+    dir: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[clap(after_help = "Exit codes:
+  0  the responses match the snapshots, or --update is set
+  1  a response differs from its snapshot
+  2  the config or a snapshot could not be loaded, or a request failed")]
+struct VerifyArgs {
+    #[clap(flatten)]
+    profiles: ProfileArgs,
+
+    /// Directory holding the snapshots
+    #[clap(short, long, value_parser, default_value = DEFAULT_SNAPSHOT_DIR)]
+    dir: String,
+
+    /// Accept the changes and overwrite the differing snapshots.
+    #[clap(short, long)]
+    update: bool,
+
+    /// Only print the summary table.
+    #[clap(short, long)]
+    quiet: bool,
+}
+
+impl ProfileArgs {
+    /// Load the config file and resolve the names of the selected profiles.
+    async fn load(&self) -> Result<(DiffConfig, Vec<String>)> {
+        let config_file = self
+            .config
+            .clone()
+            .unwrap_or_else(|| "./xdiff.yaml".to_string());
+        let config = DiffConfig::load_yaml(&config_file).await?;
+
+        let names = match &self.profile {
+            Some(name) if config.get_profile(name).is_none() => {
+                return Err(anyhow!(
+                    "Profile {} not found in config file {}",
+                    name,
+                    config_file
+                ))
+            }
+            Some(name) => vec![name.clone()],
+            None if self.all => config.profile_names(&[]),
+            None => config.profile_names(&self.tag),
+        };
+
+        if names.is_empty() && self.all {
+            return Err(anyhow!("No profiles in config file {}", config_file));
+        }
+
+        if names.is_empty() {
+            return Err(anyhow!(
+                "No profile tagged with {} found in config file {}",
+                self.tag.join(", "),
+                config_file
+            ));
+        }
+
+        Ok((config, names))
+    }
+
+    fn extra_args(&self) -> ExtraArgs {
+        ExtraArgs::from(self.extra_params.clone())
+    }
+}
+
+/// Exit code when the responses are identical.
+const EXIT_SAME: u8 = 0;
+/// Exit code when the responses differ in check mode.
+const EXIT_DIFFERENT: u8 = 1;
+/// Exit code when the config or a request failed.
+const EXIT_ERROR: u8 = 2;
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let args = Args::parse();
 
     let result = match args.action {
         Action::Run(args) => run(args).await,
-        Action::Parse => parse().await,
+        Action::Snapshot(args) => snapshot(args).await,
+        Action::Parse(args) => parse(args).await.map(|_| EXIT_SAME),
+        Action::Curl(args) => curl(args).await.map(|_| EXIT_SAME),
+        Action::Import(args) => import(args).await.map(|_| EXIT_SAME),
         // _ => panic!("Not implemented yet"),
     };
 
-    process_error_output(result)
+    match result {
+        Result::Ok(code) => Ok(ExitCode::from(code)),
+        Err(e) => {
+            process_error_output(Err(e))?;
+            Ok(ExitCode::from(EXIT_ERROR))
+        }
+    }
 }
 
-async fn run(args: RunArgs) -> Result<()> {
+async fn run(args: RunArgs) -> Result<u8> {
+    let (config, names) = args.profiles.load().await?;
+    let client = HttpClient::new(&config.client)?.verbose(args.profiles.verbose);
+    let extra_args = args.profiles.extra_args();
+    let check = args.check || args.quiet;
+
+    // the JSON formats print an array of entries, even for a single profile
+    let json = matches!(args.format, DiffFormat::Json | DiffFormat::JsonPatch);
+    let name = match &args.profiles.profile {
+        Some(name) if !json => name,
+        _ => {
+            let results = config
+                .diff_profiles(&client, &names, &extra_args, args.profiles.parallel)
+                .await;
+            return match args.format {
+                DiffFormat::Terminal => print_results(&results, args.quiet, check),
+                format => print_formatted(&results, format, check),
+            };
+        }
+    };
+
+    let profile = config.get_profile(name).expect("profile was resolved");
+    let result = profile.diff(&client, extra_args).await?;
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    match args.format {
+        DiffFormat::Terminal if args.quiet => writeln!(stdout, "{}: {}", name, result.summary())?,
+        DiffFormat::Terminal => write!(stdout, "{}", render_terminal(&result)?)?,
+        DiffFormat::Unified => write!(stdout, "{}", render_unified(&result, name)?)?,
+        DiffFormat::Json | DiffFormat::JsonPatch => unreachable!("printed as entries"),
+    }
+
+    if check && !result.is_empty() {
+        return Ok(EXIT_DIFFERENT);
+    }
+
+    Ok(EXIT_SAME)
+}
+
+async fn snapshot(args: SnapshotArgs) -> Result<u8> {
+    match args.action {
+        SnapshotAction::Record(args) => record_snapshots(args).await,
+        SnapshotAction::Verify(args) => verify_snapshots(args).await,
+    }
+}
+
+async fn record_snapshots(args: RecordArgs) -> Result<u8> {
+    let (config, names) = args.profiles.load().await?;
+    let client = HttpClient::new(&config.client)?.verbose(args.profiles.verbose);
+    let extra_args = args.profiles.extra_args();
+    let store = SnapshotStore::new(&args.dir);
+
+    let results = config
+        .for_each_profile(&names, args.profiles.parallel, |name, profile| {
+            let client = client.clone();
+            let args = extra_args.clone();
+            let store = store.clone();
+            async move {
+                let text = profile.snapshot(&client, &args).await?;
+                store.save(&name, &text).await?;
+                Ok(store.path(&name))
+            }
+        })
+        .await;
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    let mut code = EXIT_SAME;
+
+    for (name, result) in results {
+        match result {
+            Result::Ok(path) => writeln!(
+                stdout,
+                "{} {} -> {}",
+                style("recorded").green(),
+                name,
+                path.display()
+            )?,
+            Err(e) => {
+                code = EXIT_ERROR;
+                writeln!(stdout, "{} {}: {}", style("error").red(), name, e)?;
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+async fn verify_snapshots(args: VerifyArgs) -> Result<u8> {
+    let (config, names) = args.profiles.load().await?;
+    let client = HttpClient::new(&config.client)?.verbose(args.profiles.verbose);
+    let extra_args = args.profiles.extra_args();
+    let store = SnapshotStore::new(&args.dir);
+    let update = args.update;
+
+    let results = config
+        .for_each_profile(&names, args.profiles.parallel, |name, profile| {
+            let client = client.clone();
+            let args = extra_args.clone();
+            let store = store.clone();
+            async move {
+                let snapshot = store.load(&name).await?;
+                let (result, live) = profile.diff_snapshot(&client, &snapshot, &args).await?;
+                if update && !result.is_empty() {
+                    store.save(&name, &live).await?;
+                }
+                Ok(result)
+            }
+        })
+        .await;
+
+    let code = print_results(&results, args.quiet, !update)?;
+
+    if update {
+        let updated = results
+            .iter()
+            .filter(|(_, r)| matches!(r, Result::Ok(r) if !r.is_empty()))
+            .count();
+        writeln!(
+            std::io::stdout(),
+            "updated {} snapshots in {}",
+            updated,
+            store.dir().display()
+        )?;
+    }
+
+    Ok(code)
+}
+
+/// Print the diffs of several profiles followed by a pass/fail summary table.
+fn print_results(results: &[(String, Result<DiffResult>)], quiet: bool, check: bool) -> Result<u8> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    if !quiet {
+        for (name, result) in results {
+            if let Result::Ok(result) = result {
+                if !result.is_empty() {
+                    writeln!(stdout, "{}", style(format!("=== {} ===", name)).bold())?;
+                    write!(stdout, "{}", render_terminal(result)?)?;
+                }
+            }
+        }
+    }
+
+    let width = results
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default()
+        .max("PROFILE".len());
+
+    writeln!(stdout, "{:<width$}  {:<6}  DETAILS", "PROFILE", "RESULT")?;
+
+    let (mut passed, mut failed, mut errored) = (0, 0, 0);
+    for (name, result) in results {
+        let (status, details) = match result {
+            Result::Ok(result) if result.is_empty() => {
+                passed += 1;
+                (
+                    style(format!("{:<6}", "pass")).green(),
+                    "no differences".into(),
+                )
+            }
+            Result::Ok(result) => {
+                failed += 1;
+                (
+                    style(format!("{:<6}", "fail")).yellow(),
+                    result.summary().to_string(),
+                )
+            }
+            Err(e) => {
+                errored += 1;
+                (style(format!("{:<6}", "error")).red(), error_chain(e))
+            }
+        };
+
+        writeln!(stdout, "{:<width$}  {}  {}", name, status, details)?;
+    }
+
+    writeln!(
+        stdout,
+        "\n{} profiles: {} passed, {} failed, {} errored",
+        results.len(),
+        passed,
+        failed,
+        errored
+    )?;
+
+    if errored > 0 {
+        return Ok(EXIT_ERROR);
+    }
+
+    if check && failed > 0 {
+        return Ok(EXIT_DIFFERENT);
+    }
+
+    Ok(EXIT_SAME)
+}
+
+/// Print the diffs of several profiles in a machine readable format. A unified diff
+/// reports the errors on stderr, JSON formats print an array of one entry per profile.
+fn print_formatted(
+    results: &[(String, Result<DiffResult>)],
+    format: DiffFormat,
+    check: bool,
+) -> Result<u8> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    let mut errored = false;
+
+    let mut entries = vec![];
+    for (name, result) in results {
+        // errors are reported with their whole chain of causes
+        let entry = match (result, format) {
+            (Result::Ok(result), DiffFormat::Unified) => {
+                write!(stdout, "{}", render_unified(result, name)?)?;
+                continue;
+            }
+            (Result::Ok(result), DiffFormat::JsonPatch) => result
+                .json_patch()
+                .map(|patch| {
+                    json!({
+                        "profile": name,
+                        "result": if result.is_empty() { "pass" } else { "fail" },
+                        "patch": patch,
+                    })
+                })
+                .map_err(|e| error_chain(&e)),
+            (Result::Ok(result), _) => Result::Ok(json_report(name, result)),
+            (Err(e), _) => Err(error_chain(e)),
+        };
+
+        match entry {
+            Result::Ok(entry) => entries.push(entry),
+            Err(e) => {
+                errored = true;
+                match format {
+                    DiffFormat::Unified => eprintln!("error {}: {}", name, e),
+                    _ => entries.push(json!({
+                        "profile": name,
+                        "result": "error",
+                        "error": e,
+                    })),
+                }
+            }
+        }
+    }
+
+    if format != DiffFormat::Unified {
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&entries)?)?;
+    }
+
+    if errored {
+        return Ok(EXIT_ERROR);
+    }
+
+    if check
+        && results
+            .iter()
+            .any(|(_, r)| matches!(r, Result::Ok(r) if !r.is_empty()))
+    {
+        return Ok(EXIT_DIFFERENT);
+    }
+
+    Ok(EXIT_SAME)
+}
+
+/// The diff of a profile as reported by `--format json`.
+fn json_report(name: &str, result: &DiffResult) -> Value {
+    json!({
+        "profile": name,
+        "result": if result.is_empty() { "pass" } else { "fail" },
+        "summary": result.summary(),
+        "diff": result,
+    })
+}
+
+async fn curl(args: CurlArgs) -> Result<()> {
     let config_file = args.config.unwrap_or_else(|| "./xdiff.yaml".to_string());
-    // let profile = config
     let config = DiffConfig::load_yaml(&config_file).await?;
     let profile = config.get_profile(&args.profile).ok_or_else(|| {
         anyhow::anyhow!(
@@ -72,33 +630,88 @@ async fn run(args: RunArgs) -> Result<()> {
     })?;
 
     let extra_args = ExtraArgs::from(args.extra_params);
-    let result = profile.diff(extra_args).await?;
+    let client = HttpClient::new(&config.client)?;
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    let requests = [
+        (Side::Req1, "req1", &profile.req1),
+        (Side::Req2, "req2", &profile.req2),
+    ];
+
+    for (side, label, req) in requests {
+        if args.req.is_some_and(|r| r != side) {
+            continue;
+        }
+
+        if args.req.is_none() {
+            writeln!(stdout, "# {}", label)?;
+        }
+        let output = export_request(req, &client, &extra_args, args.format).await?;
+        writeln!(stdout, "{}", output)?;
+    }
+
+    Ok(())
+}
+
+async fn import(args: ImportArgs) -> Result<()> {
+    let Imported { config, warnings } = match args.action {
+        ImportAction::Har(args) => {
+            let content = tokio::fs::read_to_string(&args.file).await?;
+            let filter = HarFilter {
+                url_pattern: args.url,
+                method: args.method,
+            };
+            import_har_diff(&content, &filter, &args.host)?
+        }
+        ImportAction::Openapi(args) => {
+            let content = tokio::fs::read_to_string(&args.file).await?;
+            import_openapi_diff(&content, &args.server1, &args.server2)?
+        }
+    };
+
+    for warning in &warnings {
+        eprintln!("{} {}", style("warning:").yellow().bold(), warning);
+    }
+
+    let result = serde_yaml::to_string(&config)?;
 
     let stdout = std::io::stdout();
     let mut stdout = stdout.lock();
-    write!(stdout, "{}", result)?;
+
+    if atty::is(atty::Stream::Stdout) {
+        write!(stdout, "{}", highlight_text(&result, "yaml", None)?)?;
+    } else {
+        write!(stdout, "{}", result)?;
+    }
 
     Ok(())
 }
 
-async fn parse() -> Result<()> {
+async fn parse(args: ParseArgs) -> Result<()> {
     let color_theme = ColorfulTheme::default();
 
-    let url1: String = Input::with_theme(&color_theme)
-        .with_prompt("URL 1")
+    let prompt = if args.curl { "curl command" } else { "URL" };
+    let input1: String = Input::with_theme(&color_theme)
+        .with_prompt(format!("{} 1", prompt))
         .interact_text()?;
-    let url2: String = Input::with_theme(&color_theme)
-        .with_prompt("URL 2")
+    let input2: String = Input::with_theme(&color_theme)
+        .with_prompt(format!("{} 2", prompt))
         .interact_text()?;
     let name: String = Input::with_theme(&color_theme)
         .with_prompt("Profile Name")
         .interact_text()?;
 
-    let req1: RequestProfile = url1.parse()?;
-    let req2: RequestProfile = url2.parse()?;
+    let (req1, req2): (RequestProfile, RequestProfile) = if args.curl {
+        (parse_curl(&input1)?, parse_curl(&input2)?)
+    } else {
+        (input1.parse()?, input2.parse()?)
+    };
 
-    let res1 = req1.send(&ExtraArgs::default()).await?;
-    // let res2 = req2.send(&ExtraArgs::default()).await?;
+    let res1 = req1
+        .send(&HttpClient::default(), &ExtraArgs::default())
+        .await?;
 
     let headers = res1.get_header_keys();
 
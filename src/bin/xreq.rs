@@ -1,12 +1,22 @@
-use anyhow::{Ok, Result};
+use anyhow::{Context, Ok, Result};
 use clap::{Parser, Subcommand};
+use console::style;
 use dialoguer::{theme::ColorfulTheme, Input};
+use regex::Regex;
+use reqwest::{header::HeaderMap, Method, StatusCode};
+use std::collections::HashMap;
 use std::fmt::Write as _;
 use std::io::Write as _;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 use xdiff::{
     cli::{parse_key_value, KeyVal},
-    get_body_text, get_header_text, get_status_text, highlight_text, process_error_output,
-    LoadConfig, RequestConfig, RequestProfile,
+    export::{export_request, ExportFormat},
+    format_body_text, get_header_text, get_status_text, highlight_text,
+    import::{import_har, import_openapi, import_postman, parse_curl, HarFilter, Imported},
+    process_error_output,
+    session::{SessionStore, DEFAULT_SESSION_DIR},
+    ExtraArgs, HttpClient, LoadConfig, RequestConfig, RequestProfile,
 };
 
 /// Diff two http requests and compare the difference of the responses
@@ -23,82 +33,508 @@ enum Action {
     /// Diff two API response based on given profile.
     Run(RunArgs),
 
+    /// Run the steps of a flow in order, passing captured variables along.
+    Flow(FlowArgs),
+
     /// Parse URLs to generate a profile.
-    Parse,
+    Parse(ParseArgs),
+
+    /// Print the resolved request of a profile as a curl command.
+    Curl(CurlArgs),
+
+    /// Generate profiles from recorded traffic.
+    Import(ImportArgs),
+
+    /// Manage the cookie jars kept by `xreq run --session`.
+    Session(SessionArgs),
 }
 
 #[derive(Parser, Debug, Clone)]
+struct SessionArgs {
+    #[clap(subcommand)]
+    action: SessionAction,
+
+    /// Directory holding the sessions
+    #[clap(short, long, value_parser, default_value = DEFAULT_SESSION_DIR, global = true)]
+    dir: String,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum SessionAction {
+    /// List the sessions and their cookies.
+    List,
+
+    /// Delete a session, or all of them with `--all`.
+    Clear(ClearArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+struct ClearArgs {
+    /// The session name
+    #[clap(value_parser, required_unless_present = "all")]
+    name: Option<String>,
+
+    /// Delete all the sessions.
+    #[clap(short, long, conflicts_with = "name")]
+    all: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct CurlArgs {
+    /// The profile name
+    #[clap(short, long, value_parser)]
+    profile: String,
+
+    /// Overrides args, see `xreq run --help`.
+    #[clap(
+        short,
+        long,
+        value_parser = parse_key_value,
+        number_of_values = 1
+    )]
+    extra_params: Vec<KeyVal>,
+
+    /// Configuration to use
+    #[clap(short, long, value_parser)]
+    config: Option<String>,
+
+    /// Output format
+    #[clap(short, long, value_enum, default_value_t)]
+    format: ExportFormat,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct ImportArgs {
+    #[clap(subcommand)]
+    action: ImportAction,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ImportAction {
+    /// Generate request profiles from a HAR 1.2 file.
+    Har(HarArgs),
+
+    /// Generate request profiles from the operations of an OpenAPI 3 document.
+    Openapi(OpenapiArgs),
+
+    /// Generate request profiles from a Postman v2.1 collection.
+    Postman(PostmanArgs),
+}
+
+#[derive(Parser, Debug, Clone)]
+struct HarArgs {
+    /// The HAR file, as exported from the browser devtools
+    #[clap(value_parser)]
+    file: String,
+
+    /// Only import the entries whose url matches this regex.
+    #[clap(short, long, value_parser)]
+    url: Option<Regex>,
+
+    /// Only import the entries with this method.
+    #[clap(short, long, value_parser)]
+    method: Option<Method>,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct OpenapiArgs {
+    /// The OpenAPI document, in JSON or YAML
+    #[clap(value_parser)]
+    file: String,
+
+    /// Server to use as base url: the description, url or index of a server of the
+    /// document, or a base url. Defaults to the first server.
+    #[clap(short, long, value_parser)]
+    server: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct PostmanArgs {
+    /// The collection file, as exported from Postman
+    #[clap(value_parser)]
+    file: String,
+
+    /// An exported environment, overriding the collection variables
+    #[clap(short, long, value_parser)]
+    environment: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+struct ParseArgs {
+    /// Ask for a curl command instead of a URL.
+    #[clap(long)]
+    curl: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[clap(after_help = "Exit codes:
+  0  the request succeeded and the checks of its `expect:` block passed
+  1  a check of the `expect:` block failed
+  2  the config could not be loaded or the request failed")]
 struct RunArgs {
     /// The profile name
     #[clap(short, long, value_parser)]
     profile: String,
 
-    /// Overrides args. Could be used to override the query, headers and body of the request.
-    /// for query params, use `-e key=value`
-    /// for headers, use `-e %key=value`
-    /// for body, use `-e @key=value`
-    #[clap(short, long, value_parser = parse_key_value, number_of_values = 1)]
+    /// Overrides args, for the query (`key=value`), headers (`%key=value`) or body (`@key=value`).
+    ///
+    /// Values are strings. Use `-e @key:=json` for numbers, booleans, arrays or objects.
+    /// Use `-e @key=@path` or `-e @key:=@path` to read a value from a file.
+    /// Query and body keys are paths, such as `-e @user.address.city=Paris` or `-e @items[0].qty:=3`.
+    /// Use `-e @tags[]=value` to append to an array.
+    /// Use `-e key!` to remove a query param, and `-e @-key` to remove a body field.
+    #[clap(
+        short,
+        long,
+        value_parser = parse_key_value,
+        number_of_values = 1
+    )]
+    extra_params: Vec<KeyVal>,
+
+    /// Configuration to use
+    #[clap(short, long, value_parser)]
+    config: Option<String>,
+
+    /// Report retried requests on stderr.
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Send the cookies of this session, and keep the ones set by the response.
+    #[clap(short, long, value_parser)]
+    session: Option<String>,
+
+    /// Directory holding the sessions
+    #[clap(long, value_parser, default_value = DEFAULT_SESSION_DIR)]
+    session_dir: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[clap(after_help = "Exit codes:
+  0  every step succeeded and passed its checks
+  1  a step failed the checks of its `expect:` block, the next ones were not run
+  2  the config could not be loaded, a request failed or a variable could not be captured")]
+struct FlowArgs {
+    /// The flow name
+    #[clap(short, long, value_parser)]
+    name: String,
+
+    /// Overrides args applied to every step, see `xreq run --help`.
+    #[clap(
+        short,
+        long,
+        value_parser = parse_key_value,
+        number_of_values = 1
+    )]
     extra_params: Vec<KeyVal>,
 
     /// Configuration to use
     #[clap(short, long, value_parser)]
     config: Option<String>,
+
+    /// Report retried requests on stderr.
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Start from the cookies of this session, and keep the ones set by the steps.
+    #[clap(short, long, value_parser)]
+    session: Option<String>,
+
+    /// Directory holding the sessions
+    #[clap(long, value_parser, default_value = DEFAULT_SESSION_DIR)]
+    session_dir: String,
 }
 
+/// Exit code when the requests succeeded and their checks passed.
+const EXIT_OK: u8 = 0;
+/// Exit code when a check of an `expect:` block failed.
+const EXIT_FAILED: u8 = 1;
+/// Exit code when the config or a request failed.
+const EXIT_ERROR: u8 = 2;
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let args = Args::parse();
 
     let result = match args.action {
         Action::Run(args) => run(args).await,
-        Action::Parse => parse().await,
+        Action::Flow(args) => flow(args).await,
+        Action::Parse(args) => parse(args).await.map(|_| EXIT_OK),
+        Action::Curl(args) => curl(args).await.map(|_| EXIT_OK),
+        Action::Import(args) => import(args).await.map(|_| EXIT_OK),
+        Action::Session(args) => session(args).await.map(|_| EXIT_OK),
         // _ => panic!("Not implemented yet"),
     };
 
-    process_error_output(result)
+    match result {
+        Result::Ok(code) => Ok(ExitCode::from(code)),
+        Err(e) => {
+            process_error_output(Err(e))?;
+            Ok(ExitCode::from(EXIT_ERROR))
+        }
+    }
 }
 
-async fn run(args: RunArgs) -> Result<()> {
+async fn run(args: RunArgs) -> Result<u8> {
     let config_file = args.config.unwrap_or_else(|| "./xdiff.yaml".to_string());
     // let profile = config
     let config = RequestConfig::load_yaml(&config_file).await?;
-    let profile = config.get_profile(&args.profile).ok_or_else(|| {
+    config.get_profile(&args.profile).ok_or_else(|| {
         anyhow::anyhow!(
             "Profile {} not found in config file {}",
             args.profile,
             config_file
         )
     })?;
+    let profile = config.resolve_profile(&args.profile, &HashMap::new())?;
+
+    let extra_args = args.extra_params.into();
+
+    let mut client = HttpClient::new(&config.client)?.verbose(args.verbose);
+    let sessions = SessionStore::new(&args.session_dir);
+    if let Some(name) = &args.session {
+        client = client.cookies(sessions.load(name).await?)?;
+    }
+
+    let received = Received::send(&profile, &client, &extra_args).await?;
+    if let (Some(name), Some(jar)) = (&args.session, client.cookie_jar()) {
+        sessions.save(name, &jar).await?;
+    }
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    write!(stdout, "{}", received.output)?;
+    stdout.flush()?;
+
+    match received.check(&profile)? {
+        true => Ok(EXIT_OK),
+        false => Ok(EXIT_FAILED),
+    }
+}
+
+async fn flow(args: FlowArgs) -> Result<u8> {
+    let config_file = args.config.unwrap_or_else(|| "./xdiff.yaml".to_string());
+    let config = RequestConfig::load_yaml(&config_file).await?;
+    let steps = config.get_flow(&args.name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Flow {} not found in config file {}",
+            args.name,
+            config_file
+        )
+    })?;
 
     let extra_args = args.extra_params.into();
 
-    let url = profile.get_url(&extra_args)?;
+    // cookies are always passed between the steps, and only saved with --session
+    let sessions = SessionStore::new(&args.session_dir);
+    let jar = match &args.session {
+        Some(name) => sessions.load(name).await?,
+        None => Default::default(),
+    };
+    let client = HttpClient::new(&config.client)?
+        .verbose(args.verbose)
+        .cookies(jar)?;
 
-    let res = profile.send(&extra_args).await?;
-    let res = res.into_inner();
+    let tty = atty::is(atty::Stream::Stdout);
+    // variables captured by the previous steps
+    let mut vars = HashMap::new();
+    let mut code = EXIT_OK;
+    for (idx, name) in steps.iter().enumerate() {
+        let title = format!("[{}/{}] {}", idx + 1, steps.len(), name);
+        let profile = config
+            .resolve_profile(name, &vars)
+            .with_context(|| format!("failed to resolve step {}", title))?;
 
-    let status = get_status_text(&res)?;
-    let headers = get_header_text(&res, &[])?;
-    let body = get_body_text(res, &[]).await?;
+        let received = Received::send(&profile, &client, &extra_args)
+            .await
+            .with_context(|| format!("step {} failed", title))?;
 
-    let mut output = String::new();
+        {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            match tty {
+                true => writeln!(stdout, "{}", style(&title).cyan().bold())?,
+                false => writeln!(stdout, "# {}", title)?,
+            }
+            writeln!(stdout, "{}", received.output)?;
+            stdout.flush()?;
+        }
 
-    if atty::is(atty::Stream::Stdout) {
-        writeln!(&mut output, "Url: {}\n", url)?;
-
-        write!(&mut output, "{}", status)?;
-        write!(&mut output, "{}", highlight_text(&headers, "yaml", None)?)?;
-        write!(
-            &mut output,
-            "{}",
-            highlight_text(&body, "json", Some("base16-mocha.dark"))?
+        // the next steps likely depend on this one
+        if !received.check(&profile)? {
+            eprintln!(
+                "{} step {} failed its checks, stopping the flow",
+                style("error:").red().bold(),
+                title
+            );
+            code = EXIT_FAILED;
+            break;
+        }
+
+        let captured = profile
+            .capture(received.status, &received.headers, &received.body)
+            .with_context(|| format!("step {} failed", title))?;
+        let mut names: Vec<_> = captured.keys().collect();
+        names.sort();
+
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        for name in names {
+            writeln!(
+                stdout,
+                "{} {} = {}",
+                style("captured").dim(),
+                name,
+                captured[name]
+            )?;
+        }
+        writeln!(stdout)?;
+
+        vars.extend(captured);
+    }
+
+    if let (Some(name), Some(jar)) = (&args.session, client.cookie_jar()) {
+        sessions.save(name, &jar).await?;
+    }
+
+    Ok(code)
+}
+
+/// A response read in full, with its printed output.
+struct Received {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: String,
+    latency: Duration,
+    output: String,
+}
+
+impl Received {
+    async fn send(profile: &RequestProfile, client: &HttpClient, args: &ExtraArgs) -> Result<Self> {
+        let url = profile.get_url(args)?;
+
+        let start = Instant::now();
+        let res = profile.send(client, args).await?.into_inner();
+        let latency = start.elapsed();
+
+        let status_text = get_status_text(&res)?;
+        let header_text = get_header_text(&res, &[])?;
+        let (status, headers) = (res.status(), res.headers().clone());
+        let body = res.text().await?;
+        let pretty = format_body_text(&headers, body.clone(), &[])?;
+
+        let mut output = String::new();
+
+        if atty::is(atty::Stream::Stdout) {
+            writeln!(&mut output, "Url: {}\n", url)?;
+
+            write!(&mut output, "{}", status_text)?;
+            write!(
+                &mut output,
+                "{}",
+                highlight_text(&header_text, "yaml", None)?
+            )?;
+            write!(
+                &mut output,
+                "{}",
+                highlight_text(&pretty, "json", Some("base16-mocha.dark"))?
+            )?;
+        } else {
+            // write!(&mut output, "{}", status)?;
+            // write!(&mut output, "{}", &headers)?;
+            write!(&mut output, "{}", &pretty)?;
+        }
+
+        Ok(Self {
+            status,
+            headers,
+            body,
+            latency,
+            output,
+        })
+    }
+
+    /// Report the checks of the `expect:` block on stderr, returning whether they passed.
+    fn check(&self, profile: &RequestProfile) -> Result<bool> {
+        let Some(expect) = &profile.expect else {
+            return Ok(true);
+        };
+
+        let assertions = expect.check(self.status, &self.headers, &self.body, self.latency)?;
+        let failed = assertions.iter().filter(|a| !a.passed()).count();
+
+        let stderr = std::io::stderr();
+        let mut stderr = stderr.lock();
+        writeln!(stderr)?;
+        for assertion in &assertions {
+            match &assertion.failure {
+                None => writeln!(stderr, "{} {}", style("✓").green(), assertion.name)?,
+                Some(failure) => writeln!(
+                    stderr,
+                    "{} {}: {}",
+                    style("✗").red(),
+                    assertion.name,
+                    style(failure).red()
+                )?,
+            }
+        }
+        writeln!(
+            stderr,
+            "{} passed, {} failed",
+            assertions.len() - failed,
+            failed
         )?;
-    } else {
-        // write!(&mut output, "{}", status)?;
-        // write!(&mut output, "{}", &headers)?;
-        write!(&mut output, "{}", &body)?;
+
+        Ok(failed == 0)
+    }
+}
+
+async fn session(args: SessionArgs) -> Result<()> {
+    let store = SessionStore::new(&args.dir);
+
+    match args.action {
+        SessionAction::List => {
+            for name in store.list().await? {
+                let jar = store.load(&name).await?;
+                let mut domains: Vec<_> = jar.cookies.iter().map(|c| c.domain.as_str()).collect();
+                domains.sort_unstable();
+                domains.dedup();
+                println!("{}\t{} cookies\t{}", name, jar.len(), domains.join(", "));
+            }
+        }
+        SessionAction::Clear(ClearArgs {
+            name: Some(name), ..
+        }) => {
+            if !store.clear(&name).await? {
+                return Err(anyhow::anyhow!("no session named {}", name));
+            }
+        }
+        SessionAction::Clear(ClearArgs { name: None, .. }) => {
+            for name in store.list().await? {
+                store.clear(&name).await?;
+            }
+        }
     }
 
+    Ok(())
+}
+
+async fn curl(args: CurlArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "./xdiff.yaml".to_string());
+    let config = RequestConfig::load_yaml(&config_file).await?;
+    config.get_profile(&args.profile).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Profile {} not found in config file {}",
+            args.profile,
+            config_file
+        )
+    })?;
+
+    let profile = config.resolve_profile(&args.profile, &HashMap::new())?;
+
+    let extra_args = args.extra_params.into();
+    let client = HttpClient::new(&config.client)?;
+    let output = export_request(&profile, &client, &extra_args, args.format).await?;
+
     let stdout = std::io::stdout();
     let mut stdout = stdout.lock();
     write!(stdout, "{}", output)?;
@@ -106,13 +542,62 @@ async fn run(args: RunArgs) -> Result<()> {
     Ok(())
 }
 
-async fn parse() -> Result<()> {
+async fn import(args: ImportArgs) -> Result<()> {
+    let Imported { config, warnings } = match args.action {
+        ImportAction::Har(args) => {
+            let content = tokio::fs::read_to_string(&args.file).await?;
+            let filter = HarFilter {
+                url_pattern: args.url,
+                method: args.method,
+            };
+            import_har(&content, &filter)?
+        }
+        ImportAction::Openapi(args) => {
+            let content = tokio::fs::read_to_string(&args.file).await?;
+            import_openapi(&content, args.server.as_deref())?
+        }
+        ImportAction::Postman(args) => {
+            let content = tokio::fs::read_to_string(&args.file).await?;
+            let environment = match &args.environment {
+                Some(path) => Some(tokio::fs::read_to_string(path).await?),
+                None => None,
+            };
+            import_postman(&content, environment.as_deref())?
+        }
+    };
+
+    for warning in &warnings {
+        eprintln!("{} {}", style("warning:").yellow().bold(), warning);
+    }
+
+    let result = serde_yaml::to_string(&config)?;
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    if atty::is(atty::Stream::Stdout) {
+        write!(stdout, "{}", highlight_text(&result, "yaml", None)?)?;
+    } else {
+        write!(stdout, "{}", result)?;
+    }
+
+    Ok(())
+}
+
+async fn parse(args: ParseArgs) -> Result<()> {
     let color_theme = ColorfulTheme::default();
 
-    let url: String = Input::with_theme(&color_theme)
-        .with_prompt("URL")
-        .interact_text()?;
-    let profile: RequestProfile = url.parse()?;
+    let profile: RequestProfile = if args.curl {
+        let command: String = Input::with_theme(&color_theme)
+            .with_prompt("curl command")
+            .interact_text()?;
+        parse_curl(&command)?
+    } else {
+        let url: String = Input::with_theme(&color_theme)
+            .with_prompt("URL")
+            .interact_text()?;
+        url.parse()?
+    };
 
     let name: String = Input::with_theme(&color_theme)
         .with_prompt("Profile name")
@@ -1,11 +1,13 @@
 use crate::ExtraArgs;
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{anyhow, Context, Ok, Result};
+use serde_json::Value;
+use std::fs;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KeyVal {
     pub key_type: KeyValType,
     pub key: String,
-    pub value: String,
+    pub value: ArgValue,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -15,17 +17,33 @@ pub enum KeyValType {
     Body,
 }
 
-pub fn parse_key_value(s: &str) -> Result<KeyVal> {
-    let mut parts = s.splitn(2, '=');
+/// The value of an override.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgValue {
+    /// `key=value`, always sent as a string.
+    Text(String),
+    /// `key:=value`, a raw JSON value such as `3`, `true` or `["a", "b"]`.
+    Json(Value),
+    /// `key!` or `@-key`, removes the key.
+    Remove,
+}
 
-    let key = parts
-        .next()
-        .ok_or_else(|| anyhow!("Invalid key value pair"))?
-        .trim();
-    let value = parts
-        .next()
-        .ok_or_else(|| anyhow!("Invalid key value pair"))?
-        .trim();
+/// Parse an override: `key=value` for a query param, `%key=value` for a header and
+/// `@key=value` for a body field.
+///
+/// Values are strings, `key:=json` gives a raw JSON value and `key=@path` (or
+/// `key:=@path`) reads the value from a file; `\@` escapes a leading `@`.
+/// Query and body keys are paths such as `user.name` or `items[0]`, `key[]` appends to an
+/// array and `key!` (or `@-key` for the body) without a value removes the key.
+pub fn parse_key_value(s: &str) -> Result<KeyVal> {
+    let (key, value) = match s.split_once('=') {
+        Some((key, value)) => (key.trim(), Some(value.trim())),
+        None => (s.trim(), None),
+    };
+    let (key, json) = match key.strip_suffix(':') {
+        Some(key) => (key.trim_end(), true),
+        None => (key, false),
+    };
 
     let (key_type, key) = match key.chars().next() {
         Some('%') => (KeyValType::Header, &key[1..]),
@@ -34,19 +52,45 @@ pub fn parse_key_value(s: &str) -> Result<KeyVal> {
         _ => return Err(anyhow!("Invalid key value pair")),
     };
 
-    let key = match key_type {
-        KeyValType::Header => key.to_string(),
-        KeyValType::Body => key.to_string(),
-        _ => key.to_string(),
+    let removed = match key_type {
+        KeyValType::Header => None,
+        KeyValType::Query => key.strip_suffix('!'),
+        KeyValType::Body => key.strip_prefix('-').or_else(|| key.strip_suffix('!')),
+    };
+    let (key, value) = match (value, removed) {
+        (None, Some(key)) => (key, ArgValue::Remove),
+        (Some(_), Some(key)) => return Err(anyhow!("Removing {} takes no value", key)),
+        (None, None) => return Err(anyhow!("Invalid key value pair")),
+        (Some(_), None) if json && key_type == KeyValType::Header => {
+            return Err(anyhow!("Header {} takes a text value, use `=`", key))
+        }
+        (Some(value), None) => (key, parse_value(value, json)?),
     };
 
     Ok(KeyVal {
         key_type,
         key: key.to_string(),
-        value: value.to_string(),
+        value,
     })
 }
 
+fn parse_value(value: &str, json: bool) -> Result<ArgValue> {
+    let text = match (value.strip_prefix('@'), value.strip_prefix("\\@")) {
+        (Some(path), _) => {
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?
+        }
+        (None, Some(rest)) => format!("@{}", rest),
+        (None, None) => value.to_string(),
+    };
+
+    match json {
+        true => Ok(ArgValue::Json(
+            serde_json::from_str(&text).with_context(|| format!("Invalid JSON value: {}", text))?,
+        )),
+        false => Ok(ArgValue::Text(text)),
+    }
+}
+
 impl From<Vec<KeyVal>> for ExtraArgs {
     fn from(args: Vec<KeyVal>) -> Self {
         let mut headers = vec![];
@@ -55,7 +99,13 @@ impl From<Vec<KeyVal>> for ExtraArgs {
 
         for arg in args {
             match arg.key_type {
-                KeyValType::Header => headers.push((arg.key, arg.value)),
+                KeyValType::Header => {
+                    // parse_key_value only gives text values to headers, read from a file
+                    // they usually end with a newline
+                    if let ArgValue::Text(value) = arg.value {
+                        headers.push((arg.key, value.trim_end().to_string()))
+                    }
+                }
                 KeyValType::Query => query.push((arg.key, arg.value)),
                 KeyValType::Body => body.push((arg.key, arg.value)),
             }
@@ -72,6 +122,11 @@ impl From<Vec<KeyVal>> for ExtraArgs {
 #[cfg(test)]
 mod test {
     use super::*;
+    use serde_json::json;
+
+    fn text(value: &str) -> ArgValue {
+        ArgValue::Text(value.to_string())
+    }
 
     #[test]
     fn from_vec_key_val_for_extra_args_should_work() {
@@ -79,17 +134,17 @@ mod test {
             KeyVal {
                 key_type: KeyValType::Header,
                 key: "Content-Type".to_string(),
-                value: "application/json".to_string(),
+                value: text("application/json"),
             },
             KeyVal {
                 key_type: KeyValType::Query,
                 key: "id".to_string(),
-                value: "1".to_string(),
+                value: text("1"),
             },
             KeyVal {
                 key_type: KeyValType::Body,
                 key: "name".to_string(),
-                value: "misky".to_string(),
+                value: text("misky"),
             },
         ];
 
@@ -99,19 +154,19 @@ mod test {
             extra_args,
             ExtraArgs {
                 headers: vec![("Content-Type".to_string(), "application/json".to_string())],
-                query: vec![("id".to_string(), "1".to_string())],
-                body: vec![("name".to_string(), "misky".to_string())],
+                query: vec![("id".to_string(), text("1"))],
+                body: vec![("name".to_string(), text("misky"))],
             }
         )
     }
 
     #[test]
     fn parse_key_val_should_work() {
-        let args = vec!["%Content-Type=application/json", "id=1", "@name=misky"];
+        let args = vec!["%Content-Type=application/json", "id=007", "@name=misky"];
 
         let key_vals = args
             .into_iter()
-            .map(|s| parse_key_value(s))
+            .map(parse_key_value)
             .collect::<Result<Vec<_>>>()
             .unwrap();
 
@@ -121,19 +176,61 @@ mod test {
                 KeyVal {
                     key_type: KeyValType::Header,
                     key: "Content-Type".to_string(),
-                    value: "application/json".to_string(),
+                    value: text("application/json"),
                 },
                 KeyVal {
                     key_type: KeyValType::Query,
                     key: "id".to_string(),
-                    value: "1".to_string(),
+                    value: text("007"),
                 },
                 KeyVal {
                     key_type: KeyValType::Body,
                     key: "name".to_string(),
-                    value: "misky".to_string(),
+                    value: text("misky"),
                 },
             ]
         )
     }
+
+    #[test]
+    fn parse_typed_key_val_should_work() {
+        let value = |arg: &str| parse_key_value(arg).unwrap().value;
+
+        assert_eq!(value("@tags:=[\"a\", 1]"), ArgValue::Json(json!(["a", 1])));
+        assert_eq!(value("page := 2"), ArgValue::Json(json!(2)));
+        assert_eq!(value("@email=\\@misky"), text("@misky"));
+        assert_eq!(value("@-user.name"), ArgValue::Remove);
+        assert_eq!(parse_key_value("@-user.name").unwrap().key, "user.name");
+
+        let file =
+            std::env::temp_dir().join(format!("xdiff-cli-value-{}.json", std::process::id()));
+        std::fs::write(&file, "{\"a\": 1}\n").unwrap();
+        let path = file.display();
+        assert_eq!(value(&format!("@note=@{}", path)), text("{\"a\": 1}\n"));
+        assert_eq!(
+            value(&format!("@note:=@{}", path)),
+            ArgValue::Json(json!({"a": 1}))
+        );
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(parse_key_value("@tags:=[a]").is_err());
+        assert!(parse_key_value("%x-token:=1").is_err());
+        assert!(parse_key_value("@note=@/no/such/file").is_err());
+        assert!(parse_key_value("@-name=misky").is_err());
+        assert!(parse_key_value("@name").is_err());
+        assert!(parse_key_value("%x-token").is_err());
+    }
+
+    #[test]
+    fn parse_key_val_should_remove_keys() {
+        assert_eq!(parse_key_value("name!").unwrap().value, ArgValue::Remove);
+        assert_eq!(parse_key_value("filter.done!").unwrap().key, "filter.done");
+        assert_eq!(parse_key_value("@draft!").unwrap().key, "draft");
+        assert_eq!(parse_key_value("@-draft").unwrap().key, "draft");
+
+        assert!(parse_key_value("-name").is_err());
+        assert!(parse_key_value("--verbose").is_err());
+        assert!(parse_key_value("%x-token!").is_err());
+        assert!(parse_key_value("name!=1").is_err());
+    }
 }
@@ -0,0 +1,670 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{
+    redirect, Certificate, Client, ClientBuilder, NoProxy, Proxy, Request, Response,
+    ResponseBuilderExt,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::RandomState, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{error::Error, fmt};
+
+use crate::auth::TokenCache;
+use crate::config::is_default;
+use crate::session::{CookieJar, SharedJar};
+
+/// Transport settings, from the top level `client:` block of a config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientOptions {
+    /// Timeout of a whole request, from connecting to reading the end of the body.
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub timeout: Option<Duration>,
+
+    /// Timeout of the connection phase only.
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub connect_timeout: Option<Duration>,
+
+    /// Maximum time to wait for the response headers or for the next chunk of the body.
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub read_timeout: Option<Duration>,
+
+    /// Retry policy of failed requests. Requests are not retried by default.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub retry: RetryPolicy,
+
+    /// How long an idle connection is kept in the pool.
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub pool_idle_timeout: Option<Duration>,
+
+    /// Maximum number of idle connections kept per host.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Proxy url used for every request, e.g. `http://localhost:8888`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub proxy: Option<String>,
+
+    /// Comma separated hosts that bypass the proxy, as in `NO_PROXY`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub no_proxy: Option<String>,
+
+    /// PEM file with an extra root certificate to trust.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Accept invalid TLS certificates. Only meant for local test servers.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub insecure: bool,
+
+    /// Maximum number of redirects to follow, `0` to return redirect responses as is.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub redirects: Option<usize>,
+}
+
+/// Timeouts and retry policy of a single profile, overriding the `client:` block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportOptions {
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub timeout: Option<Duration>,
+
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub connect_timeout: Option<Duration>,
+
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub read_timeout: Option<Duration>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub retry: Option<RetryPolicy>,
+}
+
+/// When and how often a failed request is sent again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled at each following one.
+    #[serde(with = "duration::required")]
+    pub backoff: Duration,
+
+    /// Upper bound of the delay between two attempts.
+    #[serde(with = "duration::required")]
+    pub max_backoff: Duration,
+
+    /// Randomize the delays, so that concurrent profiles don't retry in lockstep.
+    pub jitter: bool,
+
+    /// Response status codes worth a retry.
+    pub on_status: Vec<u16>,
+
+    /// Retry when the connection fails or times out.
+    pub on_connection_error: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            on_status: vec![429, 502, 503, 504],
+            on_connection_error: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the given retry, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.backoff.saturating_mul(factor).min(self.max_backoff);
+
+        match self.jitter {
+            // equal jitter: keep half of the delay, randomize the other half
+            true => delay / 2 + random_fraction(delay / 2),
+            false => delay,
+        }
+    }
+}
+
+/// HTTP client shared by all the requests of a run, so that connections are pooled
+/// between `req1`/`req2` and across profiles. Cloning it is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClient {
+    client: Client,
+    options: ClientOptions,
+    /// Clients of the profiles overriding the connect timeout, by timeout.
+    overrides: Arc<Mutex<HashMap<Duration, Client>>>,
+    tokens: TokenCache,
+    /// Cookies of the session, if any, updated by the responses.
+    cookies: Option<SharedJar>,
+    verbose: bool,
+}
+
+/// The response headers or a body chunk took longer than the read timeout.
+#[derive(Debug)]
+struct ReadTimeout(Duration);
+
+impl fmt::Display for ReadTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "read timed out after {:?}", self.0)
+    }
+}
+
+impl Error for ReadTimeout {}
+
+impl HttpClient {
+    pub fn new(options: &ClientOptions) -> Result<Self> {
+        Ok(Self {
+            client: builder(options, None)?.build()?,
+            options: options.clone(),
+            overrides: Default::default(),
+            tokens: TokenCache::default(),
+            cookies: None,
+            verbose: false,
+        })
+    }
+
+    /// Report retries on stderr.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Send the cookies of the jar, and keep the ones set by the responses, including
+    /// the redirects followed on the way.
+    pub fn cookies(mut self, jar: CookieJar) -> Result<Self> {
+        let jar = SharedJar(Arc::new(Mutex::new(jar)));
+        self.client = builder(&self.options, Some(&jar))?.build()?;
+        self.overrides = Default::default();
+        self.cookies = Some(jar);
+        Ok(self)
+    }
+
+    /// The cookies of the session as they are now, to be saved.
+    pub fn cookie_jar(&self) -> Option<CookieJar> {
+        self.cookies
+            .as_ref()
+            .map(|jar| jar.0.lock().unwrap().clone())
+    }
+
+    /// Add the cookies of the session to a request, after the ones set explicitly.
+    pub(crate) fn add_cookies(&self, req: &mut Request) -> Result<()> {
+        match &self.cookies {
+            Some(jar) => {
+                let url = req.url().clone();
+                jar.0.lock().unwrap().add_to(&url, req.headers_mut())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The underlying client, to build requests with.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// OAuth2 tokens fetched so far.
+    pub fn tokens(&self) -> &TokenCache {
+        &self.tokens
+    }
+
+    /// Send a request with the timeouts and retry policy of a profile, falling back
+    /// to the ones of the `client:` block. The body of the response is buffered.
+    pub async fn send(&self, req: Request, transport: &TransportOptions) -> Result<Response> {
+        let retry = transport.retry.as_ref().unwrap_or(&self.options.retry);
+        let timeout = transport.timeout.or(self.options.timeout);
+        let read_timeout = transport.read_timeout.or(self.options.read_timeout);
+        let client = match transport.connect_timeout {
+            Some(connect_timeout) => self.client_with_connect_timeout(connect_timeout)?,
+            None => self.client.clone(),
+        };
+
+        let max_attempts = retry.max_attempts.max(1);
+        let mut req = req;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+
+            // a streamed body can't be sent twice, so such a request is never retried
+            let next = match attempt < max_attempts {
+                true => req.try_clone(),
+                false => None,
+            };
+            *req.timeout_mut() = timeout;
+            let (method, url) = (req.method().clone(), req.url().clone());
+            let result = execute(&client, req, read_timeout).await;
+
+            let Some(next) = next else {
+                return result;
+            };
+            let reason = match &result {
+                Ok(res) if retry.on_status.contains(&res.status().as_u16()) => {
+                    format!("status {}", res.status())
+                }
+                Err(e) if retry.on_connection_error && is_connection_error(e) => e.to_string(),
+                _ => return result,
+            };
+
+            let mut delay = retry.delay(attempt);
+            if let Ok(res) = &result {
+                if let Some(after) = retry_after(res) {
+                    delay = delay.max(after.min(retry.max_backoff));
+                }
+            }
+
+            if self.verbose {
+                eprintln!(
+                    "retrying {} {} after attempt {}/{} failed ({}), waiting {:?}",
+                    method, url, attempt, max_attempts, reason, delay
+                );
+            }
+
+            tokio::time::sleep(delay).await;
+            req = next;
+        }
+    }
+
+    fn client_with_connect_timeout(&self, connect_timeout: Duration) -> Result<Client> {
+        let mut overrides = self.overrides.lock().expect("client cache poisoned");
+        if let Some(client) = overrides.get(&connect_timeout) {
+            return Ok(client.clone());
+        }
+
+        let options = ClientOptions {
+            connect_timeout: Some(connect_timeout),
+            ..self.options.clone()
+        };
+        let client = builder(&options, self.cookies.as_ref())?.build()?;
+        overrides.insert(connect_timeout, client.clone());
+
+        Ok(client)
+    }
+}
+
+fn builder(options: &ClientOptions, cookies: Option<&SharedJar>) -> Result<ClientBuilder> {
+    let mut builder = Client::builder();
+
+    if let Some(jar) = cookies {
+        builder = builder.cookie_provider(Arc::new(jar.clone()));
+    }
+    if let Some(timeout) = options.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = options.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(timeout);
+    }
+    if let Some(max) = options.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(proxy) = &options.proxy {
+        let proxy = Proxy::all(proxy).with_context(|| format!("invalid proxy: {}", proxy))?;
+        let no_proxy = options.no_proxy.as_deref().and_then(NoProxy::from_string);
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+    if let Some(path) = &options.ca_cert {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("failed to read ca_cert: {}", path.display()))?;
+        builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+    }
+    if options.insecure {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    match options.redirects {
+        Some(0) => builder = builder.redirect(redirect::Policy::none()),
+        Some(max) => builder = builder.redirect(redirect::Policy::limited(max)),
+        None => {}
+    }
+
+    Ok(builder)
+}
+
+/// Send a request once and buffer the body, enforcing the read timeout on each read.
+async fn execute(
+    client: &Client,
+    req: Request,
+    read_timeout: Option<Duration>,
+) -> Result<Response> {
+    let mut res = with_read_timeout(read_timeout, client.execute(req)).await?;
+
+    // keep the final url, after the redirects
+    let mut builder = http::Response::builder()
+        .status(res.status())
+        .version(res.version())
+        .url(res.url().clone());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = res.headers().clone();
+    }
+
+    let mut body = vec![];
+    while let Some(chunk) = with_read_timeout(read_timeout, res.chunk()).await? {
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(Response::from(builder.body(body)?))
+}
+
+async fn with_read_timeout<T>(
+    read_timeout: Option<Duration>,
+    fut: impl std::future::Future<Output = reqwest::Result<T>>,
+) -> Result<T> {
+    match read_timeout {
+        Some(limit) => match tokio::time::timeout(limit, fut).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(ReadTimeout(limit).into()),
+        },
+        None => Ok(fut.await?),
+    }
+}
+
+/// Whether the request failed to connect, or timed out, as opposed to an invalid request.
+fn is_connection_error(e: &anyhow::Error) -> bool {
+    if e.is::<ReadTimeout>() {
+        return true;
+    }
+
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.is_connect() || e.is_timeout(),
+        None => false,
+    }
+}
+
+/// The delay asked for by a `Retry-After` header, in seconds.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(reqwest::header::RETRY_AFTER)?;
+    let secs: u64 = value.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// A pseudo random duration between zero and `max`.
+fn random_fraction(max: Duration) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(now.as_nanos());
+    let fraction = (hasher.finish() % 1_000) as u32;
+
+    max * fraction / 1_000
+}
+
+/// Durations written as `500ms`, `10s`, `2m` or `1h`. A bare number is in seconds.
+pub(crate) mod duration {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(d) if d.subsec_millis() != 0 => s.serialize_str(&format!("{}ms", d.as_millis())),
+            Some(d) => s.serialize_str(&format!("{}s", d.as_secs())),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Seconds(f64),
+            Text(String),
+        }
+
+        let duration = match Option::<Raw>::deserialize(d)? {
+            Some(Raw::Seconds(secs)) if secs >= 0.0 => Duration::from_secs_f64(secs),
+            Some(Raw::Seconds(secs)) => {
+                return Err(serde::de::Error::custom(format!(
+                    "negative duration: {}",
+                    secs
+                )))
+            }
+            Some(Raw::Text(text)) => parse(&text).map_err(serde::de::Error::custom)?,
+            None => return Ok(None),
+        };
+
+        Ok(Some(duration))
+    }
+
+    /// For durations that are not optional.
+    pub mod required {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(value: &Duration, s: S) -> Result<S::Ok, S::Error> {
+            super::serialize(&Some(*value), s)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+            super::deserialize(d)?.ok_or_else(|| serde::de::Error::custom("a duration is required"))
+        }
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Duration> {
+        let text = text.trim();
+        let split = text
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| anyhow!("invalid duration: {}", text))?;
+
+        let secs = match unit.trim() {
+            "ms" => number / 1000.0,
+            "" | "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => return Err(anyhow!("invalid duration unit: {}", text)),
+        };
+
+        Ok(Duration::from_secs_f64(secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiffConfig, ExtraArgs, LoadConfig, RequestProfile};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+
+    fn fast_retry(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Duration::from_millis(1),
+            jitter: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn client_options_should_parse_durations() {
+        let options: ClientOptions = serde_yaml::from_str(
+            "timeout: 1.5\nconnect_timeout: 500ms\npool_idle_timeout: 2m\nredirects: 0",
+        )
+        .unwrap();
+
+        assert_eq!(options.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(options.connect_timeout, Some(Duration::from_millis(500)));
+        assert_eq!(options.pool_idle_timeout, Some(Duration::from_secs(120)));
+        assert_eq!(options.redirects, Some(0));
+
+        let yaml = serde_yaml::to_string(&options).unwrap();
+        assert_eq!(
+            yaml,
+            "timeout: 1500ms\nconnect_timeout: 500ms\npool_idle_timeout: 120s\nredirects: 0\n"
+        );
+
+        assert!(serde_yaml::from_str::<ClientOptions>("timeout: 3 days").is_err());
+    }
+
+    #[test]
+    fn client_block_should_not_be_a_profile() {
+        let config = DiffConfig::from_yaml(
+            "client:\n  timeout: 10s\n  proxy: http://localhost:8888\ntodo:\n  req1:\n    url: https://a.com/1\n  req2:\n    url: https://a.com/2\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.client.timeout, Some(Duration::from_secs(10)));
+        assert_eq!(config.profiles.len(), 1);
+    }
+
+    #[test]
+    fn http_client_should_validate_options() {
+        let options = ClientOptions {
+            proxy: Some("http://localhost:8888".into()),
+            no_proxy: Some("localhost,.internal".into()),
+            insecure: true,
+            redirects: Some(3),
+            ..Default::default()
+        };
+        assert!(HttpClient::new(&options).is_ok());
+
+        let options = ClientOptions {
+            ca_cert: Some("/nonexistent/ca.pem".into()),
+            ..Default::default()
+        };
+        assert!(HttpClient::new(&options).is_err());
+    }
+
+    #[test]
+    fn retry_delay_should_back_off_exponentially() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+            jitter: false,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(300));
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        let delay = policy.delay(2);
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn profile_should_override_transport() {
+        let profile: RequestProfile = serde_yaml::from_str(
+            "url: https://a.com/\ntimeout: 5s\nretry:\n  max_attempts: 3\n  on_status: [500]\n",
+        )
+        .unwrap();
+
+        assert_eq!(profile.transport.timeout, Some(Duration::from_secs(5)));
+        let retry = profile.transport.retry.as_ref().unwrap();
+        assert_eq!(retry.max_attempts, 3);
+        assert_eq!(retry.on_status, vec![500]);
+        assert_eq!(retry.backoff, Duration::from_millis(200));
+
+        let yaml = serde_yaml::to_string(&profile).unwrap();
+        assert!(yaml.contains("timeout: 5s"));
+        assert!(!yaml.contains("read_timeout"));
+    }
+
+    #[tokio::test]
+    async fn send_should_retry_on_status() {
+        let mut server = mockito::Server::new_async().await;
+        let failing = server
+            .mock("GET", "/todo")
+            .with_status(503)
+            .expect(2)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("GET", "/todo")
+            .with_status(200)
+            .with_body("ok")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let options = ClientOptions {
+            retry: fast_retry(3),
+            ..Default::default()
+        };
+        let client = HttpClient::new(&options).unwrap();
+        let profile: RequestProfile = format!("{}/todo", server.url()).parse().unwrap();
+
+        let res = profile.send(&client, &ExtraArgs::default()).await.unwrap();
+        let res = res.into_inner();
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.text().await.unwrap(), "ok");
+        failing.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn send_should_retry_on_read_timeout() {
+        // a server accepting connections but never answering
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            let mut sockets = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                sockets.push(socket);
+            }
+        });
+
+        let client = HttpClient::new(&ClientOptions::default()).unwrap();
+        let mut profile: RequestProfile = format!("http://{}/slow", addr).parse().unwrap();
+        profile.transport = TransportOptions {
+            read_timeout: Some(Duration::from_millis(50)),
+            retry: Some(fast_retry(2)),
+            ..Default::default()
+        };
+
+        let err = profile
+            .send(&client, &ExtraArgs::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "read timed out after 50ms");
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn session_should_keep_cookies_of_redirects() {
+        let mut server = mockito::Server::new_async().await;
+        let login = server
+            .mock("POST", "/login")
+            .with_status(302)
+            .with_header("location", "/home")
+            .with_header("set-cookie", "sid=abc; Path=/; HttpOnly")
+            .create_async()
+            .await;
+        let home = server
+            .mock("GET", "/home")
+            .match_header("cookie", "sid=abc")
+            .with_body("welcome")
+            .create_async()
+            .await;
+
+        let client = HttpClient::new(&ClientOptions::default())
+            .unwrap()
+            .cookies(Default::default())
+            .unwrap();
+        let mut profile: RequestProfile = format!("{}/login", server.url()).parse().unwrap();
+        profile.method = reqwest::Method::POST;
+
+        let res = profile.send(&client, &ExtraArgs::default()).await.unwrap();
+        assert_eq!(res.into_inner().text().await.unwrap(), "welcome");
+        login.assert_async().await;
+        home.assert_async().await;
+
+        let jar = client.cookie_jar().unwrap();
+        assert_eq!(jar.len(), 1);
+        assert_eq!(jar.cookies[0].value, "abc");
+    }
+}
@@ -0,0 +1,586 @@
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use super::interpolate::{BLOCK_KEYS, VARS_KEY};
+use super::{apply_override, get_content_type};
+use crate::cli::ArgValue;
+
+/// Body of a request profile.
+///
+/// A mapping is sent as JSON, or as a form if the `content-type` header says so. The
+/// other kinds are selected with a YAML tag:
+///
+/// ```yaml
+/// body: !raw plain text
+/// body: !xml <todo><title>buy milk</title></todo>
+/// body: !file ./payload.bin
+/// body: !base64 aGVsbG8=
+/// body: !ndjson [{id: 1}, {id: 2}]
+/// body: !multipart
+///   - name: title
+///     value: buy milk
+///   - name: attachment
+///     file: ./receipt.png
+///     content_type: image/png
+/// ```
+///
+/// Files are read when the request is sent, relative to the working directory.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    Json(Value),
+    Raw(String),
+    Xml(String),
+    File(PathBuf),
+    Base64(String),
+    Ndjson(Vec<Value>),
+    Multipart(Vec<Part>),
+}
+
+/// A part of a multipart body, holding either a text `value` or a `file`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Part {
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub value: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub file: Option<PathBuf>,
+
+    /// Defaults to the name of the file.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub filename: Option<String>,
+
+    /// Defaults to a type guessed from the file extension.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub content_type: Option<String>,
+}
+
+impl Body {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Body::Json(value) if !value.is_object() => Err(anyhow!(
+                "body must be an object but got\n{}",
+                serde_yaml::to_string(value)?
+            )),
+            Body::Base64(data) => {
+                STANDARD
+                    .decode(data.trim())
+                    .context("body is not valid base64")?;
+                Ok(())
+            }
+            Body::Multipart(parts) => {
+                for part in parts {
+                    if part.value.is_some() == part.file.is_some() {
+                        return Err(anyhow!(
+                            "multipart part {} needs either a value or a file",
+                            part.name
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply the `-e @key=value` overrides, to the fields of a JSON or form body, or
+    /// to the text parts of a multipart body.
+    pub fn with_overrides(mut self, overrides: &[(String, ArgValue)]) -> Result<Self> {
+        if overrides.is_empty() {
+            return Ok(self);
+        }
+
+        match &mut self {
+            Body::Json(body) => {
+                for (key, value) in overrides {
+                    apply_override(body, key, value)?;
+                }
+            }
+            Body::Multipart(parts) => {
+                for (key, value) in overrides {
+                    parts.retain(|p| &p.name != key);
+                    let value = match value {
+                        ArgValue::Remove => continue,
+                        ArgValue::Text(text) => text.clone(),
+                        ArgValue::Json(value) => value.to_string(),
+                    };
+                    parts.push(Part {
+                        name: key.clone(),
+                        value: Some(value),
+                        ..Default::default()
+                    });
+                }
+            }
+            _ => {
+                return Err(anyhow!(
+                    "body overrides need a mapping or a multipart body, not {}",
+                    self.kind()
+                ))
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// The bytes to send. The `content-type` header is set if missing, and completed
+    /// with the boundary for multipart bodies.
+    pub fn render(&self, headers: &mut HeaderMap) -> Result<Vec<u8>> {
+        let default_type = match self {
+            Body::Json(_) => "application/json",
+            Body::Raw(_) => "text/plain",
+            Body::Xml(_) => "application/xml",
+            Body::File(path) => guess_content_type(path),
+            Body::Base64(_) => "application/octet-stream",
+            Body::Ndjson(_) => "application/x-ndjson",
+            Body::Multipart(_) => "multipart/form-data",
+        };
+        if !headers.contains_key(CONTENT_TYPE) {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(default_type));
+        }
+
+        match self {
+            Body::Json(value) => render_mapping(value, headers),
+            Body::Raw(text) | Body::Xml(text) => Ok(text.clone().into_bytes()),
+            Body::File(path) => fs::read(path)
+                .with_context(|| format!("failed to read body file {}", path.display())),
+            Body::Base64(data) => Ok(STANDARD.decode(data.trim())?),
+            Body::Ndjson(lines) => {
+                let mut body = vec![];
+                for line in lines {
+                    serde_json::to_writer(&mut body, line)?;
+                    body.push(b'\n');
+                }
+                Ok(body)
+            }
+            Body::Multipart(parts) => render_multipart(parts, headers),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Body::Json(_) => "a mapping",
+            Body::Raw(_) => "!raw",
+            Body::Xml(_) => "!xml",
+            Body::File(_) => "!file",
+            Body::Base64(_) => "!base64",
+            Body::Ndjson(_) => "!ndjson",
+            Body::Multipart(_) => "!multipart",
+        }
+    }
+}
+
+fn render_mapping(value: &Value, headers: &mut HeaderMap) -> Result<Vec<u8>> {
+    match get_content_type(headers).as_deref() {
+        Some("application/x-www-form-urlencoded") => {
+            Ok(serde_urlencoded::to_string(value)?.into_bytes())
+        }
+        Some("multipart/form-data") => {
+            let parts: Vec<_> = value
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(name, value)| Part {
+                    name: name.clone(),
+                    value: Some(match value {
+                        Value::String(s) => s.clone(),
+                        _ => value.to_string(),
+                    }),
+                    ..Default::default()
+                })
+                .collect();
+            render_multipart(&parts, headers)
+        }
+        Some(ct) if ct == "application/json" || ct.ends_with("+json") => {
+            Ok(serde_json::to_vec(value)?)
+        }
+        Some(ct) => Err(anyhow!(
+            "a mapping body can't be sent as {}, use a tagged body such as !raw",
+            ct
+        )),
+        None => Ok(serde_json::to_vec(value)?),
+    }
+}
+
+fn render_multipart(parts: &[Part], headers: &mut HeaderMap) -> Result<Vec<u8>> {
+    let mut contents = vec![];
+    for part in parts {
+        let content = match (&part.value, &part.file) {
+            (Some(value), _) => value.clone().into_bytes(),
+            (None, Some(path)) => fs::read(path).with_context(|| {
+                format!(
+                    "failed to read file {} of part {}",
+                    path.display(),
+                    part.name
+                )
+            })?,
+            (None, None) => vec![],
+        };
+        contents.push(content);
+    }
+
+    let boundary = boundary(&contents);
+    let mut body = vec![];
+    for (part, content) in parts.iter().zip(&contents) {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        let mut disposition = format!("form-data; name=\"{}\"", escape_quotes(&part.name));
+        let filename = part.filename.clone().or_else(|| {
+            part.file
+                .as_ref()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().into_owned())
+        });
+        if let Some(filename) = filename {
+            disposition.push_str(&format!("; filename=\"{}\"", escape_quotes(&filename)));
+        }
+        body.extend_from_slice(format!("content-disposition: {}\r\n", disposition).as_bytes());
+
+        let content_type = part
+            .content_type
+            .as_deref()
+            .or_else(|| part.file.as_deref().map(guess_content_type));
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(format!("content-type: {}\r\n", content_type).as_bytes());
+        }
+
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    let content_type = get_content_type(headers).unwrap_or_else(|| "multipart/form-data".into());
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&format!("{}; boundary={}", content_type, boundary))?,
+    );
+
+    Ok(body)
+}
+
+/// A boundary derived from the contents, so that the same profile always renders the
+/// same request, and which does not appear in any of them.
+fn boundary(contents: &[Vec<u8>]) -> String {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+
+    loop {
+        let boundary = format!("xdiff-{:016x}", hasher.finish());
+        let found = contents
+            .iter()
+            .any(|c| c.windows(boundary.len()).any(|w| w == boundary.as_bytes()));
+        if !found {
+            return boundary;
+        }
+        boundary.hash(&mut hasher);
+    }
+}
+
+fn escape_quotes(text: &str) -> String {
+    text.replace('"', "%22")
+}
+
+fn guess_content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "ndjson" | "jsonl" => "application/x-ndjson",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Replace the YAML tags of the bodies of every profile of a config, see [`untag_profile`].
+pub(crate) fn untag_config(config: &mut serde_yaml::Value) {
+    let serde_yaml::Value::Mapping(root) = config else {
+        return;
+    };
+
+    for (key, profile) in root.iter_mut() {
+        let name = key.as_str().unwrap_or_default();
+        if name != VARS_KEY && !BLOCK_KEYS.contains(&name) {
+            untag_profile(profile);
+        }
+    }
+}
+
+/// Replace the YAML tag of the body of a request profile, or of both requests of a diff
+/// profile, with a single-key mapping whose key is the tag in a sequence, `{[!tag]: value}`.
+///
+/// Profiles contain flattened fields, and serde buffers such structs in a way that rejects
+/// tagged values, so bodies are untagged before being deserialized; `Body` accepts both.
+/// JSON keys are strings, so a plain body such as `{"!important": true}` is never taken
+/// for a tag.
+pub(crate) fn untag_profile(profile: &mut serde_yaml::Value) {
+    for key in ["req1", "req2"] {
+        if let Some(req) = profile.get_mut(key) {
+            untag_profile(req);
+        }
+    }
+
+    if let Some(body) = profile.get_mut("body") {
+        untag(body);
+    }
+}
+
+fn untag(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Tagged(tagged) => {
+            let mut inner = std::mem::replace(&mut tagged.value, serde_yaml::Value::Null);
+            untag(&mut inner);
+            let tag = serde_yaml::Value::Sequence(vec![tagged.tag.to_string().into()]);
+            let mut map = serde_yaml::Mapping::new();
+            map.insert(tag, inner);
+            *value = serde_yaml::Value::Mapping(map);
+        }
+        serde_yaml::Value::Mapping(map) => map.values_mut().for_each(untag),
+        serde_yaml::Value::Sequence(seq) => seq.iter_mut().for_each(untag),
+        _ => {}
+    }
+}
+
+/// The tag and value of a tagged body, or of a body untagged by [`untag`]. Other values
+/// are given back.
+fn split_tag(value: serde_yaml::Value) -> Result<(String, serde_yaml::Value), serde_yaml::Value> {
+    match value {
+        serde_yaml::Value::Tagged(tagged) => Ok((tagged.tag.to_string(), tagged.value)),
+        serde_yaml::Value::Mapping(map) if map.len() == 1 => {
+            let (key, inner) = map.iter().next().expect("one entry");
+            match key.as_sequence().map(Vec::as_slice) {
+                Some([serde_yaml::Value::String(tag)]) => Ok((tag.clone(), inner.clone())),
+                _ => Err(serde_yaml::Value::Mapping(map)),
+            }
+        }
+        value => Err(value),
+    }
+}
+
+impl Serialize for Body {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Body::Json(value) => value.serialize(s),
+            Body::Raw(text) => s.serialize_newtype_variant("Body", 1, "raw", text),
+            Body::Xml(text) => s.serialize_newtype_variant("Body", 2, "xml", text),
+            Body::File(path) => s.serialize_newtype_variant("Body", 3, "file", path),
+            Body::Base64(data) => s.serialize_newtype_variant("Body", 4, "base64", data),
+            Body::Ndjson(lines) => s.serialize_newtype_variant("Body", 5, "ndjson", lines),
+            Body::Multipart(parts) => s.serialize_newtype_variant("Body", 6, "multipart", parts),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let (tag, inner) = match split_tag(serde_yaml::Value::deserialize(d)?) {
+            Ok(tagged) => tagged,
+            Err(value) => {
+                return serde_yaml::from_value(value)
+                    .map(Body::Json)
+                    .map_err(Error::custom)
+            }
+        };
+        let body = match tag.trim_start_matches('!') {
+            "raw" => serde_yaml::from_value(inner).map(Body::Raw),
+            "xml" => serde_yaml::from_value(inner).map(Body::Xml),
+            "file" => serde_yaml::from_value(inner).map(Body::File),
+            "base64" => serde_yaml::from_value(inner).map(Body::Base64),
+            "ndjson" => serde_yaml::from_value(inner).map(Body::Ndjson),
+            "multipart" => serde_yaml::from_value(inner).map(Body::Multipart),
+            "json" => serde_yaml::from_value(inner).map(Body::Json),
+            _ => {
+                return Err(Error::custom(format!(
+                    "unknown body tag {}, expected one of !raw, !xml, !file, !base64, !ndjson, !multipart",
+                    tag
+                )))
+            }
+        };
+
+        body.map_err(|e| Error::custom(format!("invalid {} body: {}", tag, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LoadConfig, RequestConfig};
+    use serde_json::json;
+
+    fn render(yaml: &str, content_type: Option<&'static str>) -> (String, String) {
+        let body: Body = serde_yaml::from_str(yaml).unwrap();
+        body.validate().unwrap();
+
+        let mut headers = HeaderMap::new();
+        if let Some(content_type) = content_type {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        }
+        let bytes = body.render(&mut headers).unwrap();
+
+        (
+            headers[CONTENT_TYPE].to_str().unwrap().to_string(),
+            String::from_utf8(bytes).unwrap(),
+        )
+    }
+
+    #[test]
+    fn tagged_bodies_should_round_trip() {
+        for yaml in [
+            "a: 1\n",
+            "!raw hello\n",
+            "!xml <a/>\n",
+            "!file ./a.bin\n",
+            "!base64 aGVsbG8=\n",
+            "!ndjson\n- a: 1\n- a: 2\n",
+            "!multipart\n- name: a\n  value: '1'\n",
+        ] {
+            let body: Body = serde_yaml::from_str(yaml).unwrap();
+            assert_eq!(serde_yaml::to_string(&body).unwrap(), yaml);
+        }
+
+        assert_eq!(
+            serde_yaml::from_str::<Body>("!raw hello").unwrap(),
+            Body::Raw("hello".into())
+        );
+        assert!(serde_yaml::from_str::<Body>("!yaml a").is_err());
+    }
+
+    #[test]
+    fn bang_keys_should_not_be_tags() {
+        let config = RequestConfig::from_yaml(
+            r#"
+vars:
+  "!x": a
+todo:
+  url: https://a.com/todo
+  params:
+    "!page": 1
+  body:
+    "!important": true
+upload:
+  url: https://a.com/upload
+  body: !raw hello
+"#,
+        )
+        .unwrap();
+
+        let todo = config.get_profile("todo").unwrap();
+        assert_eq!(todo.body, Some(Body::Json(json!({"!important": true}))));
+        assert_eq!(todo.params, Some(json!({"!page": 1})));
+        let upload = config
+            .resolve_profile("upload", &Default::default())
+            .unwrap();
+        assert_eq!(upload.body, Some(Body::Raw("hello".into())));
+    }
+
+    #[test]
+    fn bodies_should_render_with_content_type() {
+        assert_eq!(
+            render("!raw hello", None),
+            ("text/plain".into(), "hello".into())
+        );
+        assert_eq!(
+            render("!xml <a/>", Some("text/xml")),
+            ("text/xml".into(), "<a/>".into())
+        );
+        assert_eq!(
+            render("!base64 aGVsbG8=", None),
+            ("application/octet-stream".into(), "hello".into())
+        );
+        assert_eq!(
+            render("!ndjson [{a: 1}, {a: 2}]", None),
+            (
+                "application/x-ndjson".into(),
+                "{\"a\":1}\n{\"a\":2}\n".into()
+            )
+        );
+        assert_eq!(
+            render("a: 1\nb: x", Some("application/x-www-form-urlencoded")),
+            ("application/x-www-form-urlencoded".into(), "a=1&b=x".into())
+        );
+    }
+
+    #[test]
+    fn multipart_should_render_text_and_file_parts() {
+        let dir = std::env::temp_dir().join(format!("xdiff-body-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("note.txt");
+        fs::write(&file, "from file").unwrap();
+
+        let body = Body::Multipart(vec![
+            Part {
+                name: "title".into(),
+                value: Some("buy \"milk\"".into()),
+                ..Default::default()
+            },
+            Part {
+                name: "note".into(),
+                file: Some(file),
+                ..Default::default()
+            },
+        ]);
+        let mut headers = HeaderMap::new();
+        let bytes = body.render(&mut headers).unwrap();
+
+        let content_type = headers[CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            format!(
+                "--{b}\r\ncontent-disposition: form-data; name=\"title\"\r\n\r\nbuy \"milk\"\r\n\
+                 --{b}\r\ncontent-disposition: form-data; name=\"note\"; filename=\"note.txt\"\r\n\
+                 content-type: text/plain\r\n\r\nfrom file\r\n--{b}--\r\n",
+                b = boundary
+            )
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn overrides_should_apply_to_mappings_and_multipart() {
+        let body = Body::Json(json!({"a": 1, "c": 3}))
+            .with_overrides(&[
+                ("b".into(), ArgValue::Json(json!(2))),
+                ("c".into(), ArgValue::Remove),
+            ])
+            .unwrap();
+        assert_eq!(body, Body::Json(json!({"a": 1, "b": 2})));
+
+        let body = Body::Multipart(vec![])
+            .with_overrides(&[("b".into(), ArgValue::Text("x".into()))])
+            .unwrap();
+        assert!(matches!(body, Body::Multipart(parts) if parts[0].value.as_deref() == Some("x")));
+
+        let err = Body::Raw("a".into())
+            .with_overrides(&[("b".into(), ArgValue::Text("2".into()))])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "body overrides need a mapping or a multipart body, not !raw"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::JsonPath;
+
+/// Where a captured variable is read from in a response.
+///
+/// Written as `status`, `header:<name>`, or a JSON path into the body such as `$.data.id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Capture {
+    Status,
+    Header(String),
+    Body(JsonPath),
+}
+
+impl Capture {
+    /// The captured value. JSON strings are unquoted, other values are kept as JSON.
+    pub fn extract(&self, status: StatusCode, headers: &HeaderMap, body: &str) -> Result<String> {
+        match self {
+            Capture::Status => Ok(status.as_u16().to_string()),
+            Capture::Header(name) => {
+                let value = headers
+                    .get(name)
+                    .ok_or_else(|| anyhow!("no `{}` header in the response", name))?;
+                Ok(value.to_str()?.to_string())
+            }
+            Capture::Body(path) => {
+                let json: serde_json::Value =
+                    serde_json::from_str(body).context("the response body is not JSON")?;
+                match path.select(&json).first() {
+                    Some(serde_json::Value::String(s)) => Ok(s.clone()),
+                    Some(value) => Ok(value.to_string()),
+                    None => Err(anyhow!("`{}` matched nothing in the response body", path)),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Capture {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s {
+            "status" => Ok(Capture::Status),
+            _ => match s.strip_prefix("header:") {
+                Some(name) => Ok(Capture::Header(name.trim().to_ascii_lowercase())),
+                None => Ok(Capture::Body(s.parse()?)),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Capture {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Capture> for String {
+    fn from(capture: Capture) -> Self {
+        capture.to_string()
+    }
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Capture::Status => write!(f, "status"),
+            Capture::Header(name) => write!(f, "header:{}", name),
+            Capture::Body(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn capture_should_extract_status_header_and_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert("location", HeaderValue::from_static("/todos/7"));
+        let body = r#"{"data": {"id": 7, "title": "todo"}}"#;
+        let extract = |capture: &str| {
+            capture
+                .parse::<Capture>()
+                .unwrap()
+                .extract(StatusCode::CREATED, &headers, body)
+        };
+
+        assert_eq!(extract("status").unwrap(), "201");
+        assert_eq!(extract("header:Location").unwrap(), "/todos/7");
+        assert_eq!(extract("$.data.id").unwrap(), "7");
+        assert_eq!(extract("data.title").unwrap(), "todo");
+        assert_eq!(
+            extract("$.data.missing").unwrap_err().to_string(),
+            "`$.data.missing` matched nothing in the response body"
+        );
+        assert!(extract("header:etag").is_err());
+    }
+}
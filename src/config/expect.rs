@@ -0,0 +1,536 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::client::duration;
+use crate::config::is_default;
+use crate::JsonPath;
+
+/// Checks run on the response of a profile, from its `expect:` block.
+///
+/// ```yaml
+/// expect:
+///   status: 2xx
+///   headers:
+///     content-type: ^application/json
+///     x-request-id: true
+///   json:
+///     $.data.items: { type: array, not_empty: true }
+///     $.data.owner: { equals: alice }
+///   body_contains: [alice]
+///   max_latency: 500ms
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Expect {
+    /// A code, a range such as `200-204` or `2xx`, or a list of them.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub status: Option<StatusExpect>,
+
+    /// `true` if the header must be present, `false` if it must not, or a regex its
+    /// value must match.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub headers: BTreeMap<String, HeaderExpect>,
+
+    /// Checks of the values matched by JSON paths in the body.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub json: BTreeMap<String, JsonExpect>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub body_contains: Vec<String>,
+
+    /// Maximum time from sending the request to reading the end of the body.
+    #[serde(skip_serializing_if = "Option::is_none", with = "duration", default)]
+    pub max_latency: Option<Duration>,
+}
+
+/// Inclusive ranges of accepted status codes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusExpect(Vec<(u16, u16)>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum HeaderExpect {
+    Present(bool),
+    Matches(Pattern),
+}
+
+/// A regex of an `expect:` block, compiled when the config is loaded.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+/// Every value matched by the path must pass all the checks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JsonExpect {
+    /// Set to `false` to check that the path matches nothing.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub exists: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub equals: Option<Value>,
+
+    /// A regex matched against strings, or against the JSON text of other values.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub matches: Option<Pattern>,
+
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none", default)]
+    pub kind: Option<JsonType>,
+
+    /// Strings, arrays and objects must have at least one item.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub not_empty: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+/// The outcome of one check of an `expect:` block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    /// What was checked, e.g. `status is 2xx`.
+    pub name: String,
+    /// Why the check failed, `None` if it passed.
+    pub failure: Option<String>,
+}
+
+impl Expect {
+    /// Check that the JSON paths are valid. The regexes are checked when parsed.
+    pub fn validate(&self) -> Result<()> {
+        for path in self.json.keys() {
+            path.parse::<JsonPath>()?;
+        }
+
+        Ok(())
+    }
+
+    /// Run all the checks on a response.
+    pub fn check(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+        latency: Duration,
+    ) -> Result<Vec<Assertion>> {
+        let mut assertions = vec![];
+
+        if let Some(expected) = &self.status {
+            assertions.push(Assertion::new(
+                format!("status is {}", expected),
+                (!expected.contains(status.as_u16())).then(|| format!("got {}", status)),
+            ));
+        }
+
+        for (name, expected) in &self.headers {
+            let value = headers.get(name).map(|v| v.to_str().unwrap_or_default());
+            let assertion = match (expected, value) {
+                (HeaderExpect::Present(true), value) => Assertion::new(
+                    format!("header {} is present", name),
+                    value.is_none().then(|| "missing".to_string()),
+                ),
+                (HeaderExpect::Present(false), value) => Assertion::new(
+                    format!("header {} is absent", name),
+                    value.map(|v| format!("got {:?}", v)),
+                ),
+                (HeaderExpect::Matches(pattern), value) => Assertion::new(
+                    format!("header {} matches {}", name, pattern),
+                    match value {
+                        Some(v) if pattern.is_match(v) => None,
+                        Some(v) => Some(format!("got {:?}", v)),
+                        None => Some("missing".into()),
+                    },
+                ),
+            };
+            assertions.push(assertion);
+        }
+
+        if !self.json.is_empty() {
+            let json = serde_json::from_str::<Value>(body);
+            for (path, expected) in &self.json {
+                let parsed: JsonPath = path.parse()?;
+                match &json {
+                    Ok(json) => assertions.extend(expected.check(path, &parsed.select(json))),
+                    Err(_) => assertions.push(Assertion::new(
+                        format!("{} is checked", path),
+                        Some("the body is not JSON".into()),
+                    )),
+                }
+            }
+        }
+
+        for text in &self.body_contains {
+            assertions.push(Assertion::new(
+                format!("body contains {:?}", text),
+                (!body.contains(text.as_str())).then(|| "not found".to_string()),
+            ));
+        }
+
+        if let Some(max) = self.max_latency {
+            assertions.push(Assertion::new(
+                format!("latency is at most {:?}", max),
+                (latency > max).then(|| format!("took {}ms", latency.as_millis())),
+            ));
+        }
+
+        Ok(assertions)
+    }
+}
+
+impl JsonExpect {
+    fn check(&self, path: &str, values: &[&Value]) -> Vec<Assertion> {
+        let exists = self.exists.unwrap_or(true);
+        let found = !values.is_empty();
+        if !exists || !found {
+            let name = match exists {
+                true => format!("{} exists", path),
+                false => format!("{} does not exist", path),
+            };
+            let failure = match (exists, found) {
+                (true, false) => Some("matched nothing".to_string()),
+                (false, true) => Some(format!("got {}", values[0])),
+                _ => None,
+            };
+            return vec![Assertion::new(name, failure)];
+        }
+
+        let mut assertions = vec![];
+        let first_failure = |check: &dyn Fn(&Value) -> bool| {
+            values
+                .iter()
+                .find(|v| !check(v))
+                .map(|v| format!("got {}", v))
+        };
+
+        if let Some(expected) = &self.equals {
+            assertions.push(Assertion::new(
+                format!("{} equals {}", path, expected),
+                first_failure(&|v| v == expected),
+            ));
+        }
+
+        if let Some(pattern) = &self.matches {
+            assertions.push(Assertion::new(
+                format!("{} matches {}", path, pattern),
+                first_failure(&|v| match v {
+                    Value::String(s) => pattern.is_match(s),
+                    _ => pattern.is_match(&v.to_string()),
+                }),
+            ));
+        }
+
+        if let Some(kind) = self.kind {
+            assertions.push(Assertion::new(
+                format!("{} is a {}", path, kind),
+                first_failure(&|v| JsonType::of(v) == kind),
+            ));
+        }
+
+        if self.not_empty {
+            assertions.push(Assertion::new(
+                format!("{} is not empty", path),
+                first_failure(&|v| match v {
+                    Value::String(s) => !s.is_empty(),
+                    Value::Array(a) => !a.is_empty(),
+                    Value::Object(o) => !o.is_empty(),
+                    Value::Null => false,
+                    _ => true,
+                }),
+            ));
+        }
+
+        // a bare path only checks that something matched
+        if assertions.is_empty() {
+            assertions.push(Assertion::new(format!("{} exists", path), None));
+        }
+
+        assertions
+    }
+}
+
+impl JsonType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => JsonType::Null,
+            Value::Bool(_) => JsonType::Bool,
+            Value::Number(_) => JsonType::Number,
+            Value::String(_) => JsonType::String,
+            Value::Array(_) => JsonType::Array,
+            Value::Object(_) => JsonType::Object,
+        }
+    }
+}
+
+impl fmt::Display for JsonType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            JsonType::Null => "null",
+            JsonType::Bool => "bool",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Assertion {
+    fn new(name: String, failure: Option<String>) -> Self {
+        Self { name, failure }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl StatusExpect {
+    pub fn contains(&self, status: u16) -> bool {
+        self.0
+            .iter()
+            .any(|(min, max)| (*min..=*max).contains(&status))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut ranges = vec![];
+        for item in text.split(',').map(str::trim) {
+            let invalid = || {
+                anyhow!(
+                    "invalid status `{}`, expected e.g. 200, 2xx or 200-204",
+                    item
+                )
+            };
+            let range = if let Some(class) = item.strip_suffix("xx").or(item.strip_suffix("XX")) {
+                let class: u16 = class.parse().map_err(|_| invalid())?;
+                if !(1..=5).contains(&class) {
+                    return Err(invalid());
+                }
+                (class * 100, class * 100 + 99)
+            } else if let Some((min, max)) = item.split_once('-') {
+                (
+                    min.trim().parse().map_err(|_| invalid())?,
+                    max.trim().parse().map_err(|_| invalid())?,
+                )
+            } else {
+                let code = item.parse().map_err(|_| invalid())?;
+                (code, code)
+            };
+
+            let (min, max) = range;
+            if !(100..=599).contains(&min) || !(100..=599).contains(&max) || min > max {
+                return Err(invalid());
+            }
+            ranges.push(range);
+        }
+
+        Ok(Self(ranges))
+    }
+}
+
+impl fmt::Display for StatusExpect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ranges: Vec<_> = self
+            .0
+            .iter()
+            .map(|&(min, max)| match (min, max) {
+                _ if min == max => min.to_string(),
+                _ if min % 100 == 0 && max == min + 99 => format!("{}xx", min / 100),
+                _ => format!("{}-{}", min, max),
+            })
+            .collect();
+        write!(f, "{}", ranges.join(", "))
+    }
+}
+
+impl Serialize for StatusExpect {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.0[..] {
+            [(min, max)] if min == max => s.serialize_u16(min),
+            _ => s.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusExpect {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Code(u16),
+            Text(String),
+            List(Vec<Raw>),
+        }
+
+        fn flatten(raw: Raw) -> String {
+            match raw {
+                Raw::Code(code) => code.to_string(),
+                Raw::Text(text) => text,
+                Raw::List(items) => items.into_iter().map(flatten).collect::<Vec<_>>().join(","),
+            }
+        }
+
+        Self::parse(&flatten(Raw::deserialize(d)?)).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for HeaderExpect {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Present(bool),
+            Matches(String),
+        }
+
+        match Raw::deserialize(d)? {
+            Raw::Present(present) => Ok(HeaderExpect::Present(present)),
+            Raw::Matches(pattern) => Pattern::new(&pattern)
+                .map(HeaderExpect::Matches)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+            .map(Self)
+            .map_err(|e| anyhow!("invalid regex {}: {}", pattern, e))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Self::new(&String::deserialize(d)?).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn status_expect_should_parse() {
+        let parse = |yaml: &str| serde_yaml::from_str::<StatusExpect>(yaml).unwrap();
+
+        assert!(parse("200").contains(200));
+        assert!(!parse("200").contains(201));
+        assert!(parse("2xx").contains(204));
+        assert!(parse("[200, 301-302]").contains(302));
+        assert_eq!(
+            parse("[200, 301-302, 4xx]").to_string(),
+            "200, 301-302, 4xx"
+        );
+        assert!(serde_yaml::from_str::<StatusExpect>("ok").is_err());
+
+        for invalid in [
+            "700xx", "0xx", "6xx", "0", "999", "99-200", "200-600", "204-200",
+        ] {
+            let err = serde_yaml::from_str::<StatusExpect>(invalid).unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with(&format!("invalid status `{}`", invalid)));
+        }
+    }
+
+    #[test]
+    fn expect_should_report_each_check() {
+        let expect: Expect = serde_yaml::from_str(
+            r#"
+status: 2xx
+headers:
+  content-type: ^application/json
+  x-request-id: true
+  x-debug: false
+json:
+  $.data.items: { type: array, not_empty: true }
+  $.data.items[*].id: { type: number }
+  $.data.owner: { equals: alice, matches: "^a" }
+  $.data.deleted: { exists: false }
+  $.data.missing: {}
+body_contains: [alice, bob]
+max_latency: 500ms
+"#,
+        )
+        .unwrap();
+        expect.validate().unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers.insert("x-debug", HeaderValue::from_static("1"));
+        let body = r#"{"data": {"items": [{"id": 1}, {"id": "2"}], "owner": "alice"}}"#;
+
+        let assertions = expect
+            .check(StatusCode::OK, &headers, body, Duration::from_millis(800))
+            .unwrap();
+        let failures: Vec<_> = assertions
+            .iter()
+            .filter(|a| !a.passed())
+            .map(|a| format!("{}: {}", a.name, a.failure.as_ref().unwrap()))
+            .collect();
+
+        assert_eq!(assertions.len(), 14);
+        assert_eq!(
+            failures,
+            [
+                "header x-debug is absent: got \"1\"",
+                "header x-request-id is present: missing",
+                "$.data.items[*].id is a number: got \"2\"",
+                "$.data.missing exists: matched nothing",
+                "body contains \"bob\": not found",
+                "latency is at most 500ms: took 800ms",
+            ]
+        );
+    }
+
+    #[test]
+    fn expect_should_compile_regexes_when_parsed() {
+        let expect: Expect =
+            serde_yaml::from_str("headers:\n  etag: ^W/\njson:\n  $.id: { matches: '^\\d+$' }")
+                .unwrap();
+        assert_eq!(
+            serde_yaml::to_string(&expect).unwrap(),
+            "headers:\n  etag: ^W/\njson:\n  $.id:\n    matches: ^\\d+$\n"
+        );
+
+        let err = serde_yaml::from_str::<Expect>("headers:\n  etag: '('").unwrap_err();
+        assert!(err.to_string().contains("invalid regex ("), "{}", err);
+        assert!(serde_yaml::from_str::<Expect>("json:\n  $.id: { matches: '(' }").is_err());
+    }
+}
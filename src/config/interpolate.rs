@@ -0,0 +1,369 @@
+use anyhow::{anyhow, Context, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::env;
+
+/// Key of the top level block holding user defined variables.
+pub const VARS_KEY: &str = "vars";
+
+/// Top level keys of settings shared by the profiles, rather than profiles.
+pub(crate) const BLOCK_KEYS: &[&str] = &["client", "flows"];
+
+/// Key of the profile block declaring variables captured from the response.
+pub const CAPTURES_KEY: &str = "captures";
+
+/// Characters escaped in a captured value placed in a path segment of the url.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Substitute placeholders in every string of a config document.
+///
+/// - `${NAME}` is replaced by the environment variable `NAME`
+/// - `${NAME:-default}` falls back to `default` if `NAME` is unset or empty
+/// - `{{name}}` is replaced by the entry `name` of the top level `vars:` block
+/// - `$${` and `\{{` are written as a literal `${` and `{{`
+///
+/// Values of the `vars:` block may themselves reference environment variables.
+/// Placeholders of variables declared in a `captures:` block are kept as is, they
+/// are only known once a flow runs.
+pub fn interpolate(mut value: Value) -> Result<Value> {
+    let Value::Mapping(ref mut root) = value else {
+        return Ok(value);
+    };
+
+    let mut vars = resolve_vars(root)?;
+    for name in capture_names(root) {
+        vars.insert(name.clone(), format!("{{{{{}}}}}", name));
+    }
+
+    for (key, profile) in root.iter_mut() {
+        let name = key_to_string(key);
+        if name == VARS_KEY {
+            continue;
+        }
+
+        let kind = match BLOCK_KEYS.contains(&name.as_str()) {
+            true => "block",
+            false => "profile",
+        };
+        substitute_value(profile, "", &|s| substitute_str(s, &vars))
+            .with_context(|| format!("failed to interpolate {}: {}", kind, name))?;
+    }
+
+    Ok(value)
+}
+
+/// Substitute the placeholders of a profile as written in the config, once the captured
+/// variables are known. Captured values placed in the path of the url are percent-encoded,
+/// so that each of them stays in one path segment.
+pub fn substitute_profile(
+    profile: &mut Value,
+    vars: &HashMap<String, String>,
+    captured: &HashMap<String, String>,
+) -> Result<()> {
+    let mut all = vars.clone();
+    all.extend(captured.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    let url = profile.get_mut("url").map(std::mem::take);
+    substitute_value(profile, "", &|s| substitute_str(s, &all))?;
+
+    if let Some(mut url) = url {
+        if let Value::String(s) = &mut url {
+            *s = substitute_url(s, &all, captured).map_err(|e| anyhow!("{} at field `url`", e))?;
+        }
+        profile["url"] = url;
+    }
+
+    Ok(())
+}
+
+fn substitute_url(
+    url: &str,
+    vars: &HashMap<String, String>,
+    captured: &HashMap<String, String>,
+) -> Result<String> {
+    // the path starts after the authority, or after a leading `{{base}}` placeholder
+    let start = match url.find("://") {
+        Some(i) => url[i + 3..].find('/').map(|j| i + 3 + j),
+        None => url.find('/'),
+    }
+    .unwrap_or(url.len());
+    let end = url[start..]
+        .find(['?', '#'])
+        .map_or(url.len(), |i| start + i);
+
+    let mut path_vars = vars.clone();
+    for (name, value) in captured {
+        let value = utf8_percent_encode(value, PATH_SEGMENT).to_string();
+        path_vars.insert(name.clone(), value);
+    }
+
+    Ok(format!(
+        "{}{}{}",
+        substitute_str(&url[..start], vars)?,
+        substitute_str(&url[start..end], &path_vars)?,
+        substitute_str(&url[end..], vars)?
+    ))
+}
+
+/// Names of the variables declared in the `captures:` blocks of the profiles.
+fn capture_names(root: &Mapping) -> Vec<String> {
+    root.values()
+        .filter_map(|profile| profile.get(CAPTURES_KEY)?.as_mapping())
+        .flat_map(|captures| captures.keys().map(key_to_string))
+        .collect()
+}
+
+/// Resolve the `vars:` block in place and return its entries.
+fn resolve_vars(root: &mut Mapping) -> Result<HashMap<String, String>> {
+    let mut vars = HashMap::new();
+
+    let Some(block) = root.get_mut(VARS_KEY) else {
+        return Ok(vars);
+    };
+
+    let Value::Mapping(block) = block else {
+        return Err(anyhow!(
+            "`{}` must be a mapping of names to values",
+            VARS_KEY
+        ));
+    };
+
+    for (key, value) in block.iter_mut() {
+        let name = key_to_string(key);
+        let text = match value {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            _ => return Err(anyhow!("variable `{}` must be a scalar", name)),
+        };
+
+        let text = substitute_str(&text, &HashMap::new())
+            .map_err(|e| anyhow!("{} in variable `{}`", e, name))?;
+
+        *value = Value::String(text.clone());
+        vars.insert(name, text);
+    }
+
+    Ok(vars)
+}
+
+fn substitute_value(
+    value: &mut Value,
+    field: &str,
+    substitute: &dyn Fn(&str) -> Result<String>,
+) -> Result<()> {
+    match value {
+        Value::String(s) => {
+            *s = substitute(s).map_err(|e| anyhow!("{} at field `{}`", e, field))?;
+        }
+        Value::Sequence(seq) => {
+            for (idx, item) in seq.iter_mut().enumerate() {
+                substitute_value(item, &format!("{}[{}]", field, idx), substitute)?;
+            }
+        }
+        Value::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                let key = key_to_string(key);
+                let field = match field {
+                    "" => key,
+                    _ => format!("{}.{}", field, key),
+                };
+                substitute_value(item, &field, substitute)?;
+            }
+        }
+        Value::Tagged(tagged) => substitute_value(&mut tagged.value, field, substitute)?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Substitute `${ENV}`, `${ENV:-default}` and `{{var}}` placeholders in a string.
+pub fn substitute_str(s: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find(['$', '{', '\\']) {
+        output.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        if let Some(inner) = tail.strip_prefix("$${") {
+            output.push_str("${");
+            rest = inner;
+        } else if let Some(inner) = tail.strip_prefix("\\{{") {
+            output.push_str("{{");
+            rest = inner;
+        } else if let Some(inner) = tail.strip_prefix("${") {
+            let end = inner
+                .find('}')
+                .ok_or_else(|| anyhow!("unclosed placeholder `{}`", tail))?;
+            output.push_str(&resolve_env(&inner[..end])?);
+            rest = &inner[end + 1..];
+        } else if let Some(inner) = tail.strip_prefix("{{") {
+            let end = inner
+                .find("}}")
+                .ok_or_else(|| anyhow!("unclosed placeholder `{}`", tail))?;
+            let name = inner[..end].trim();
+            let value = vars
+                .get(name)
+                .ok_or_else(|| anyhow!("unresolved variable `{{{{{}}}}}`", name))?;
+            output.push_str(value);
+            rest = &inner[end + 2..];
+        } else {
+            output.push_str(&tail[..1]);
+            rest = &tail[1..];
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn resolve_env(expr: &str) -> Result<String> {
+    let (name, default) = match expr.split_once(":-") {
+        Some((name, default)) => (name.trim(), Some(default)),
+        None => (expr.trim(), None),
+    };
+
+    match (env::var(name), default) {
+        (Ok(value), Some(default)) if value.is_empty() => Ok(default.to_string()),
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(default)) => Ok(default.to_string()),
+        (Err(_), None) => Err(anyhow!("unresolved environment variable `${{{}}}`", name)),
+    }
+}
+
+fn key_to_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        _ => serde_yaml::to_string(key)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute_str_should_work() {
+        env::set_var("XDIFF_TEST_HOST", "example.com");
+        env::remove_var("XDIFF_TEST_MISSING");
+
+        let vars = HashMap::from([("id".to_string(), "42".to_string())]);
+
+        assert_eq!(
+            substitute_str("https://${XDIFF_TEST_HOST}/todos/{{ id }}", &vars).unwrap(),
+            "https://example.com/todos/42"
+        );
+        assert_eq!(
+            substitute_str("${XDIFF_TEST_MISSING:-localhost}:8080", &vars).unwrap(),
+            "localhost:8080"
+        );
+        assert_eq!(
+            substitute_str("{\"a\": {\"b\": 1}}", &vars).unwrap(),
+            "{\"a\": {\"b\": 1}}"
+        );
+        assert_eq!(
+            substitute_str("echo $${HOME} \\{{ id }} $$ \\n {{id}}", &vars).unwrap(),
+            "echo ${HOME} {{ id }} $$ \\n 42"
+        );
+        assert!(substitute_str("${XDIFF_TEST_MISSING}", &vars).is_err());
+        assert!(substitute_str("{{missing}}", &vars).is_err());
+    }
+
+    #[test]
+    fn interpolate_should_keep_captured_placeholders() {
+        let mut value: Value = serde_yaml::from_str(
+            r#"
+vars:
+  host: example.com
+create:
+  url: https://{{host}}/todos
+  captures:
+    id: $.id
+get:
+  url: https://{{host}}/todos/{{id}}
+"#,
+        )
+        .unwrap();
+
+        let raw = value["get"].clone();
+        value = interpolate(value).unwrap();
+        assert_eq!(value["get"]["url"], "https://example.com/todos/{{id}}");
+
+        let vars = HashMap::from([("host".to_string(), "example.com".to_string())]);
+        let captured = HashMap::from([("id".to_string(), "a/b?c".to_string())]);
+        let mut profile = raw.clone();
+        substitute_profile(&mut profile, &vars, &captured).unwrap();
+        assert_eq!(profile["url"], "https://example.com/todos/a%2Fb%3Fc");
+
+        let mut profile: Value =
+            serde_yaml::from_str("url: '{{base}}/todos/{{id}}?q={{id}}'\nbody: '{{id}}'").unwrap();
+        let vars = HashMap::from([("base".to_string(), "http://a.com/v1".to_string())]);
+        substitute_profile(&mut profile, &vars, &captured).unwrap();
+        assert_eq!(profile["url"], "http://a.com/v1/todos/a%2Fb%3Fc?q=a/b?c");
+        assert_eq!(profile["body"], "a/b?c");
+
+        let mut profile = raw;
+        let err = substitute_profile(&mut profile, &vars, &HashMap::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unresolved variable `{{host}}` at field `url`"
+        );
+    }
+
+    #[test]
+    fn interpolate_should_name_blocks() {
+        let value: Value = serde_yaml::from_str(
+            r#"
+client:
+  headers:
+    user-agent: ${XDIFF_TEST_MISSING}
+"#,
+        )
+        .unwrap();
+
+        let err = interpolate(value).unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", err),
+            "failed to interpolate block: client: unresolved environment variable `${XDIFF_TEST_MISSING}` at field `headers.user-agent`"
+        );
+    }
+
+    #[test]
+    fn interpolate_should_name_profile_and_field() {
+        let value: Value = serde_yaml::from_str(
+            r#"
+vars:
+  host: example.com
+todo:
+  url: https://{{host}}/todos
+  headers:
+    authorization: Bearer {{token}}
+"#,
+        )
+        .unwrap();
+
+        let err = interpolate(value).unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", err),
+            "failed to interpolate profile: todo: unresolved variable `{{token}}` at field `headers.authorization`"
+        );
+    }
+}
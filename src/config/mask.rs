@@ -0,0 +1,357 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use reqwest::header::{Entry, HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::JsonPath;
+
+/// A regex replacement applied to a response before it is diffed, to normalize volatile
+/// values such as etags, request ids or timestamps.
+///
+/// ```yaml
+/// mask:
+///   - in: header:etag
+///     preset: hex
+///   - in: $.data[*].created_at
+///     preset: iso8601
+///   - pattern: 'req-[0-9]+'
+///     replace: req-<id>
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mask {
+    /// Where the rule applies, the body text by default.
+    #[serde(rename = "in", skip_serializing_if = "is_body", default)]
+    pub target: MaskTarget,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pattern: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub preset: Option<MaskPreset>,
+
+    /// Replacement of the matches, which may refer to groups as `$1`. Defaults to a
+    /// placeholder such as `<uuid>`, or `<masked>` for a pattern.
+    ///
+    /// Configs are interpolated first, so the `${1}` form is written `$${1}`, otherwise
+    /// it is read as the environment variable `1`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub replace: Option<String>,
+
+    /// The regex of the pattern or preset, compiled once.
+    #[serde(skip)]
+    compiled: CompiledRegex,
+}
+
+/// A lazily compiled regex, ignored when comparing masks.
+#[derive(Debug, Clone, Default)]
+struct CompiledRegex(OnceLock<Regex>);
+
+impl PartialEq for CompiledRegex {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for CompiledRegex {}
+
+/// Part of a response a mask applies to, written as `status`, `header:<name>`, `body`,
+/// or a JSON path into the body such as `$.data.id`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum MaskTarget {
+    Status,
+    Header(String),
+    #[default]
+    Body,
+    Json(JsonPath),
+}
+
+/// Built-in patterns for common volatile values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskPreset {
+    Uuid,
+    Iso8601,
+    /// Hex strings of at least 16 digits, such as hashes and trace ids.
+    Hex,
+}
+
+impl Mask {
+    pub fn validate(&self) -> Result<()> {
+        if let MaskTarget::Header(name) = &self.target {
+            HeaderName::from_str(name)
+                .with_context(|| format!("invalid header name in mask: {}", name))?;
+        }
+        self.regex().map(|_| ())
+    }
+
+    /// Replace the matches in a text.
+    pub fn apply(&self, text: &str) -> Result<String> {
+        Ok(self
+            .regex()?
+            .replace_all(text, self.replacement())
+            .into_owned())
+    }
+
+    fn regex(&self) -> Result<&Regex> {
+        if let Some(regex) = self.compiled.0.get() {
+            return Ok(regex);
+        }
+
+        let pattern = match (&self.pattern, self.preset) {
+            (Some(pattern), None) => pattern.as_str(),
+            (None, Some(preset)) => preset.pattern(),
+            _ => {
+                return Err(anyhow!(
+                    "mask in {} needs either a pattern or a preset",
+                    self.target
+                ))
+            }
+        };
+
+        let regex =
+            Regex::new(pattern).with_context(|| format!("invalid mask pattern: {}", pattern))?;
+        Ok(self.compiled.0.get_or_init(|| regex))
+    }
+
+    fn replacement(&self) -> &str {
+        match (&self.replace, self.preset) {
+            (Some(replace), _) => replace,
+            (None, Some(preset)) => preset.placeholder(),
+            (None, None) => "<masked>",
+        }
+    }
+}
+
+impl MaskPreset {
+    fn pattern(&self) -> &'static str {
+        match self {
+            MaskPreset::Uuid => {
+                r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b"
+            }
+            MaskPreset::Iso8601 => {
+                r"\b\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(:\d{2}(\.\d+)?)?(Z|[+-]\d{2}:?\d{2})?"
+            }
+            MaskPreset::Hex => r"\b[0-9a-fA-F]{16,}\b",
+        }
+    }
+
+    fn placeholder(&self) -> &'static str {
+        match self {
+            MaskPreset::Uuid => "<uuid>",
+            MaskPreset::Iso8601 => "<timestamp>",
+            MaskPreset::Hex => "<hex>",
+        }
+    }
+}
+
+/// Apply the masks of the status line.
+pub(crate) fn mask_status(masks: &[Mask], status: String) -> Result<String> {
+    masks
+        .iter()
+        .filter(|m| m.target == MaskTarget::Status)
+        .try_fold(status, |text, mask| mask.apply(&text))
+}
+
+/// Apply the masks of single headers to their values.
+pub(crate) fn mask_headers(masks: &[Mask], headers: &mut HeaderMap) -> Result<()> {
+    for mask in masks {
+        let MaskTarget::Header(name) = &mask.target else {
+            continue;
+        };
+        if let Entry::Occupied(mut entry) = headers.entry(HeaderName::from_str(name)?) {
+            for value in entry.iter_mut() {
+                let masked = mask.apply(&String::from_utf8_lossy(value.as_bytes()))?;
+                *value = HeaderValue::from_str(&masked)
+                    .with_context(|| format!("invalid masked value of header {}", name))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply the masks of JSON paths to the values they select. Values other than strings
+/// are masked as JSON text, and become strings if changed.
+pub(crate) fn mask_json(masks: &[Mask], json: &mut Value) -> Result<()> {
+    for mask in masks {
+        let MaskTarget::Json(path) = &mask.target else {
+            continue;
+        };
+        path.update(json, |value| {
+            let text = match &*value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let masked = mask.apply(&text)?;
+            if masked != text {
+                *value = Value::String(masked);
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Apply the masks of the body to its normalized text.
+pub(crate) fn mask_body(masks: &[Mask], body: String) -> Result<String> {
+    masks
+        .iter()
+        .filter(|m| m.target == MaskTarget::Body)
+        .try_fold(body, |text, mask| mask.apply(&text))
+}
+
+fn is_body(target: &MaskTarget) -> bool {
+    *target == MaskTarget::Body
+}
+
+impl FromStr for MaskTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s {
+            "status" => Ok(MaskTarget::Status),
+            "body" => Ok(MaskTarget::Body),
+            _ => match s.strip_prefix("header:") {
+                Some(name) => Ok(MaskTarget::Header(name.trim().to_ascii_lowercase())),
+                None => Ok(MaskTarget::Json(s.parse()?)),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for MaskTarget {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<MaskTarget> for String {
+    fn from(target: MaskTarget) -> Self {
+        target.to_string()
+    }
+}
+
+impl fmt::Display for MaskTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaskTarget::Status => write!(f, "status"),
+            MaskTarget::Header(name) => write!(f, "header:{}", name),
+            MaskTarget::Body => write!(f, "body"),
+            MaskTarget::Json(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn masks(yaml: &str) -> Vec<Mask> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn presets_should_mask_volatile_values() {
+        let masks = masks("[{preset: uuid}, {preset: iso8601}, {preset: hex}]");
+        let body = r#"{"id": "3f2b9c1e-8a4d-4b6f-9c2e-1d7a5b3c9e0f", "at": "2023-04-05T06:07:08.123Z", "sha": "9fceb02d0ae598e95dc970b74767f19372d61af8", "day": "2023-04-05"}"#;
+
+        assert_eq!(
+            mask_body(&masks, body.into()).unwrap(),
+            r#"{"id": "<uuid>", "at": "<timestamp>", "sha": "<hex>", "day": "2023-04-05"}"#
+        );
+    }
+
+    #[test]
+    fn masks_should_apply_to_their_target() {
+        let masks = masks(
+            r#"
+- in: header:ETag
+  pattern: '"(\w+)-\d+"'
+  replace: '"$1-<n>"'
+- in: $.items[*].id
+  pattern: '\d+'
+- in: status
+  pattern: HTTP/\S+
+  replace: HTTP
+"#,
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("etag", HeaderValue::from_static("\"abc-42\""));
+        headers.insert("x-id", HeaderValue::from_static("7"));
+        mask_headers(&masks, &mut headers).unwrap();
+        assert_eq!(headers["etag"], "\"abc-<n>\"");
+        assert_eq!(headers["x-id"], "7");
+
+        let mut json = json!({"items": [{"id": 1, "ok": true}, {"id": "a2"}]});
+        mask_json(&masks, &mut json).unwrap();
+        assert_eq!(
+            json,
+            json!({"items": [{"id": "<masked>", "ok": true}, {"id": "a<masked>"}]})
+        );
+
+        assert_eq!(
+            mask_status(&masks, "HTTP/1.1 200 OK".into()).unwrap(),
+            "HTTP 200 OK"
+        );
+        assert_eq!(mask_body(&masks, "1".into()).unwrap(), "1");
+    }
+
+    #[test]
+    fn invalid_masks_should_fail_to_validate() {
+        assert!(masks("[{pattern: '('}]")[0].validate().is_err());
+        assert!(masks("[{in: status}]")[0].validate().is_err());
+        assert!(masks("[{pattern: a, preset: uuid}]")[0].validate().is_err());
+        assert!(serde_yaml::from_str::<Mask>("{in: 'a[', preset: hex}").is_err());
+
+        let err = masks("[{in: 'header:e tag', preset: hex}]")[0]
+            .validate()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid header name in mask: e tag");
+    }
+
+    #[test]
+    fn mask_should_compile_its_regex_once() {
+        let mask = &masks("[{preset: hex}]")[0];
+        mask.validate().unwrap();
+        let compiled = mask.regex().unwrap() as *const Regex;
+
+        assert_eq!(mask.apply("id: 9fceb02d0ae598e9").unwrap(), "id: <hex>");
+        assert_eq!(mask.regex().unwrap() as *const Regex, compiled);
+        assert_eq!(mask, &masks("[{preset: hex}]")[0]);
+    }
+
+    #[test]
+    fn escaped_group_reference_should_survive_interpolation() {
+        use crate::{DiffConfig, LoadConfig};
+
+        let config = DiffConfig::from_yaml(
+            r#"
+todo:
+  req1:
+    url: https://a.com/1
+  req2:
+    url: https://a.com/2
+  res:
+    mask:
+      - pattern: 'req-(\d+)'
+        replace: '$${1}-id'
+"#,
+        )
+        .unwrap();
+        let mask = &config.get_profile("todo").unwrap().res.mask[0];
+
+        assert_eq!(mask.replace.as_deref(), Some("${1}-id"));
+        assert_eq!(mask.apply("req-42").unwrap(), "42-id");
+    }
+}
@@ -1,23 +1,31 @@
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{anyhow, Context, Ok, Result};
 use async_trait::async_trait;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
-    Client, Method, Response, Url,
+    Client, Method, Request, Response, StatusCode, Url,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
-use serde_urlencoded;
-use serde_yaml;
-use std::fmt::Write;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
 use std::str::FromStr;
 use tokio::fs;
 
-use crate::{cli::KeyValType, ExtraArgs};
+use crate::{cli::ArgValue, Auth, ExtraArgs, HttpClient, JsonPath, Signing, TransportOptions};
 
+mod body;
+mod capture;
+mod expect;
+mod interpolate;
+mod mask;
 mod xdiff;
 mod xreq;
 
-pub use xdiff::{DiffConfig, DiffProfile, ResponseProfile};
+pub use body::{Body, Part};
+pub use capture::Capture;
+pub use expect::{Assertion, Expect, HeaderExpect, JsonExpect, JsonType, Pattern, StatusExpect};
+pub use mask::{Mask, MaskPreset, MaskTarget};
+pub use xdiff::{DiffConfig, DiffMode, DiffProfile, ResponseProfile};
 pub use xreq::RequestConfig;
 
 #[async_trait]
@@ -31,14 +39,20 @@ where
         Self::from_yaml(&content)
     }
 
-    /// Load config from yaml string
+    /// Load config from yaml string, substituting `${ENV}` and `{{var}}` placeholders
     fn from_yaml(content: &str) -> Result<Self> {
-        let config: Self = serde_yaml::from_str(content)?;
-        config.validate()?;
-        Ok(config)
+        from_yaml_value(serde_yaml::from_str(content)?)
     }
 }
 
+fn from_yaml_value<T: DeserializeOwned + ValidateConfig>(value: serde_yaml::Value) -> Result<T> {
+    let mut value = interpolate::interpolate(value)?;
+    body::untag_config(&mut value);
+    let config: T = serde_yaml::from_value(value)?;
+    config.validate()?;
+    Ok(config)
+}
+
 pub trait ValidateConfig {
     fn validate(&self) -> Result<()>;
 }
@@ -64,71 +78,124 @@ pub struct RequestProfile {
     )]
     pub headers: HeaderMap,
 
-    #[serde(skip_serializing_if = "empty_json_value", default)]
-    pub body: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "empty_body", default)]
+    pub body: Option<Body>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub auth: Option<Auth>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sign: Option<Signing>,
+
+    /// Variables read from the response, usable as `{{name}}` by the next steps of a flow.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub captures: HashMap<String, Capture>,
+
+    /// Checks of the response, reported by xreq.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub expect: Option<Expect>,
+
+    /// Timeouts and retry policy of this request, overriding the `client:` block.
+    #[serde(flatten)]
+    pub transport: TransportOptions,
 }
 
+#[derive(Debug)]
 pub struct ResponseExt(Response);
 
+/// Normalized text of a response, ready to be diffed.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ResponseText {
+    pub status: String,
+    pub headers: String,
+    pub body: String,
+}
+
 impl RequestProfile {
-    pub async fn send(&self, args: &ExtraArgs) -> Result<ResponseExt> {
-        let (headers, query, body) = self.generate(&args)?;
+    pub async fn send(&self, client: &HttpClient, args: &ExtraArgs) -> Result<ResponseExt> {
+        let res = self.send_once(client, args).await?;
+
+        // a cached oauth2 token may be revoked before it expires: fetch a new one, once
+        if let Some(Auth::Oauth2ClientCredentials(oauth2)) = &self.auth {
+            if res.status() == StatusCode::UNAUTHORIZED {
+                client.tokens().invalidate(oauth2).await;
+                return Ok(ResponseExt(self.send_once(client, args).await?));
+            }
+        }
 
-        let client = Client::new();
-        let req = client
-            .request(self.method.clone(), self.url.clone())
-            .query(&query)
+        Ok(ResponseExt(res))
+    }
+
+    async fn send_once(&self, client: &HttpClient, args: &ExtraArgs) -> Result<Response> {
+        let req = self.build_request(client, args).await?;
+        client.send(req, &self.transport).await
+    }
+
+    /// Build the request as [`RequestProfile::send`] sends it: resolved with the extra
+    /// args, with the oauth2 token (fetched if needed) and the cookies of the session of
+    /// the client, then signed.
+    pub async fn build_request(&self, client: &HttpClient, args: &ExtraArgs) -> Result<Request> {
+        let mut req = self.build_unsigned_request(client.client(), args)?;
+        if let Some(auth) = &self.auth {
+            auth.apply_oauth2(client, &mut req).await?;
+        }
+        client.add_cookies(&mut req)?;
+        if let Some(sign) = &self.sign {
+            sign.apply(&mut req)?;
+        }
+
+        Ok(req)
+    }
+
+    fn build_unsigned_request(&self, client: &Client, args: &ExtraArgs) -> Result<Request> {
+        let (headers, query, body) = self.generate(args)?;
+
+        // serde_qs, unlike the query encoder of reqwest, supports nested params
+        let mut url = self.url.clone();
+        if query.as_object().is_some_and(|q| !q.is_empty()) {
+            let mut pairs = url.query().map(|q| format!("{}&", q)).unwrap_or_default();
+            pairs.push_str(&serde_qs::to_string(&query)?);
+            url.set_query(Some(&pairs));
+        }
+
+        let mut req = client
+            .request(self.method.clone(), url)
             .headers(headers)
             .body(body)
             .build()?;
 
-        let res = client.execute(req).await?;
+        if let Some(auth) = &self.auth {
+            auth.apply(&mut req)?;
+        }
 
-        Ok(ResponseExt(res))
+        Ok(req)
     }
 
-    fn generate(&self, args: &ExtraArgs) -> Result<(HeaderMap, serde_json::Value, String)> {
+    fn generate(&self, args: &ExtraArgs) -> Result<(HeaderMap, serde_json::Value, Vec<u8>)> {
         let mut header = self.headers.clone();
         let mut query = self.params.clone().unwrap_or_else(|| json!({}));
-        let mut body = self.body.clone().unwrap_or_else(|| json!({}));
-
-        for (key_value_type, value) in args.clone().into_iter() {
-            match key_value_type {
-                KeyValType::Header => {
-                    for (key, value) in &value {
-                        header.insert(HeaderName::from_str(key)?, HeaderValue::from_str(value)?);
-                    }
-
-                    if !header.contains_key(CONTENT_TYPE) {
-                        header.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-                    }
-                }
-                KeyValType::Query => {
-                    for (key, value) in &value {
-                        query[key] = value.parse()?;
-                    }
-                }
-                KeyValType::Body => {
-                    for (key, value) in &value {
-                        body[key] = value.parse()?;
-                    }
-                }
-            }
+
+        for (key, value) in &args.headers {
+            header.insert(HeaderName::from_str(key)?, HeaderValue::from_str(value)?);
+        }
+        for (key, value) in &args.query {
+            apply_override(&mut query, key, value)?;
         }
 
-        let content_type = get_content_type(&header);
+        // a request without a body is sent without one, nor a content type
+        let body = match &self.body {
+            None if args.body.is_empty() => return Ok((header, query, vec![])),
+            None => Body::Json(json!({})),
+            Some(body) => body.clone(),
+        };
 
-        match content_type.as_deref() {
-            Some("application/json") => {
-                let body = serde_json::to_string(&body)?;
-                Ok((header, query, body))
-            }
-            Some("application/x-www-form-urlencoded" | "multipart/form-data") => {
-                let body = serde_urlencoded::to_string(&body)?;
-                Ok((header, query, body))
-            }
-            _ => Err(anyhow::anyhow!("Unsupported content type!")),
+        // the other bodies have their own default content type
+        if matches!(body, Body::Json(_)) && !header.contains_key(CONTENT_TYPE) {
+            header.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         }
+        let body = body.with_overrides(&args.body)?.render(&mut header)?;
+
+        Ok((header, query, body))
     }
 
     pub fn new(
@@ -136,7 +203,7 @@ impl RequestProfile {
         url: Url,
         params: Option<serde_json::Value>,
         headers: HeaderMap,
-        body: Option<serde_json::Value>,
+        body: Option<Body>,
     ) -> Self {
         Self {
             method,
@@ -144,7 +211,30 @@ impl RequestProfile {
             params,
             headers,
             body,
+            auth: None,
+            sign: None,
+            captures: HashMap::new(),
+            expect: None,
+            transport: TransportOptions::default(),
+        }
+    }
+
+    /// Read the variables of the `captures:` block from a response.
+    pub fn capture(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Result<HashMap<String, String>> {
+        let mut vars = HashMap::new();
+        for (name, capture) in &self.captures {
+            let value = capture
+                .extract(status, headers, body)
+                .with_context(|| format!("failed to capture `{}` from {}", name, capture))?;
+            vars.insert(name.clone(), value);
         }
+
+        Ok(vars)
     }
 
     pub fn get_url(&self, args: &ExtraArgs) -> Result<String> {
@@ -172,12 +262,11 @@ impl ValidateConfig for RequestProfile {
         }
 
         if let Some(body) = self.body.as_ref() {
-            if !body.is_object() {
-                return Err(anyhow!(
-                    "body must be an object but got\n{}",
-                    serde_yaml::to_string(body)?
-                ));
-            }
+            body.validate()?;
+        }
+
+        if let Some(expect) = &self.expect {
+            expect.validate()?;
         }
 
         Ok(())
@@ -189,14 +278,7 @@ impl FromStr for RequestProfile {
 
     fn from_str(s: &str) -> Result<Self> {
         let mut url = Url::parse(s)?;
-
-        let qs = url.query_pairs();
-        let mut params = json!({});
-        for (k, v) in qs {
-            params[&*k] = v.parse()?;
-        }
-
-        url.set_query(None);
+        let params = take_query_params(&mut url);
 
         Ok(RequestProfile::new(
             Method::GET,
@@ -208,28 +290,51 @@ impl FromStr for RequestProfile {
     }
 }
 
+/// Remove the query string from the url and return it as a params object.
+/// Values are kept as JSON if they parse as such, as strings otherwise.
+pub(crate) fn take_query_params(url: &mut Url) -> serde_json::Value {
+    let mut params = json!({});
+    for (k, v) in url.query_pairs() {
+        params[&*k] = v
+            .parse()
+            .unwrap_or_else(|_| serde_json::Value::String(v.into_owned()));
+    }
+
+    url.set_query(None);
+    params
+}
+
 impl ResponseExt {
     pub fn into_inner(self) -> Response {
         self.0
     }
 
     pub async fn get_text(self, profile: &ResponseProfile) -> Result<String> {
-        let res = self.0;
-
-        let mut output = get_status_text(&res)?;
-        write!(
-            &mut output,
-            "{}",
-            get_header_text(&res, &profile.skip_headers)?
-        )?;
-
-        write!(
-            &mut output,
-            "{}",
-            get_body_text(res, &profile.skip_body).await?
-        )?;
+        Ok(self.into_text(profile).await?.to_string())
+    }
 
-        Ok(output)
+    /// Split the response into normalized status, header and body text.
+    pub async fn into_text(self, profile: &ResponseProfile) -> Result<ResponseText> {
+        let res = self.0;
+        let masks = &profile.mask;
+
+        let status = mask::mask_status(masks, get_status_text(&res)?)?;
+        let mut headers = res.headers().clone();
+        mask::mask_headers(masks, &mut headers)?;
+        let header_text = format_header_text(&headers, &profile.skip_headers)?;
+
+        let text = res.text().await?;
+        let body = match get_content_type(&headers).as_deref() {
+            Some("application/json") => filter_json(&text, &profile.skip_body, masks)?,
+            _ => text,
+        };
+        let body = mask::mask_body(masks, body)?;
+
+        Ok(ResponseText {
+            status,
+            headers: header_text,
+            body,
+        })
     }
 
     pub fn get_header_keys(&self) -> Vec<String> {
@@ -241,26 +346,58 @@ impl ResponseExt {
     }
 }
 
+impl fmt::Display for ResponseText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.status, self.headers, self.body)
+    }
+}
+
+/// Apply a `-e` override to the query or a JSON body. The key is a path such as `user.name`
+/// or `items[0].qty`, and `tags[]` appends to an array.
+pub(crate) fn apply_override(
+    target: &mut serde_json::Value,
+    key: &str,
+    value: &ArgValue,
+) -> Result<()> {
+    let value = match value {
+        ArgValue::Remove => {
+            key.parse::<JsonPath>()?.remove(target);
+            return Ok(());
+        }
+        ArgValue::Text(text) => serde_json::Value::String(text.clone()),
+        ArgValue::Json(value) => value.clone(),
+    };
+
+    match key.strip_suffix("[]") {
+        Some(path) => path.parse::<JsonPath>()?.push(target, value),
+        None => key.parse::<JsonPath>()?.set(target, value),
+    }
+}
+
 pub fn get_content_type(headers: &HeaderMap) -> Option<String> {
     headers
         .get(CONTENT_TYPE)
         .and_then(|v| v.to_str().unwrap().split(';').next().map(|s| s.to_string()))
 }
 
-fn filter_json(text: &str, skip_body: &[String]) -> Result<String> {
+fn filter_json(text: &str, skip_body: &[String], masks: &[Mask]) -> Result<String> {
     let mut json = serde_json::from_str::<serde_json::Value>(text)?;
 
-    if let serde_json::Value::Object(ref mut obj) = json {
-        for k in skip_body {
-            obj.remove(k);
-        }
-    }
+    let paths = skip_body
+        .iter()
+        .map(|path| path.parse())
+        .collect::<Result<Vec<JsonPath>>>()?;
+    JsonPath::remove_all(&paths, &mut json);
+    mask::mask_json(masks, &mut json)?;
 
     Ok(serde_json::to_string_pretty(&json)?)
 }
 
 pub fn get_header_text(res: &Response, skip_headers: &[String]) -> Result<String> {
-    let headers = res.headers();
+    format_header_text(res.headers(), skip_headers)
+}
+
+fn format_header_text(headers: &HeaderMap, skip_headers: &[String]) -> Result<String> {
     let mut output = String::new();
 
     for (k, v) in headers.iter() {
@@ -279,25 +416,39 @@ pub fn get_status_text(res: &Response) -> Result<String> {
 }
 
 pub async fn get_body_text(res: Response, skip_body: &[String]) -> Result<String> {
-    let content_type = get_content_type(&res.headers());
+    let headers = res.headers().clone();
     let text = res.text().await?;
 
-    match content_type.as_deref() {
-        Some("application/json") => filter_json(&text, skip_body),
+    format_body_text(&headers, text, skip_body)
+}
+
+/// Pretty print a JSON body without the skipped paths, other bodies are kept as is.
+pub fn format_body_text(headers: &HeaderMap, text: String, skip_body: &[String]) -> Result<String> {
+    match get_content_type(headers).as_deref() {
+        Some("application/json") => filter_json(&text, skip_body, &[]),
         _ => Ok(text),
     }
 }
 
+fn empty_body(body: &Option<Body>) -> bool {
+    match body {
+        Some(Body::Json(value)) => empty_json_value(&Some(value.clone())),
+        Some(_) => false,
+        None => true,
+    }
+}
+
 fn empty_json_value(v: &Option<serde_json::Value>) -> bool {
-    v.as_ref().map_or(true, |v| {
-        if v.is_object() {
-            if let Some(obj) = v.as_object() {
-                return obj.is_empty();
-            }
+    let Some(v) = v else {
+        return true;
+    };
+    if v.is_object() {
+        if let Some(obj) = v.as_object() {
+            return obj.is_empty();
         }
+    }
 
-        true
-    })
+    true
 }
 
 #[cfg(test)]
@@ -305,6 +456,7 @@ mod tests {
     use reqwest::{header, StatusCode};
 
     use super::*;
+    use crate::cli::parse_key_value;
 
     #[tokio::test]
     async fn request_profile_send_should_work() {
@@ -331,13 +483,38 @@ mod tests {
             json!({"id":1, "title": "todo"}),
         );
         let args =
-            ExtraArgs::new_with_query(vec![("a".into(), "1".into()), ("b".into(), "2".into())]);
+            ExtraArgs::new_with_query(vec![("a".into(), text("1")), ("b".into(), text("2"))]);
 
         let res = get_response(server, "/todo?a=1&b=2", &args).await;
 
         assert_eq!(res.into_inner().status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn request_profile_send_without_body_should_send_no_body() {
+        let mut server = mockito::Server::new_async().await;
+        let empty = server
+            .mock("GET", "/todo")
+            .match_header("content-type", mockito::Matcher::Missing)
+            .match_body("")
+            .create_async()
+            .await;
+        let json = server
+            .mock("GET", "/todo")
+            .match_header("content-type", "application/json")
+            .match_body(r#"{"a":"1"}"#)
+            .create_async()
+            .await;
+        let profile = get_profile(&server.url(), "/todo");
+        let client = HttpClient::default();
+
+        profile.send(&client, &Default::default()).await.unwrap();
+        empty.assert_async().await;
+
+        profile.send(&client, &extra_args(&["@a=1"])).await.unwrap();
+        json.assert_async().await;
+    }
+
     #[test]
     fn request_profile_get_url_should_work() {
         let profile = get_profile("http://localhost:8080", "/todo?c=3&d=4");
@@ -353,7 +530,7 @@ mod tests {
         let profile = get_profile("http://localhost:8080", "/todo?a=1&b=2");
 
         let args =
-            ExtraArgs::new_with_query(vec![("b".into(), "2".into()), ("a".into(), "1".into())]);
+            ExtraArgs::new_with_query(vec![("b".into(), text("2")), ("a".into(), text("1"))]);
 
         assert_eq!(
             profile.get_url(&args).unwrap(),
@@ -361,6 +538,160 @@ mod tests {
         );
     }
 
+    #[test]
+    fn request_profile_deep_overrides_should_work() {
+        let mut profile = get_profile("http://localhost:8080", "/todo?a=1&b=2");
+        profile.body = Some(Body::Json(json!({
+            "user": {"name": "misky"},
+            "items": [{"qty": 1}],
+            "tags": ["a"],
+            "draft": true
+        })));
+        let args = extra_args(&[
+            "filter.done:=true",
+            "b!",
+            "@user.address.city=Paris",
+            "@items[0].qty:=3",
+            "@items[1].qty:=4",
+            "@tags[]=b",
+            "@-draft",
+        ]);
+
+        let (_, query, body) = profile.generate(&args).unwrap();
+        assert_eq!(query, json!({"a": 1, "filter": {"done": true}}));
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "user": {"name": "misky", "address": {"city": "Paris"}},
+                "items": [{"qty": 3}, {"qty": 4}],
+                "tags": ["a", "b"]
+            })
+        );
+
+        let args = extra_args(&["@user.name.first=m"]);
+        assert_eq!(
+            profile.generate(&args).unwrap_err().to_string(),
+            "cannot set `$.user.name.first`: `$.user.name` is a string"
+        );
+    }
+
+    #[test]
+    fn from_yaml_should_interpolate_placeholders() {
+        std::env::set_var("XDIFF_TEST_TOKEN", "secret");
+
+        let config = RequestConfig::from_yaml(
+            r#"
+vars:
+  host: localhost:8080
+  id: 1
+todo:
+  url: http://{{host}}/todos/{{id}}
+  params:
+    env: ${XDIFF_TEST_ENV:-dev}
+  headers:
+    authorization: Bearer ${XDIFF_TEST_TOKEN}
+"#,
+        )
+        .unwrap();
+
+        let profile = config.get_profile("todo").unwrap();
+        assert_eq!(profile.url.as_str(), "http://localhost:8080/todos/1");
+        assert_eq!(profile.params, Some(json!({"env": "dev"})));
+        assert_eq!(profile.headers["authorization"], "Bearer secret");
+        assert_eq!(config.vars["id"], "1");
+    }
+
+    #[tokio::test]
+    async fn flow_steps_should_use_captured_vars() {
+        let mut server = mockito::Server::new_async().await;
+        let _create = server
+            .mock("POST", "/todos")
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_header("x-request-id", "r1")
+            .with_body(r#"{"data": {"id": 7}}"#)
+            .create_async()
+            .await;
+        let get = server
+            .mock("GET", "/todos/7?trace=r1")
+            .with_body("{}")
+            .create_async()
+            .await;
+
+        let config = RequestConfig::from_yaml(&format!(
+            r#"
+vars:
+  host: {}
+create:
+  method: POST
+  url: "{{{{host}}}}/todos"
+  captures:
+    id: $.data.id
+    trace: header:x-request-id
+get:
+  url: "{{{{host}}}}/todos/{{{{id}}}}"
+  params:
+    trace: "{{{{trace}}}}"
+flows:
+  todo: [create, get]
+"#,
+            server.url()
+        ))
+        .unwrap();
+        assert_eq!(config.get_flow("todo").unwrap(), ["create", "get"]);
+        assert!(config.resolve_profile("get", &HashMap::new()).is_err());
+
+        let client = HttpClient::default();
+        let create = config.get_profile("create").unwrap();
+        let res = create.send(&client, &Default::default()).await.unwrap();
+        let res = res.into_inner();
+        let (status, headers) = (res.status(), res.headers().clone());
+        let body = get_body_text(res, &[]).await.unwrap();
+        let vars = create.capture(status, &headers, &body).unwrap();
+        assert_eq!(vars["id"], "7");
+
+        let get_profile = config.resolve_profile("get", &vars).unwrap();
+        assert_eq!(get_profile.url.path(), "/todos/7");
+        get_profile
+            .send(&client, &Default::default())
+            .await
+            .unwrap();
+        get.assert_async().await;
+
+        let config = RequestConfig::from_yaml("a:\n  url: http://a.com/%7B%7Bx%7D%7D").unwrap();
+        let profile = config.resolve_profile("a", &vars).unwrap();
+        assert_eq!(profile.url.path(), "/%7B%7Bx%7D%7D");
+
+        let err =
+            RequestConfig::from_yaml("a:\n  url: http://a.com\nflows:\n  f: [a, b]").unwrap_err();
+        assert_eq!(err.to_string(), "flow f refers to unknown profile b");
+    }
+
+    #[test]
+    fn tagged_bodies_should_load_from_config() {
+        let config = RequestConfig::from_yaml(
+            r#"
+vars:
+  name: misky
+note:
+  method: POST
+  url: http://a.com/notes
+  timeout: 3s
+  body: !raw "hello {{name}}"
+"#,
+        )
+        .unwrap();
+        let profile = config.get_profile("note").unwrap();
+        assert_eq!(profile.body, Some(Body::Raw("hello misky".into())));
+
+        let profile = config.resolve_profile("note", &HashMap::new()).unwrap();
+        assert_eq!(profile.body, Some(Body::Raw("hello misky".into())));
+        assert_eq!(
+            profile.transport.timeout,
+            Some(std::time::Duration::from_secs(3))
+        );
+    }
+
     #[test]
     fn test_get_content_type() {
         let mut headers = HeaderMap::new();
@@ -411,6 +742,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn filter_json_should_skip_nested_paths() {
+        let text = json!({
+            "id": 1,
+            "data": {"items": [{"id": 1, "updated_at": "x"}, {"id": 2, "updated_at": "y"}]}
+        })
+        .to_string();
+
+        assert_eq!(
+            filter_json(
+                &text,
+                &["id".into(), "data.items[*].updated_at".into()],
+                &[]
+            )
+            .unwrap(),
+            serde_json::to_string_pretty(&json!({"data": {"items": [{"id": 1}, {"id": 2}]}}))
+                .unwrap()
+        );
+
+        let text = json!([{"id": 1, "title": "a"}]).to_string();
+
+        assert_eq!(
+            filter_json(&text, &["[*].id".into()], &[]).unwrap(),
+            serde_json::to_string_pretty(&json!([{"title": "a"}])).unwrap()
+        );
+    }
+
     #[tokio::test]
     async fn get_body_text_should_work() {
         let mut server_guard = mockito::Server::new();
@@ -424,6 +782,43 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn into_text_should_apply_masks() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/todo")
+            .with_header("content-type", "application/json")
+            .with_header("etag", "W/\"9fceb02d0ae598e95dc970b74767f193\"")
+            .with_body(r#"{"id": 1, "at": "2023-04-05T06:07:08Z", "note": "req-42 done"}"#)
+            .create_async()
+            .await;
+        let profile: ResponseProfile = serde_yaml::from_str(
+            r#"
+skip_headers: [date, content-length, connection]
+mask:
+  - in: header:etag
+    preset: hex
+  - in: $.at
+    preset: iso8601
+  - pattern: req-\d+
+    replace: req-<id>
+"#,
+        )
+        .unwrap();
+
+        let res = get_response(server, "/todo", &Default::default()).await;
+        let text = res.into_text(&profile).await.unwrap();
+
+        assert!(text.headers.contains("etag:\"W/\\\"<hex>\\\"\""));
+        assert_eq!(
+            text.body,
+            serde_json::to_string_pretty(
+                &json!({"id": 1, "at": "<timestamp>", "note": "req-<id> done"})
+            )
+            .unwrap()
+        );
+    }
+
     fn mock_for_url(
         server_guard: &mut mockito::ServerGuard,
         path_and_query: &str,
@@ -442,9 +837,9 @@ mod tests {
         path_and_query: &str,
         args: &ExtraArgs,
     ) -> ResponseExt {
-        let profile = get_profile(&server.url(), &path_and_query);
+        let profile = get_profile(&server.url(), path_and_query);
 
-        profile.send(&args).await.unwrap()
+        profile.send(&HttpClient::default(), args).await.unwrap()
     }
 
     fn get_profile(url: &str, path_and_query: &str) -> RequestProfile {
@@ -454,6 +849,15 @@ mod tests {
         RequestProfile::from_str(url.as_str()).unwrap()
     }
 
+    fn text(value: &str) -> ArgValue {
+        ArgValue::Text(value.into())
+    }
+
+    fn extra_args(args: &[&str]) -> ExtraArgs {
+        let args = args.iter().map(|arg| parse_key_value(arg).unwrap());
+        ExtraArgs::from(args.collect::<Vec<_>>())
+    }
+
     fn get_url(url: &str, path: &str) -> Url {
         Url::parse(&format!("{}{}", url, path)).unwrap()
     }
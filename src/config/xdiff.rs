@@ -1,17 +1,29 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
 
-use crate::diff_text;
-use crate::ExtraArgs;
-use anyhow::{Context, Result};
+use crate::diff::{apply_rules, CompareRule, JsonDiffOptions};
+use crate::{ClientOptions, DiffResult, ExtraArgs, HttpClient, JsonPath, ResponseText};
+use anyhow::{anyhow, Context, Result};
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::Semaphore;
 
 use super::LoadConfig;
 use super::ValidateConfig;
-use super::{is_default, RequestProfile};
+use super::{is_default, Mask, RequestProfile};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DiffConfig {
+    /// Variables referenced as `{{name}}` in the profiles.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub vars: HashMap<String, String>,
+
+    /// Transport settings shared by all the requests.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub client: ClientOptions,
+
     #[serde(flatten)]
     pub profiles: HashMap<String, DiffProfile>,
 }
@@ -30,23 +42,135 @@ impl ValidateConfig for DiffConfig {
 
 impl DiffConfig {
     pub fn new(profiles: HashMap<String, DiffProfile>) -> Self {
-        Self { profiles }
+        Self {
+            vars: HashMap::new(),
+            client: ClientOptions::default(),
+            profiles,
+        }
     }
 
     pub fn get_profile(&self, name: &str) -> Option<&DiffProfile> {
         self.profiles.get(name)
     }
+
+    /// Sorted names of the profiles carrying any of the given tags, or of all
+    /// profiles if no tag is given.
+    pub fn profile_names(&self, tags: &[String]) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .profiles
+            .iter()
+            .filter(|(_, profile)| tags.is_empty() || tags.iter().any(|t| profile.has_tag(t)))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    /// Diff the given profiles concurrently, running at most `parallel` of
+    /// them at the same time. Results are returned in the order of `names`.
+    pub async fn diff_profiles(
+        &self,
+        client: &HttpClient,
+        names: &[String],
+        args: &ExtraArgs,
+        parallel: usize,
+    ) -> Vec<(String, Result<DiffResult>)> {
+        self.for_each_profile(names, parallel, |_, profile| {
+            let client = client.clone();
+            let args = args.clone();
+            async move { profile.diff(&client, args).await }
+        })
+        .await
+    }
+
+    /// Run `f` on the given profiles concurrently, at most `parallel` at a time.
+    /// Results are returned in the order of `names`.
+    pub async fn for_each_profile<T, F, Fut>(
+        &self,
+        names: &[String],
+        parallel: usize,
+        f: F,
+    ) -> Vec<(String, Result<T>)>
+    where
+        T: Send + 'static,
+        F: Fn(String, DiffProfile) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let semaphore = Arc::new(Semaphore::new(parallel.max(1)));
+
+        let handles: Vec<_> = names
+            .iter()
+            .map(|name| {
+                let task = self
+                    .get_profile(name)
+                    .cloned()
+                    .map(|profile| f(name.clone(), profile));
+                let name = name.clone();
+                let semaphore = semaphore.clone();
+
+                tokio::spawn(async move {
+                    let task = task.ok_or_else(|| anyhow!("Profile {} not found", name))?;
+                    let _permit = semaphore.acquire_owned().await?;
+                    task.await
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(names.len());
+        for (name, handle) in names.iter().zip(handles) {
+            let result = handle.await.map_err(anyhow::Error::from).and_then(|r| r);
+            results.push((name.clone(), result));
+        }
+
+        results
+    }
 }
 
 impl DiffProfile {
-    pub async fn diff(&self, args: ExtraArgs) -> Result<String> {
-        let res1 = self.req1.send(&args).await?;
-        let res2 = self.req2.send(&args).await?;
+    pub async fn diff(&self, client: &HttpClient, args: ExtraArgs) -> Result<DiffResult> {
+        let res1 = self
+            .req1
+            .send(client, &args)
+            .await
+            .context("failed to send req1")?;
+        let res2 = self
+            .req2
+            .send(client, &args)
+            .await
+            .context("failed to send req2")?;
+
+        let mut text1 = res1.into_text(&self.res).await?;
+        let mut text2 = res2.into_text(&self.res).await?;
+        self.res.apply_compare_rules(&mut text1, &mut text2)?;
+
+        Ok(DiffResult::new(&text1, &text2, self.res.json_options()))
+    }
+
+    /// Fetch the normalized response of `req1`, as recorded in snapshots.
+    pub async fn snapshot(&self, client: &HttpClient, args: &ExtraArgs) -> Result<ResponseText> {
+        let res = self.req1.send(client, args).await?;
+        res.into_text(&self.res).await
+    }
 
-        let text1 = res1.get_text(&self.res).await?;
-        let text2 = res2.get_text(&self.res).await?;
+    /// Diff a recorded snapshot against the live response of `req1`.
+    pub async fn diff_snapshot(
+        &self,
+        client: &HttpClient,
+        snapshot: &ResponseText,
+        args: &ExtraArgs,
+    ) -> Result<(DiffResult, ResponseText)> {
+        let live = self.snapshot(client, args).await?;
+        // the live response is returned as fetched, to be recorded as is
+        let (mut expected, mut compared) = (snapshot.clone(), live.clone());
+        self.res.apply_compare_rules(&mut expected, &mut compared)?;
+        let result = DiffResult::new(&expected, &compared, self.res.json_options());
 
-        diff_text(&text1, &text2)
+        Ok((result, live))
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
     }
 }
 
@@ -54,6 +178,7 @@ impl ValidateConfig for DiffProfile {
     fn validate(&self) -> Result<()> {
         self.req1.validate().context("req1 failed to validate")?;
         self.req2.validate().context("req2 failed to validate")?;
+        self.res.validate().context("res failed to validate")?;
 
         Ok(())
     }
@@ -66,21 +191,82 @@ pub struct DiffProfile {
 
     #[serde(skip_serializing_if = "is_default", default)]
     pub res: ResponseProfile,
+
+    /// Tags used to select a group of profiles with `xdiff run --tag`.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub tags: Vec<String>,
 }
 
 impl DiffProfile {
     pub fn new(req1: RequestProfile, req2: RequestProfile, res: ResponseProfile) -> Self {
-        Self { req1, req2, res }
+        Self {
+            req1,
+            req2,
+            res,
+            tags: vec![],
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct ResponseProfile {
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub skip_headers: Vec<String>,
 
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub skip_body: Vec<String>,
+
+    /// How response bodies are compared.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub diff_mode: DiffMode,
+
+    /// In `json` mode, compare arrays regardless of the order of their elements.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub ignore_array_order: bool,
+
+    /// In `json` mode, compare numbers by value so that `1.0` equals `1`.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub numeric_compare: bool,
+
+    /// Regex replacements normalizing volatile values before the diff.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub mask: Vec<Mask>,
+
+    /// Comparison rules of JSON body values, by path, such as a numeric tolerance.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub compare: BTreeMap<String, CompareRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffMode {
+    /// Line based diff of the (pretty printed) body.
+    #[default]
+    Text,
+
+    /// Structural diff of JSON bodies, reported by path.
+    Json,
+}
+
+impl ValidateConfig for ResponseProfile {
+    fn validate(&self) -> Result<()> {
+        for path in &self.skip_body {
+            path.parse::<JsonPath>()
+                .context(format!("invalid skip_body entry: {}", path))?;
+        }
+
+        for mask in &self.mask {
+            mask.validate()?;
+        }
+
+        for (path, rule) in &self.compare {
+            path.parse::<JsonPath>()
+                .and_then(|_| rule.validate())
+                .context(format!("invalid compare rule: {}", path))?;
+        }
+
+        Ok(())
+    }
 }
 
 impl ResponseProfile {
@@ -88,6 +274,218 @@ impl ResponseProfile {
         Self {
             skip_headers,
             skip_body,
+            ..Default::default()
+        }
+    }
+
+    /// Let the `compare:` rules settle the values of the second JSON body they consider
+    /// equal to the first one. Both bodies are then pretty printed, so that they only
+    /// differ by their values whatever their original layout.
+    pub fn apply_compare_rules(
+        &self,
+        old: &mut ResponseText,
+        new: &mut ResponseText,
+    ) -> Result<()> {
+        if self.compare.is_empty() {
+            return Ok(());
+        }
+
+        let rules = self
+            .compare
+            .iter()
+            .map(|(path, rule)| Ok((path.parse()?, rule.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        let (Ok(old_json), Ok(mut new_json)) = (
+            serde_json::from_str::<Value>(&old.body),
+            serde_json::from_str::<Value>(&new.body),
+        ) else {
+            return Ok(());
+        };
+
+        if apply_rules(&old_json, &mut new_json, &rules) {
+            old.body = serde_json::to_string_pretty(&old_json)?;
+            new.body = serde_json::to_string_pretty(&new_json)?;
+        }
+
+        Ok(())
+    }
+
+    /// Options of the structural comparator, if bodies are compared as JSON.
+    pub fn json_options(&self) -> Option<JsonDiffOptions> {
+        match self.diff_mode {
+            DiffMode::Text => None,
+            DiffMode::Json => Some(JsonDiffOptions {
+                ignore_array_order: self.ignore_array_order,
+                numeric: self.numeric_compare,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names_should_filter_by_tag() {
+        let config = DiffConfig::from_yaml(
+            r#"
+b:
+  tags: [smoke]
+  req1:
+    url: http://localhost/b
+  req2:
+    url: http://localhost/b
+a:
+  tags: [smoke, slow]
+  req1:
+    url: http://localhost/a
+  req2:
+    url: http://localhost/a
+c:
+  req1:
+    url: http://localhost/c
+  req2:
+    url: http://localhost/c
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.profile_names(&[]), vec!["a", "b", "c"]);
+        assert_eq!(config.profile_names(&["smoke".into()]), vec!["a", "b"]);
+        assert_eq!(config.profile_names(&["slow".into()]), vec!["a"]);
+    }
+
+    #[test]
+    fn validate_should_reject_invalid_mask() {
+        let result = DiffConfig::from_yaml(
+            r#"
+todo:
+  req1:
+    url: http://localhost/a
+  req2:
+    url: http://localhost/b
+  res:
+    mask:
+      - in: header:etag
+"#,
+        );
+
+        assert_eq!(
+            format!("{:#}", result.unwrap_err()),
+            "failed to validate profile: todo: res failed to validate: mask in header:etag needs either a pattern or a preset"
+        );
+    }
+
+    #[test]
+    fn compare_rules_should_settle_equivalent_bodies() {
+        let config = DiffConfig::from_yaml(
+            r#"
+todo:
+  req1:
+    url: http://localhost/a
+  req2:
+    url: http://localhost/b
+  res:
+    compare:
+      $.total: {tolerance: 0.01}
+      $.status: {ignore_case: true}
+"#,
+        )
+        .unwrap();
+        let profile = config.get_profile("todo").unwrap();
+        let text = |body: &str| ResponseText {
+            status: "HTTP/1.1 200 OK".into(),
+            headers: String::new(),
+            body: body.into(),
+        };
+
+        let mut old = text("{\n  \"status\": \"OK\",\n  \"total\": 1.5\n}");
+        let mut new = text("{\n  \"status\": \"ok\",\n  \"total\": 1.505\n}");
+        profile.res.apply_compare_rules(&mut old, &mut new).unwrap();
+        assert_eq!(new.body, old.body);
+
+        let mut new = text("{\n  \"status\": \"ko\",\n  \"total\": 1.505\n}");
+        profile.res.apply_compare_rules(&mut old, &mut new).unwrap();
+        assert_eq!(new.body, "{\n  \"status\": \"ko\",\n  \"total\": 1.5\n}");
+    }
+
+    #[tokio::test]
+    async fn compare_rules_should_settle_compact_json_bodies() {
+        let mut server = mockito::Server::new_async().await;
+        for (path, body) in [
+            ("/a", r#"{"status":"OK","total":1.5}"#),
+            ("/b", r#"{"status":"ok","total":1.505}"#),
+        ] {
+            server
+                .mock("GET", path)
+                .with_header("content-type", "application/problem+json")
+                .with_body(body)
+                .create_async()
+                .await;
         }
+
+        let config = DiffConfig::from_yaml(&format!(
+            r#"
+todo:
+  req1:
+    url: {0}/a
+  req2:
+    url: {0}/b
+  res:
+    skip_headers: [date, content-length]
+    compare:
+      $.total: {{tolerance: 0.01}}
+      $.status: {{ignore_case: true}}
+"#,
+            server.url()
+        ))
+        .unwrap();
+        let profile = config.get_profile("todo").unwrap();
+
+        let result = profile
+            .diff(&HttpClient::default(), ExtraArgs::default())
+            .await
+            .unwrap();
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn validate_should_reject_invalid_compare_rule() {
+        let result = DiffConfig::from_yaml(
+            r#"
+todo:
+  req1:
+    url: http://localhost/a
+  req2:
+    url: http://localhost/b
+  res:
+    compare:
+      $.total: {tolerance: -1}
+"#,
+        );
+
+        assert_eq!(
+            format!("{:#}", result.unwrap_err()),
+            "failed to validate profile: todo: res failed to validate: invalid compare rule: $.total: tolerance must be a non-negative number, got -1"
+        );
+    }
+
+    #[test]
+    fn validate_should_reject_invalid_skip_body() {
+        let result = DiffConfig::from_yaml(
+            r#"
+todo:
+  req1:
+    url: http://localhost/a
+  req2:
+    url: http://localhost/b
+  res:
+    skip_body:
+      - data.items[
+"#,
+        );
+
+        assert!(result.is_err());
     }
 }
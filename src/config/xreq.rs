@@ -1,17 +1,52 @@
-use crate::RequestProfile;
-use anyhow::{Context, Result};
+use crate::{ClientOptions, RequestProfile};
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use super::{LoadConfig, ValidateConfig};
+use super::{body, from_yaml_value, interpolate, is_default, LoadConfig, ValidateConfig};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RequestConfig {
+    /// Variables referenced as `{{name}}` in the profiles.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub vars: HashMap<String, String>,
+
+    /// Transport settings shared by all the requests.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub client: ClientOptions,
+
+    /// Sequences of profiles run by `xreq flow`, passing captured variables along.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub flows: HashMap<String, Vec<String>>,
+
     #[serde(flatten)]
     pub profiles: HashMap<String, RequestProfile>,
+
+    /// Profiles as written in the file, resolved again once captured variables are known.
+    #[serde(skip)]
+    raw: HashMap<String, serde_yaml::Value>,
+}
+
+impl LoadConfig for RequestConfig {
+    fn from_yaml(content: &str) -> Result<Self> {
+        let value: serde_yaml::Value = serde_yaml::from_str(content)?;
+        let raw = match &value {
+            serde_yaml::Value::Mapping(root) => root
+                .iter()
+                .filter_map(|(k, v)| Some((k.as_str()?.to_string(), v.clone())))
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        let mut config: Self = from_yaml_value(value)?;
+        config.raw = raw;
+        config
+            .raw
+            .retain(|name, _| config.profiles.contains_key(name));
+        Ok(config)
+    }
 }
 
-impl LoadConfig for RequestConfig {}
 impl ValidateConfig for RequestConfig {
     fn validate(&self) -> Result<()> {
         for (name, profile) in &self.profiles {
@@ -19,16 +54,59 @@ impl ValidateConfig for RequestConfig {
                 .validate()
                 .context(format!("failed to validate profile: {}", name))?;
         }
+
+        for (name, steps) in &self.flows {
+            if steps.is_empty() {
+                return Err(anyhow!("flow {} has no steps", name));
+            }
+            if let Some(step) = steps.iter().find(|s| !self.profiles.contains_key(*s)) {
+                return Err(anyhow!("flow {} refers to unknown profile {}", name, step));
+            }
+        }
         Ok(())
     }
 }
 
 impl RequestConfig {
     pub fn new(profiles: HashMap<String, RequestProfile>) -> Self {
-        Self { profiles }
+        Self {
+            vars: HashMap::new(),
+            client: ClientOptions::default(),
+            flows: HashMap::new(),
+            profiles,
+            raw: HashMap::new(),
+        }
     }
 
     pub fn get_profile(&self, name: &str) -> Option<&RequestProfile> {
         self.profiles.get(name)
     }
+
+    /// The profile with its placeholders substituted from the config vars and the
+    /// variables captured by the previous steps of a flow.
+    pub fn resolve_profile(
+        &self,
+        name: &str,
+        captured: &HashMap<String, String>,
+    ) -> Result<RequestProfile> {
+        let Some(raw) = self.raw.get(name) else {
+            // built in code rather than loaded, there is nothing left to substitute
+            return self
+                .get_profile(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Profile {} not found", name));
+        };
+
+        let mut value = raw.clone();
+        interpolate::substitute_profile(&mut value, &self.vars, captured)?;
+        body::untag_profile(&mut value);
+        let profile: RequestProfile = serde_yaml::from_value(value)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// The profile names of the steps of a flow.
+    pub fn get_flow(&self, name: &str) -> Option<&[String]> {
+        self.flows.get(name).map(Vec::as_slice)
+    }
 }
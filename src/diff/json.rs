@@ -0,0 +1,273 @@
+use serde::Serialize;
+use serde_json::Value;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+use crate::json_path::{JsonPath, Segment};
+
+/// Options of the structural JSON comparator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonDiffOptions {
+    /// Compare arrays as multisets instead of ordered lists.
+    pub ignore_array_order: bool,
+
+    /// Compare numbers by value, so that `1.0` equals `1`.
+    pub numeric: bool,
+}
+
+/// A value added, removed or changed at a path of the document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JsonChange {
+    pub path: JsonPath,
+    pub kind: JsonChangeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonChangeKind {
+    Added(Value),
+    Removed(Value),
+    Changed { old: Value, new: Value },
+}
+
+/// Walk both documents and report the values that differ, by path.
+pub fn diff_json(old: &Value, new: &Value, options: &JsonDiffOptions) -> Vec<JsonChange> {
+    let mut changes = vec![];
+    diff_value(&JsonPath::default(), old, new, options, &mut changes);
+    changes
+}
+
+fn diff_value(
+    path: &JsonPath,
+    old: &Value,
+    new: &Value,
+    options: &JsonDiffOptions,
+    changes: &mut Vec<JsonChange>,
+) {
+    match (old, new) {
+        (Value::Object(o), Value::Object(n)) => {
+            for (key, old_value) in o {
+                let path = path.join(Segment::Key(key.clone()));
+                match n.get(key) {
+                    Some(new_value) => diff_value(&path, old_value, new_value, options, changes),
+                    None => changes.push(JsonChange::removed(path, old_value)),
+                }
+            }
+
+            for (key, new_value) in n {
+                if !o.contains_key(key) {
+                    let path = path.join(Segment::Key(key.clone()));
+                    changes.push(JsonChange::added(path, new_value));
+                }
+            }
+        }
+        (Value::Array(o), Value::Array(n)) if options.ignore_array_order => {
+            diff_unordered(path, o, n, options, changes)
+        }
+        (Value::Array(o), Value::Array(n)) => diff_ordered(path, o, n, options, changes),
+        _ if values_equal(old, new, options) => {}
+        _ => changes.push(JsonChange {
+            path: path.clone(),
+            kind: JsonChangeKind::Changed {
+                old: old.clone(),
+                new: new.clone(),
+            },
+        }),
+    }
+}
+
+/// Align the elements of both arrays so that an insertion only reports the new element.
+fn diff_ordered(
+    path: &JsonPath,
+    old: &[Value],
+    new: &[Value],
+    options: &JsonDiffOptions,
+    changes: &mut Vec<JsonChange>,
+) {
+    let old_keys: Vec<String> = old.iter().map(|v| canonical(v, options)).collect();
+    let new_keys: Vec<String> = new.iter().map(|v| canonical(v, options)).collect();
+
+    for op in capture_diff_slices(Algorithm::Myers, &old_keys, &new_keys) {
+        match op {
+            DiffOp::Equal { .. } => {}
+            DiffOp::Delete {
+                old_index, old_len, ..
+            } => {
+                for (i, value) in old.iter().enumerate().skip(old_index).take(old_len) {
+                    changes.push(JsonChange::removed(path.join(Segment::Index(i)), value));
+                }
+            }
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => {
+                for (i, value) in new.iter().enumerate().skip(new_index).take(new_len) {
+                    changes.push(JsonChange::added(path.join(Segment::Index(i)), value));
+                }
+            }
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                let common = old_len.min(new_len);
+                for i in 0..common {
+                    diff_value(
+                        &path.join(Segment::Index(new_index + i)),
+                        &old[old_index + i],
+                        &new[new_index + i],
+                        options,
+                        changes,
+                    );
+                }
+                let removed = old.iter().enumerate().skip(old_index + common);
+                for (i, value) in removed.take(old_len - common) {
+                    changes.push(JsonChange::removed(path.join(Segment::Index(i)), value));
+                }
+                let added = new.iter().enumerate().skip(new_index + common);
+                for (i, value) in added.take(new_len - common) {
+                    changes.push(JsonChange::added(path.join(Segment::Index(i)), value));
+                }
+            }
+        }
+    }
+}
+
+/// Match equal elements regardless of their position; the rest is added or removed.
+fn diff_unordered(
+    path: &JsonPath,
+    old: &[Value],
+    new: &[Value],
+    options: &JsonDiffOptions,
+    changes: &mut Vec<JsonChange>,
+) {
+    let mut unmatched: Vec<(usize, String)> = new
+        .iter()
+        .map(|v| canonical(v, options))
+        .enumerate()
+        .collect();
+
+    for (i, value) in old.iter().enumerate() {
+        let key = canonical(value, options);
+        match unmatched.iter().position(|(_, k)| *k == key) {
+            Some(pos) => {
+                unmatched.remove(pos);
+            }
+            None => changes.push(JsonChange::removed(path.join(Segment::Index(i)), value)),
+        }
+    }
+
+    for (i, _) in unmatched {
+        changes.push(JsonChange::added(path.join(Segment::Index(i)), &new[i]));
+    }
+}
+
+fn values_equal(old: &Value, new: &Value, options: &JsonDiffOptions) -> bool {
+    match (old, new) {
+        (Value::Number(o), Value::Number(n)) if options.numeric => o.as_f64() == n.as_f64(),
+        _ => old == new,
+    }
+}
+
+/// A string representation under which equal values (per the options) are identical.
+fn canonical(value: &Value, options: &JsonDiffOptions) -> String {
+    match value {
+        Value::Number(n) if options.numeric => format!("{:?}", n.as_f64().unwrap_or(f64::NAN)),
+        Value::Array(arr) => {
+            let mut items: Vec<String> = arr.iter().map(|v| canonical(v, options)).collect();
+            if options.ignore_array_order {
+                items.sort();
+            }
+            format!("[{}]", items.join(","))
+        }
+        Value::Object(obj) => {
+            let mut items: Vec<String> = obj
+                .iter()
+                .map(|(k, v)| format!("{:?}:{}", k, canonical(v, options)))
+                .collect();
+            items.sort();
+            format!("{{{}}}", items.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+impl JsonChange {
+    fn added(path: JsonPath, value: &Value) -> Self {
+        Self {
+            path,
+            kind: JsonChangeKind::Added(value.clone()),
+        }
+    }
+
+    fn removed(path: JsonPath, value: &Value) -> Self {
+        Self {
+            path,
+            kind: JsonChangeKind::Removed(value.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn paths(changes: &[JsonChange]) -> Vec<String> {
+        changes.iter().map(|c| c.path.to_string()).collect()
+    }
+
+    #[test]
+    fn diff_json_should_ignore_key_order() {
+        let old = json!({"a": 1, "b": {"c": 2, "d": 3}});
+        let new: Value = serde_json::from_str(r#"{"b": {"d": 3, "c": 2}, "a": 1}"#).unwrap();
+
+        assert!(diff_json(&old, &new, &Default::default()).is_empty());
+    }
+
+    #[test]
+    fn diff_json_should_report_changes_by_path() {
+        let old = json!({"a": 1, "b": {"c": 2}, "d": true});
+        let new = json!({"a": 2, "b": {"c": 2, "e": null}});
+
+        let changes = diff_json(&old, &new, &Default::default());
+
+        assert_eq!(paths(&changes), vec!["$.a", "$.b.e", "$.d"]);
+        assert_eq!(
+            changes[0].kind,
+            JsonChangeKind::Changed {
+                old: json!(1),
+                new: json!(2)
+            }
+        );
+        assert_eq!(changes[1].kind, JsonChangeKind::Added(json!(null)));
+        assert_eq!(changes[2].kind, JsonChangeKind::Removed(json!(true)));
+    }
+
+    #[test]
+    fn diff_json_should_only_report_inserted_array_element() {
+        let old = json!({"items": [{"id": 1}, {"id": 2}, {"id": 3}]});
+        let new = json!({"items": [{"id": 1}, {"id": 9}, {"id": 2}, {"id": 3}]});
+
+        let changes = diff_json(&old, &new, &Default::default());
+
+        assert_eq!(paths(&changes), vec!["$.items[1]"]);
+        assert_eq!(changes[0].kind, JsonChangeKind::Added(json!({"id": 9})));
+    }
+
+    #[test]
+    fn diff_json_options_should_work() {
+        let old = json!({"items": [1, 2, 3], "price": 1.0});
+        let new = json!({"items": [3, 1, 2], "price": 1});
+
+        assert_eq!(
+            paths(&diff_json(&old, &new, &Default::default())),
+            vec!["$.items[0]", "$.items[2]", "$.price"]
+        );
+
+        let options = JsonDiffOptions {
+            ignore_array_order: true,
+            numeric: true,
+        };
+        assert!(diff_json(&old, &new, &options).is_empty());
+    }
+}
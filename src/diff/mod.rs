@@ -0,0 +1,419 @@
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::fmt;
+
+use crate::ResponseText;
+
+mod json;
+mod patch;
+mod rules;
+mod terminal;
+mod unified;
+
+pub use json::{diff_json, JsonChange, JsonChangeKind, JsonDiffOptions};
+pub use patch::{json_patch, PatchOperation};
+pub use rules::{apply_rules, CompareOnly, CompareRule};
+pub use terminal::{diff_text, render_json_changes, render_section, render_terminal};
+pub use unified::render_unified;
+
+/// Number of unchanged lines kept around each change when grouping hunks.
+const CONTEXT_LINES: usize = 3;
+
+/// The structured difference between two responses.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DiffResult {
+    pub status: DiffSection,
+    pub headers: DiffSection,
+    pub body: BodyDiff,
+
+    /// The normalized responses that were diffed.
+    #[serde(skip)]
+    pub text1: ResponseText,
+    #[serde(skip)]
+    pub text2: ResponseText,
+}
+
+/// Difference of the response bodies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyDiff {
+    /// Line based difference of the body text.
+    Text(DiffSection),
+
+    /// Structural difference of two JSON bodies.
+    Json(Vec<JsonChange>),
+}
+
+/// Line based difference of one part of the response.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DiffSection {
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// A group of changes surrounded by a few lines of context.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DiffHunk {
+    pub changes: Vec<DiffChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffChange {
+    pub kind: ChangeKind,
+
+    /// Zero based line index in the first text, if the line exists there.
+    pub old_index: Option<usize>,
+
+    /// Zero based line index in the second text, if the line exists there.
+    pub new_index: Option<usize>,
+
+    /// Content of the line, split by inline emphasis.
+    pub segments: Vec<DiffSegment>,
+
+    /// Whether the line is the last one and has no trailing newline.
+    pub missing_newline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffSegment {
+    /// Whether this part of the line differs from its counterpart.
+    pub emphasized: bool,
+    pub text: String,
+}
+
+/// Counts of changed lines, used to report a diff in one line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DiffSummary {
+    pub insertions: usize,
+    pub deletions: usize,
+
+    /// Names of the sections that differ.
+    pub sections: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+impl DiffResult {
+    /// Diff two responses. Bodies are compared structurally if `json` is given
+    /// and both of them are valid JSON, line by line otherwise.
+    pub fn new(text1: &ResponseText, text2: &ResponseText, json: Option<JsonDiffOptions>) -> Self {
+        Self {
+            status: DiffSection::from_lines(&text1.status, &text2.status),
+            headers: DiffSection::from_lines(&text1.headers, &text2.headers),
+            body: BodyDiff::new(&text1.body, &text2.body, json),
+            text1: text1.clone(),
+            text2: text2.clone(),
+        }
+    }
+
+    /// Returns true if the two responses are identical.
+    pub fn is_empty(&self) -> bool {
+        self.status.is_empty() && self.headers.is_empty() && self.body.is_empty()
+    }
+
+    /// Number of inserted and deleted lines across all sections.
+    pub fn changed_lines(&self) -> usize {
+        self.status.changed_lines() + self.headers.changed_lines() + self.body.changed_lines()
+    }
+
+    pub fn summary(&self) -> DiffSummary {
+        let sections = [
+            ("status", self.status.is_empty()),
+            ("headers", self.headers.is_empty()),
+            ("body", self.body.is_empty()),
+        ];
+
+        DiffSummary {
+            insertions: self.status.count(ChangeKind::Insert)
+                + self.headers.count(ChangeKind::Insert)
+                + self.body.count(ChangeKind::Insert),
+            deletions: self.status.count(ChangeKind::Delete)
+                + self.headers.count(ChangeKind::Delete)
+                + self.body.count(ChangeKind::Delete),
+            sections: sections
+                .iter()
+                .filter(|(_, empty)| !empty)
+                .map(|(name, _)| *name)
+                .collect(),
+        }
+    }
+}
+
+impl Default for BodyDiff {
+    fn default() -> Self {
+        Self::Text(DiffSection::default())
+    }
+}
+
+impl BodyDiff {
+    pub fn new(body1: &str, body2: &str, json: Option<JsonDiffOptions>) -> Self {
+        if let Some(options) = json {
+            if let (Ok(v1), Ok(v2)) = (serde_json::from_str(body1), serde_json::from_str(body2)) {
+                return Self::Json(diff_json(&v1, &v2, &options));
+            }
+        }
+
+        Self::Text(DiffSection::from_lines(body1, body2))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Text(section) => section.is_empty(),
+            Self::Json(changes) => changes.is_empty(),
+        }
+    }
+
+    pub fn changed_lines(&self) -> usize {
+        self.count(ChangeKind::Insert) + self.count(ChangeKind::Delete)
+    }
+
+    /// Number of lines of the given kind. A changed JSON value counts as one
+    /// deletion and one insertion.
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        match self {
+            Self::Text(section) => section.count(kind),
+            Self::Json(changes) => changes
+                .iter()
+                .filter(|c| {
+                    matches!(
+                        (&c.kind, kind),
+                        (JsonChangeKind::Added(_), ChangeKind::Insert)
+                            | (JsonChangeKind::Removed(_), ChangeKind::Delete)
+                            | (JsonChangeKind::Changed { .. }, ChangeKind::Insert)
+                            | (JsonChangeKind::Changed { .. }, ChangeKind::Delete)
+                    )
+                })
+                .count(),
+        }
+    }
+}
+
+impl DiffSection {
+    pub fn from_lines(text1: &str, text2: &str) -> Self {
+        let diff = TextDiff::from_lines(text1, text2);
+
+        let hunks = diff
+            .grouped_ops(CONTEXT_LINES)
+            .iter()
+            .map(|group| {
+                let changes = group
+                    .iter()
+                    .flat_map(|op| diff.iter_inline_changes(op))
+                    .map(|change| DiffChange {
+                        kind: change.tag().into(),
+                        old_index: change.old_index(),
+                        new_index: change.new_index(),
+                        segments: change
+                            .iter_strings_lossy()
+                            .map(|(emphasized, text)| DiffSegment {
+                                emphasized,
+                                text: text.into_owned(),
+                            })
+                            .collect(),
+                        missing_newline: change.missing_newline(),
+                    })
+                    .collect();
+
+                DiffHunk { changes }
+            })
+            .filter(|hunk| !hunk.is_empty())
+            .collect();
+
+        Self { hunks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+
+    pub fn changed_lines(&self) -> usize {
+        self.hunks.iter().map(|h| h.changed_lines()).sum()
+    }
+
+    /// Number of lines of the given kind across all hunks.
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.hunks
+            .iter()
+            .flat_map(|h| &h.changes)
+            .filter(|c| c.kind == kind)
+            .count()
+    }
+}
+
+impl DiffHunk {
+    /// Returns true if the hunk only holds context lines.
+    pub fn is_empty(&self) -> bool {
+        self.changed_lines() == 0
+    }
+
+    pub fn changed_lines(&self) -> usize {
+        self.changes
+            .iter()
+            .filter(|c| c.kind != ChangeKind::Equal)
+            .count()
+    }
+}
+
+impl DiffChange {
+    /// The full content of the line.
+    pub fn value(&self) -> String {
+        self.segments.iter().map(|s| s.text.as_str()).collect()
+    }
+}
+
+impl DiffSummary {
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+}
+
+impl fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+
+        write!(
+            f,
+            "{} {}(+), {} {}(-) in {}",
+            self.insertions,
+            plural(self.insertions, "insertion", "insertions"),
+            self.deletions,
+            plural(self.deletions, "deletion", "deletions"),
+            self.sections.join(", ")
+        )
+    }
+}
+
+fn plural(count: usize, one: &'static str, many: &'static str) -> &'static str {
+    if count == 1 {
+        one
+    } else {
+        many
+    }
+}
+
+impl From<ChangeTag> for ChangeKind {
+    fn from(tag: ChangeTag) -> Self {
+        match tag {
+            ChangeTag::Equal => Self::Equal,
+            ChangeTag::Delete => Self::Delete,
+            ChangeTag::Insert => Self::Insert,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_section_from_lines_should_work() {
+        let section = DiffSection::from_lines("a\nb\nc\n", "a\nx\nc\n");
+
+        assert_eq!(section.hunks.len(), 1);
+        assert_eq!(section.changed_lines(), 2);
+
+        let changes = &section.hunks[0].changes;
+        assert_eq!(
+            changes.iter().map(|c| c.kind).collect::<Vec<_>>(),
+            vec![
+                ChangeKind::Equal,
+                ChangeKind::Delete,
+                ChangeKind::Insert,
+                ChangeKind::Equal
+            ]
+        );
+        assert_eq!(changes[1].old_index, Some(1));
+        assert_eq!(changes[1].new_index, None);
+        assert_eq!(changes[2].value(), "x\n");
+    }
+
+    #[test]
+    fn diff_result_of_identical_responses_should_be_empty() {
+        let text = ResponseText {
+            status: "HTTP/1.1 200 OK".into(),
+            headers: "content-type:\"application/json\"\n\n".into(),
+            body: "{\n  \"id\": 1\n}".into(),
+        };
+
+        let result = DiffResult::new(&text, &text.clone(), None);
+
+        assert!(result.is_empty());
+        assert_eq!(result.changed_lines(), 0);
+    }
+
+    #[test]
+    fn diff_result_should_report_changes_per_section() {
+        let text1 = ResponseText {
+            status: "HTTP/1.1 200 OK".into(),
+            headers: "a:\"1\"\n\n".into(),
+            body: "{\n  \"id\": 1\n}".into(),
+        };
+        let text2 = ResponseText {
+            body: "{\n  \"id\": 2\n}".into(),
+            ..text1.clone()
+        };
+
+        let result = DiffResult::new(&text1, &text2, None);
+
+        assert!(!result.is_empty());
+        assert!(result.status.is_empty());
+        assert!(result.headers.is_empty());
+        assert_eq!(result.body.changed_lines(), 2);
+        assert_eq!(
+            result.summary().to_string(),
+            "1 insertion(+), 1 deletion(-) in body"
+        );
+
+        let result = DiffResult::new(&text1, &text2, Some(Default::default()));
+
+        assert!(matches!(&result.body, BodyDiff::Json(changes) if changes.len() == 1));
+        assert_eq!(
+            result.summary().to_string(),
+            "1 insertion(+), 1 deletion(-) in body"
+        );
+
+        let summary = DiffSummary {
+            insertions: 2,
+            deletions: 0,
+            sections: vec!["headers", "body"],
+        };
+        assert_eq!(
+            summary.to_string(),
+            "2 insertions(+), 0 deletions(-) in headers, body"
+        );
+    }
+
+    #[test]
+    fn diff_result_should_serialize_to_json() {
+        let text1 = ResponseText {
+            body: "{\"id\": 1}".into(),
+            ..Default::default()
+        };
+        let text2 = ResponseText {
+            body: "{\"id\": 2}".into(),
+            ..Default::default()
+        };
+
+        let result = DiffResult::new(&text1, &text2, Some(Default::default()));
+
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({
+                "status": {"hunks": []},
+                "headers": {"hunks": []},
+                "body": {"json": [{"path": "$.id", "kind": {"changed": {"old": 1, "new": 2}}}]}
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(result.summary()).unwrap(),
+            serde_json::json!({"insertions": 1, "deletions": 1, "sections": ["body"]})
+        );
+    }
+}
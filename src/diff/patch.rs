@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+use super::DiffResult;
+
+/// An operation of a JSON Patch (RFC 6902). Paths are JSON Pointers (RFC 6901).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+/// The operations turning `old` into `new`, to be applied in order.
+pub fn json_patch(old: &Value, new: &Value) -> Vec<PatchOperation> {
+    let mut ops = vec![];
+    diff_value("", old, new, &mut ops);
+    ops
+}
+
+impl DiffResult {
+    /// The JSON Patch turning the body of the first response into the second one.
+    pub fn json_patch(&self) -> Result<Vec<PatchOperation>> {
+        let parse = |body: &str| {
+            serde_json::from_str::<Value>(body)
+                .map_err(|e| anyhow!("a JSON patch needs JSON bodies: {}", e))
+        };
+
+        Ok(json_patch(
+            &parse(&self.text1.body)?,
+            &parse(&self.text2.body)?,
+        ))
+    }
+}
+
+fn diff_value(path: &str, old: &Value, new: &Value, ops: &mut Vec<PatchOperation>) {
+    match (old, new) {
+        (Value::Object(o), Value::Object(n)) => {
+            for (key, old_value) in o {
+                let path = pointer(path, key);
+                match n.get(key) {
+                    Some(new_value) => diff_value(&path, old_value, new_value, ops),
+                    None => ops.push(PatchOperation::Remove { path }),
+                }
+            }
+
+            for (key, new_value) in n {
+                if !o.contains_key(key) {
+                    ops.push(PatchOperation::Add {
+                        path: pointer(path, key),
+                        value: new_value.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Array(o), Value::Array(n)) => diff_array(path, o, n, ops),
+        _ if old == new => {}
+        _ => ops.push(PatchOperation::Replace {
+            path: path.to_string(),
+            value: new.clone(),
+        }),
+    }
+}
+
+/// Align the elements of both arrays, and address them by their index in the array
+/// as patched by the previous operations.
+fn diff_array(path: &str, old: &[Value], new: &[Value], ops: &mut Vec<PatchOperation>) {
+    let old_keys: Vec<String> = old.iter().map(|v| v.to_string()).collect();
+    let new_keys: Vec<String> = new.iter().map(|v| v.to_string()).collect();
+    let at = |i: usize| pointer(path, &i.to_string());
+    let mut pos = 0;
+
+    for op in capture_diff_slices(Algorithm::Myers, &old_keys, &new_keys) {
+        match op {
+            DiffOp::Equal { len, .. } => pos += len,
+            DiffOp::Delete { old_len, .. } => {
+                for _ in 0..old_len {
+                    ops.push(PatchOperation::Remove { path: at(pos) });
+                }
+            }
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => {
+                for value in &new[new_index..new_index + new_len] {
+                    ops.push(PatchOperation::Add {
+                        path: at(pos),
+                        value: value.clone(),
+                    });
+                    pos += 1;
+                }
+            }
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                let common = old_len.min(new_len);
+                for i in 0..common {
+                    diff_value(&at(pos), &old[old_index + i], &new[new_index + i], ops);
+                    pos += 1;
+                }
+                for _ in common..old_len {
+                    ops.push(PatchOperation::Remove { path: at(pos) });
+                }
+                for value in &new[new_index + common..new_index + new_len] {
+                    ops.push(PatchOperation::Add {
+                        path: at(pos),
+                        value: value.clone(),
+                    });
+                    pos += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Append a reference token to a JSON Pointer, escaping `~` and `/`.
+fn pointer(path: &str, token: &str) -> String {
+    format!("{}/{}", path, token.replace('~', "~0").replace('/', "~1"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Apply a patch with the operations this module emits.
+    fn apply(mut doc: Value, ops: &[PatchOperation]) -> Value {
+        for op in ops {
+            let (path, value) = match op {
+                PatchOperation::Add { path, value } => (path, Some(value)),
+                PatchOperation::Remove { path } => (path, None),
+                PatchOperation::Replace { path, value } => {
+                    *doc.pointer_mut(path).unwrap() = value.clone();
+                    continue;
+                }
+            };
+            let (parent, token) = path.rsplit_once('/').unwrap();
+            let token = token.replace("~1", "/").replace("~0", "~");
+            match (doc.pointer_mut(parent).unwrap(), value) {
+                (Value::Object(map), Some(value)) => {
+                    map.insert(token, value.clone());
+                }
+                (Value::Object(map), None) => {
+                    map.remove(&token);
+                }
+                (Value::Array(list), Some(value)) => {
+                    list.insert(token.parse().unwrap(), value.clone())
+                }
+                (Value::Array(list), None) => {
+                    list.remove(token.parse().unwrap());
+                }
+                _ => panic!("invalid parent of {}", path),
+            }
+        }
+        doc
+    }
+
+    #[test]
+    fn json_patch_should_describe_changes() {
+        let old = json!({"id": 1, "a/b": "x", "gone": true, "tags": ["a", "b", "c"]});
+        let new = json!({"id": 2, "a/b": "y", "tags": ["a", "c", "d"], "new": null});
+
+        assert_eq!(
+            serde_json::to_value(json_patch(&old, &new)).unwrap(),
+            json!([
+                {"op": "replace", "path": "/a~1b", "value": "y"},
+                {"op": "remove", "path": "/gone"},
+                {"op": "replace", "path": "/id", "value": 2},
+                {"op": "remove", "path": "/tags/1"},
+                {"op": "add", "path": "/tags/2", "value": "d"},
+                {"op": "add", "path": "/new", "value": null}
+            ])
+        );
+        assert!(json_patch(&old, &old).is_empty());
+        assert_eq!(
+            json_patch(&json!(1), &json!("1")),
+            vec![PatchOperation::Replace {
+                path: "".into(),
+                value: json!("1")
+            }]
+        );
+    }
+
+    #[test]
+    fn json_patch_should_turn_old_into_new() {
+        let old = json!({"items": [1, {"id": 2}, 3, 4, 5], "m": {"k": [true]}});
+        let new = json!({"items": [0, 1, {"id": 3}, 5, 6, 7], "m": {"k": [], "j": 1}});
+
+        assert_eq!(apply(old.clone(), &json_patch(&old, &new)), new);
+        assert_eq!(apply(new.clone(), &json_patch(&new, &old)), old);
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::json_path::{JsonPath, Segment};
+
+/// How the values at a path are compared, declared in the `compare:` block of a
+/// response profile:
+///
+/// ```yaml
+/// compare:
+///   $.total: {tolerance: 0.01}
+///   $.items[*].score: {relative: 0.001}
+///   $.currency: {ignore_case: true}
+///   $.request_id: {only: presence}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompareRule {
+    /// Numbers are equal if they differ by at most this amount.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tolerance: Option<f64>,
+
+    /// Numbers are equal if they differ by at most this fraction of the larger one.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub relative: Option<f64>,
+
+    /// Strings are compared regardless of case.
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub ignore_case: bool,
+
+    /// Only compare the type of the values, or only that both are present.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub only: Option<CompareOnly>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompareOnly {
+    Type,
+    Presence,
+}
+
+impl CompareRule {
+    pub fn validate(&self) -> Result<()> {
+        let fuzzy = self.tolerance.is_some() || self.relative.is_some() || self.ignore_case;
+        if self.only.is_some() && fuzzy {
+            return Err(anyhow!(
+                "`only` cannot be combined with other comparison rules"
+            ));
+        }
+        for amount in [self.tolerance, self.relative].into_iter().flatten() {
+            if amount.is_nan() || amount < 0.0 {
+                return Err(anyhow!(
+                    "tolerance must be a non-negative number, got {}",
+                    amount
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether two values present at the path are equal under this rule.
+    fn equal(&self, old: &Value, new: &Value) -> bool {
+        match (self.only, old, new) {
+            (Some(CompareOnly::Presence), _, _) => true,
+            (Some(CompareOnly::Type), _, _) => type_name(old) == type_name(new),
+            (None, Value::Number(o), Value::Number(n)) => match (o.as_f64(), n.as_f64()) {
+                (Some(o), Some(n)) => {
+                    let delta = (o - n).abs();
+                    o == n
+                        || self.tolerance.is_some_and(|t| delta <= t)
+                        || self
+                            .relative
+                            .is_some_and(|r| delta <= r * o.abs().max(n.abs()))
+                }
+                _ => old == new,
+            },
+            (None, Value::String(o), Value::String(n)) if self.ignore_case => {
+                o.to_lowercase() == n.to_lowercase()
+            }
+            _ => old == new,
+        }
+    }
+}
+
+/// Compare both documents under the rules, and copy the values of `old` into `new`
+/// where they are considered equal, so that the diff that follows ignores them.
+/// Array elements are paired by index. Returns true if `new` was changed.
+pub fn apply_rules(old: &Value, new: &mut Value, rules: &[(JsonPath, CompareRule)]) -> bool {
+    !rules.is_empty() && reconcile(&JsonPath::default(), old, new, rules)
+}
+
+fn reconcile(
+    path: &JsonPath,
+    old: &Value,
+    new: &mut Value,
+    rules: &[(JsonPath, CompareRule)],
+) -> bool {
+    if let Some((_, rule)) = rules.iter().find(|(pattern, _)| pattern.matches(path)) {
+        if old != new && rule.equal(old, new) {
+            *new = old.clone();
+            return true;
+        }
+        return false;
+    }
+
+    let mut changed = false;
+    match (old, new) {
+        (Value::Object(o), Value::Object(n)) => {
+            for (key, new_value) in n.iter_mut() {
+                if let Some(old_value) = o.get(key) {
+                    let path = path.join(Segment::Key(key.clone()));
+                    changed |= reconcile(&path, old_value, new_value, rules);
+                }
+            }
+        }
+        (Value::Array(o), Value::Array(n)) => {
+            for (i, (old_value, new_value)) in o.iter().zip(n.iter_mut()).enumerate() {
+                changed |= reconcile(&path.join(Segment::Index(i)), old_value, new_value, rules);
+            }
+        }
+        _ => {}
+    }
+
+    changed
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(yaml: &str) -> Vec<(JsonPath, CompareRule)> {
+        let rules: std::collections::BTreeMap<String, CompareRule> =
+            serde_yaml::from_str(yaml).unwrap();
+        rules
+            .into_iter()
+            .map(|(path, rule)| (path.parse().unwrap(), rule))
+            .collect()
+    }
+
+    #[test]
+    fn rules_should_reconcile_equivalent_values() {
+        let rules = rules(
+            r#"
+$.total: {tolerance: 0.01}
+$.items[*].score: {relative: 0.001}
+$.currency: {ignore_case: true}
+$.id: {only: type}
+$.meta: {only: presence}
+"#,
+        );
+        let old = json!({
+            "total": 10.0,
+            "items": [{"score": 1000.0}, {"score": 5.0}],
+            "currency": "EUR",
+            "id": 1,
+            "meta": {"trace": "a"},
+            "other": 1
+        });
+        let mut new = json!({
+            "total": 10.004,
+            "items": [{"score": 1000.9}, {"score": 5.1}],
+            "currency": "eur",
+            "id": 2,
+            "meta": {"trace": "b", "extra": true},
+            "other": 2
+        });
+
+        assert!(apply_rules(&old, &mut new, &rules));
+
+        assert_eq!(
+            new,
+            json!({
+                "total": 10.0,
+                "items": [{"score": 1000.0}, {"score": 5.1}],
+                "currency": "EUR",
+                "id": 1,
+                "meta": {"trace": "a"},
+                "other": 2
+            })
+        );
+
+        let mut new = json!({"total": 10.1, "id": "1", "currency": "USD"});
+        assert!(!apply_rules(&old, &mut new, &rules));
+        assert_eq!(new, json!({"total": 10.1, "id": "1", "currency": "USD"}));
+    }
+
+    #[test]
+    fn invalid_rules_should_fail_to_validate() {
+        let rule = |yaml: &str| serde_yaml::from_str::<CompareRule>(yaml).unwrap();
+
+        assert!(rule("{tolerance: 0.1, relative: 0.01}").validate().is_ok());
+        assert!(rule("{tolerance: 0, relative: 0}").validate().is_ok());
+        assert_eq!(
+            rule("{tolerance: -1}").validate().unwrap_err().to_string(),
+            "tolerance must be a non-negative number, got -1"
+        );
+        assert!(rule("{relative: -0.01}").validate().is_err());
+        assert!(rule("{only: type, ignore_case: true}").validate().is_err());
+        assert!(serde_yaml::from_str::<CompareRule>("{only: value}").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{Ok, Result};
+use console::{style, Style};
+use std::fmt::{self, Write as _};
+
+use super::{BodyDiff, ChangeKind, DiffResult, DiffSection, JsonChange, JsonChangeKind};
+
+struct Line(Option<usize>);
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(idx) => write!(f, "{:<4}", idx + 1),
+            None => write!(f, "    "),
+        }
+    }
+}
+
+/// Render a diff result as colored text for the terminal.
+pub fn render_terminal(result: &DiffResult) -> Result<String> {
+    let mut sections = vec![];
+
+    for section in [&result.status, &result.headers] {
+        if !section.is_empty() {
+            sections.push(render_section(section)?);
+        }
+    }
+
+    match &result.body {
+        BodyDiff::Text(section) if !section.is_empty() => sections.push(render_section(section)?),
+        BodyDiff::Json(changes) if !changes.is_empty() => {
+            sections.push(render_json_changes(changes)?)
+        }
+        _ => {}
+    }
+
+    let mut output = String::new();
+    for (idx, section) in sections.iter().enumerate() {
+        if idx > 0 {
+            writeln!(&mut output, "{:-^1$}", "-", 80)?;
+        }
+        write!(&mut output, "{}", section)?;
+    }
+
+    Ok(output)
+}
+
+/// Render structural JSON changes, one path per line.
+pub fn render_json_changes(changes: &[JsonChange]) -> Result<String> {
+    let mut output = String::new();
+
+    for change in changes {
+        match &change.kind {
+            JsonChangeKind::Added(value) => writeln!(
+                &mut output,
+                "{}",
+                Style::new()
+                    .green()
+                    .apply_to(format!("+ {}: {}", change.path, value))
+            )?,
+            JsonChangeKind::Removed(value) => writeln!(
+                &mut output,
+                "{}",
+                Style::new()
+                    .red()
+                    .apply_to(format!("- {}: {}", change.path, value))
+            )?,
+            JsonChangeKind::Changed { old, new } => writeln!(
+                &mut output,
+                "{} {}: {} -> {}",
+                Style::new().yellow().bold().apply_to("~"),
+                change.path,
+                Style::new().red().apply_to(old),
+                Style::new().green().apply_to(new),
+            )?,
+        }
+    }
+
+    Ok(output)
+}
+
+/// Render the hunks of a single section as colored text for the terminal.
+pub fn render_section(section: &DiffSection) -> Result<String> {
+    let mut output = String::new();
+
+    for (idx, hunk) in section.hunks.iter().enumerate() {
+        if idx > 0 {
+            writeln!(&mut output, "{:-^1$}", "-", 80)?;
+        }
+
+        for change in &hunk.changes {
+            let (sign, s) = match change.kind {
+                ChangeKind::Delete => ("-", Style::new().red()),
+                ChangeKind::Insert => ("+", Style::new().green()),
+                ChangeKind::Equal => (" ", Style::new().dim()),
+            };
+            write!(
+                &mut output,
+                "{}{} |{}",
+                style(Line(change.old_index)).dim(),
+                style(Line(change.new_index)).dim(),
+                s.apply_to(sign).bold(),
+            )?;
+
+            for segment in &change.segments {
+                if segment.emphasized {
+                    write!(
+                        &mut output,
+                        "{}",
+                        s.apply_to(&segment.text).underlined().on_black()
+                    )?;
+                } else {
+                    write!(&mut output, "{}", s.apply_to(&segment.text))?;
+                }
+            }
+
+            if change.missing_newline {
+                writeln!(&mut output)?;
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Diff two texts line by line and render the result for the terminal.
+pub fn diff_text(text1: &str, text2: &str) -> Result<String> {
+    render_section(&DiffSection::from_lines(text1, text2))
+}
@@ -0,0 +1,117 @@
+use anyhow::{Ok, Result};
+use std::fmt::Write as _;
+
+use super::{BodyDiff, ChangeKind, DiffHunk, DiffResult, DiffSection};
+
+/// Render a diff result as a plain unified diff, with one file per section of the
+/// response named `<name>/status`, `<name>/headers` and `<name>/body`. JSON bodies
+/// are diffed line by line, as printed.
+pub fn render_unified(result: &DiffResult, name: &str) -> Result<String> {
+    let body = match &result.body {
+        BodyDiff::Text(section) => section.clone(),
+        BodyDiff::Json(changes) if changes.is_empty() => DiffSection::default(),
+        BodyDiff::Json(_) => DiffSection::from_lines(&result.text1.body, &result.text2.body),
+    };
+
+    let mut output = String::new();
+    for (file, section) in [
+        ("status", &result.status),
+        ("headers", &result.headers),
+        ("body", &body),
+    ] {
+        if section.is_empty() {
+            continue;
+        }
+
+        let file = format!("{}/{}", name, file);
+        writeln!(&mut output, "diff --git a/{} b/{}", file, file)?;
+        writeln!(&mut output, "--- a/{}", file)?;
+        writeln!(&mut output, "+++ b/{}", file)?;
+
+        for hunk in &section.hunks {
+            write_hunk(&mut output, hunk)?;
+        }
+    }
+
+    Ok(output)
+}
+
+fn write_hunk(output: &mut String, hunk: &DiffHunk) -> Result<()> {
+    let old = range(hunk.changes.iter().map(|c| c.old_index));
+    let new = range(hunk.changes.iter().map(|c| c.new_index));
+    writeln!(output, "@@ -{} +{} @@", old, new)?;
+
+    for change in &hunk.changes {
+        let sign = match change.kind {
+            ChangeKind::Delete => '-',
+            ChangeKind::Insert => '+',
+            ChangeKind::Equal => ' ',
+        };
+        write!(output, "{}{}", sign, change.value())?;
+
+        if change.missing_newline {
+            writeln!(output, "\n\\ No newline at end of file")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The `start,count` range of a hunk header, from the line indexes on one side.
+fn range(indexes: impl Iterator<Item = Option<usize>>) -> String {
+    let indexes: Vec<usize> = indexes.flatten().collect();
+    match indexes.as_slice() {
+        [] => "0,0".into(),
+        [first] => format!("{}", first + 1),
+        [first, ..] => format!("{},{}", first + 1, indexes.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseText;
+
+    #[test]
+    fn render_unified_should_work() {
+        let text1 = ResponseText {
+            status: "HTTP/1.1 200 OK".into(),
+            headers: "a:\"1\"\n\n".into(),
+            body: "{\n  \"id\": 1,\n  \"name\": \"a\"\n}".into(),
+        };
+        let text2 = ResponseText {
+            status: "HTTP/1.1 404 Not Found".into(),
+            body: "{\n  \"id\": 2,\n  \"name\": \"a\"\n}".into(),
+            ..text1.clone()
+        };
+
+        let expected = r#"diff --git a/todo/status b/todo/status
+--- a/todo/status
++++ b/todo/status
+@@ -1 +1 @@
+-HTTP/1.1 200 OK
+\ No newline at end of file
++HTTP/1.1 404 Not Found
+\ No newline at end of file
+diff --git a/todo/body b/todo/body
+--- a/todo/body
++++ b/todo/body
+@@ -1,4 +1,4 @@
+ {
+-  "id": 1,
++  "id": 2,
+   "name": "a"
+ }
+\ No newline at end of file
+"#;
+
+        let result = DiffResult::new(&text1, &text2, None);
+        assert_eq!(render_unified(&result, "todo").unwrap(), expected);
+
+        let result = DiffResult::new(&text1, &text2, Some(Default::default()));
+        assert_eq!(render_unified(&result, "todo").unwrap(), expected);
+
+        let result = DiffResult::new(&text1, &text1, Some(Default::default()));
+        assert_eq!(render_unified(&result, "todo").unwrap(), "");
+    }
+}
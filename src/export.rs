@@ -0,0 +1,379 @@
+use anyhow::{anyhow, Ok, Result};
+use clap::ValueEnum;
+use reqwest::{header::CONTENT_TYPE, Method, Request};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::{Body, ExtraArgs, HttpClient, Part, RequestProfile};
+
+/// Command line formats a resolved request can be exported to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A curl command.
+    #[default]
+    Curl,
+
+    /// An httpie command.
+    Httpie,
+
+    /// The raw HTTP/1.1 request.
+    Http,
+}
+
+/// Body of an exported command. Files are referenced rather than inlined, so that
+/// binary content survives.
+enum ExportBody {
+    Text(String),
+    File(PathBuf),
+    Multipart(Vec<Part>),
+}
+
+/// Render the fully resolved request of a profile in the given format, as it would be
+/// sent by the client. An oauth2 token is fetched if the profile needs one.
+pub async fn export_request(
+    profile: &RequestProfile,
+    client: &HttpClient,
+    args: &ExtraArgs,
+    format: ExportFormat,
+) -> Result<String> {
+    let req = profile.build_request(client, args).await?;
+    let body = match (&profile.body, format) {
+        (Some(Body::File(path)), ExportFormat::Curl | ExportFormat::Httpie) => {
+            ExportBody::File(path.clone())
+        }
+        (Some(body @ Body::Multipart(_)), ExportFormat::Curl | ExportFormat::Httpie) => {
+            match body.clone().with_overrides(&args.body)? {
+                Body::Multipart(parts) => ExportBody::Multipart(parts),
+                _ => unreachable!("overrides keep the kind of the body"),
+            }
+        }
+        _ => {
+            let bytes = req.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+            let text = String::from_utf8(bytes.to_vec())
+                .map_err(|_| anyhow!("the body is not valid UTF-8 and can't be exported"))?;
+            ExportBody::Text(text)
+        }
+    };
+
+    match (format, &body) {
+        (ExportFormat::Curl, _) => to_curl(&req, &body),
+        (ExportFormat::Httpie, _) => to_httpie(&req, &body),
+        (ExportFormat::Http, ExportBody::Text(text)) => to_http(&req, text),
+        (ExportFormat::Http, _) => unreachable!("raw requests inline their body"),
+    }
+}
+
+fn to_curl(req: &Request, body: &ExportBody) -> Result<String> {
+    let mut output = String::from("curl");
+
+    if req.method() != Method::GET {
+        write!(output, " -X {}", req.method())?;
+    }
+    write!(output, " {}", shell_escape(req.url().as_str()))?;
+
+    for (name, value) in req.headers() {
+        // curl sets the multipart content type itself, with its own boundary
+        if name == CONTENT_TYPE && matches!(body, ExportBody::Multipart(_)) {
+            continue;
+        }
+        let header = format!("{}: {}", name, String::from_utf8_lossy(value.as_bytes()));
+        write!(output, " \\\n  -H {}", shell_escape(&header))?;
+    }
+
+    match body {
+        ExportBody::Text(text) if text.is_empty() => {}
+        ExportBody::Text(text) => write!(output, " \\\n  --data-raw {}", shell_escape(text))?,
+        ExportBody::File(path) => {
+            let file = format!("@{}", path.display());
+            write!(output, " \\\n  --data-binary {}", shell_escape(&file))?;
+        }
+        ExportBody::Multipart(parts) => {
+            for part in parts {
+                let (option, field) = match (&part.value, &part.file) {
+                    (Some(value), _) => ("--form-string", format!("{}={}", part.name, value)),
+                    (None, Some(path)) => {
+                        let mut field = format!("{}=@{}", part.name, path.display());
+                        if let Some(filename) = &part.filename {
+                            write!(field, ";filename={}", filename)?;
+                        }
+                        if let Some(content_type) = &part.content_type {
+                            write!(field, ";type={}", content_type)?;
+                        }
+                        ("-F", field)
+                    }
+                    (None, None) => ("--form-string", format!("{}=", part.name)),
+                };
+                write!(output, " \\\n  {} {}", option, shell_escape(&field))?;
+            }
+        }
+    }
+
+    writeln!(output)?;
+    Ok(output)
+}
+
+fn to_httpie(req: &Request, body: &ExportBody) -> Result<String> {
+    let mut output = String::from("http");
+    if let ExportBody::Multipart(_) = body {
+        output.push_str(" --multipart");
+    }
+    write!(
+        output,
+        " {} {}",
+        req.method(),
+        shell_escape(req.url().as_str())
+    )?;
+
+    for (name, value) in req.headers() {
+        // httpie sets the multipart content type itself, with its own boundary
+        if name == CONTENT_TYPE && matches!(body, ExportBody::Multipart(_)) {
+            continue;
+        }
+        let header = format!("{}:{}", name, String::from_utf8_lossy(value.as_bytes()));
+        write!(output, " \\\n  {}", shell_escape(&header))?;
+    }
+
+    match body {
+        ExportBody::Text(text) if text.is_empty() => {}
+        ExportBody::Text(text) => write!(output, " \\\n  --raw {}", shell_escape(text))?,
+        ExportBody::File(path) => {
+            let file = format!("@{}", path.display());
+            write!(output, " \\\n  {}", shell_escape(&file))?;
+        }
+        ExportBody::Multipart(parts) => {
+            for part in parts {
+                let field = match (&part.value, &part.file) {
+                    (Some(value), _) => format!("{}={}", part.name, value),
+                    (None, Some(path)) => {
+                        if part.filename.is_some() {
+                            return Err(anyhow!(
+                                "httpie can't rename the file of multipart part {}",
+                                part.name
+                            ));
+                        }
+                        let mut field = format!("{}@{}", part.name, path.display());
+                        if let Some(content_type) = &part.content_type {
+                            write!(field, ";type={}", content_type)?;
+                        }
+                        field
+                    }
+                    (None, None) => format!("{}=", part.name),
+                };
+                write!(output, " \\\n  {}", shell_escape(&field))?;
+            }
+        }
+    }
+
+    writeln!(output)?;
+    Ok(output)
+}
+
+fn to_http(req: &Request, body: &str) -> Result<String> {
+    let url = req.url();
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        write!(target, "?{}", query)?;
+    }
+
+    let mut output = format!("{} {} HTTP/1.1\r\n", req.method(), target);
+
+    if let Some(host) = url.host_str() {
+        match url.port() {
+            Some(port) => write!(output, "host: {}:{}\r\n", host, port)?,
+            None => write!(output, "host: {}\r\n", host)?,
+        }
+    }
+
+    for (name, value) in req.headers() {
+        write!(
+            output,
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        )?;
+    }
+
+    if !body.is_empty() {
+        write!(output, "content-length: {}\r\n", body.len())?;
+    }
+
+    write!(output, "\r\n{}", body)?;
+    Ok(output)
+}
+
+/// Quote a word for a POSIX shell, if needed.
+fn shell_escape(s: &str) -> String {
+    let safe = !s.is_empty()
+        && s.chars().all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | ':' | ',' | '=' | '@')
+        });
+
+    match safe {
+        true => s.to_string(),
+        false => format!("'{}'", s.replace('\'', r"'\''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::parse_curl;
+
+    #[test]
+    fn shell_escape_should_work() {
+        assert_eq!(shell_escape("https://a.com/x"), "https://a.com/x");
+        assert_eq!(shell_escape("a b"), "'a b'");
+        assert_eq!(shell_escape("it's"), r"'it'\''s'");
+        assert_eq!(shell_escape(""), "''");
+    }
+
+    #[tokio::test]
+    async fn export_request_should_work() {
+        let client = HttpClient::default();
+        let profile = parse_curl(
+            r#"curl -X POST 'https://a.com/todos?page=2' -H 'content-type: application/json' -d '{"title":"it'\''s"}'"#,
+        )
+        .unwrap();
+        let args = ExtraArgs::new_with_headers(vec![("x-token".into(), "abc".into())]);
+
+        assert_eq!(
+            export_request(&profile, &client, &args, ExportFormat::Curl).await.unwrap(),
+            "curl -X POST 'https://a.com/todos?page=2' \\\n  -H 'content-type: application/json' \\\n  -H 'x-token: abc' \\\n  --data-raw '{\"title\":\"it'\\''s\"}'\n"
+        );
+
+        assert_eq!(
+            export_request(&profile, &client, &args, ExportFormat::Http).await.unwrap(),
+            "POST /todos?page=2 HTTP/1.1\r\nhost: a.com\r\ncontent-type: application/json\r\nx-token: abc\r\ncontent-length: 16\r\n\r\n{\"title\":\"it's\"}"
+        );
+    }
+
+    #[tokio::test]
+    async fn exported_curl_should_parse_back() {
+        let client = HttpClient::default();
+        let profile = parse_curl("curl https://a.com/search?q=rust -H 'accept: */*'").unwrap();
+
+        let command = export_request(&profile, &client, &Default::default(), ExportFormat::Curl)
+            .await
+            .unwrap();
+        assert!(!command.contains("content-type"));
+        let parsed = parse_curl(&command).unwrap();
+
+        assert_eq!(parsed.method, profile.method);
+        assert_eq!(parsed.url, profile.url);
+        assert_eq!(parsed.params, profile.params);
+        assert_eq!(parsed.headers["accept"], "*/*");
+    }
+
+    #[tokio::test]
+    async fn export_request_should_reference_files() {
+        let client = HttpClient::default();
+        let path = std::env::temp_dir().join(format!("xdiff-export-{}.png", std::process::id()));
+        std::fs::write(&path, [0x89, b'P', b'N', b'G', 0xff]).unwrap();
+        let file = path.display().to_string();
+        let url: reqwest::Url = "https://a.com/upload".parse().unwrap();
+
+        let profile = RequestProfile::new(
+            Method::POST,
+            url.clone(),
+            None,
+            Default::default(),
+            Some(Body::File(path.clone())),
+        );
+        assert_eq!(
+            export_request(&profile, &client, &Default::default(), ExportFormat::Curl).await.unwrap(),
+            format!("curl -X POST https://a.com/upload \\\n  -H 'content-type: image/png' \\\n  --data-binary @{}\n", file)
+        );
+        assert_eq!(
+            export_request(&profile, &client, &Default::default(), ExportFormat::Httpie)
+                .await
+                .unwrap(),
+            format!(
+                "http POST https://a.com/upload \\\n  content-type:image/png \\\n  @{}\n",
+                file
+            )
+        );
+        assert!(
+            export_request(&profile, &client, &Default::default(), ExportFormat::Http)
+                .await
+                .is_err()
+        );
+
+        let parts = vec![
+            Part {
+                name: "title".into(),
+                value: Some("a b".into()),
+                ..Default::default()
+            },
+            Part {
+                name: "photo".into(),
+                file: Some(path.clone()),
+                content_type: Some("image/png".into()),
+                ..Default::default()
+            },
+        ];
+        let profile = RequestProfile::new(
+            Method::POST,
+            url.clone(),
+            None,
+            Default::default(),
+            Some(Body::Multipart(parts)),
+        );
+        assert_eq!(
+            export_request(&profile, &client, &Default::default(), ExportFormat::Curl).await.unwrap(),
+            format!("curl -X POST https://a.com/upload \\\n  --form-string 'title=a b' \\\n  -F 'photo=@{};type=image/png'\n", file)
+        );
+        assert_eq!(
+            export_request(&profile, &client, &Default::default(), ExportFormat::Httpie).await.unwrap(),
+            format!("http --multipart POST https://a.com/upload \\\n  'title=a b' \\\n  'photo@{};type=image/png'\n", file)
+        );
+
+        let profile = RequestProfile::new(
+            Method::POST,
+            url,
+            None,
+            Default::default(),
+            Some(Body::Base64("/w==".into())),
+        );
+        let err = export_request(&profile, &client, &Default::default(), ExportFormat::Curl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the body is not valid UTF-8 and can't be exported"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn export_request_should_fetch_oauth2_token() {
+        let mut server = mockito::Server::new_async().await;
+        let token = server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token": "t1", "expires_in": 3600}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut profile: RequestProfile = "https://a.com/todos".parse().unwrap();
+        profile.auth = Some(crate::Auth::Oauth2ClientCredentials(
+            crate::OAuth2ClientCredentials {
+                token_url: format!("{}/token", server.url()).parse().unwrap(),
+                client_id: "id".into(),
+                client_secret: "secret".into(),
+                scope: None,
+                audience: None,
+                credentials_in_body: false,
+            },
+        ));
+
+        let client = HttpClient::default();
+        assert_eq!(
+            export_request(&profile, &client, &Default::default(), ExportFormat::Curl)
+                .await
+                .unwrap(),
+            "curl https://a.com/todos \\\n  -H 'authorization: Bearer t1'\n"
+        );
+        token.assert_async().await;
+    }
+}
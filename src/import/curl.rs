@@ -0,0 +1,510 @@
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE, REFERER, USER_AGENT},
+    Method, Url,
+};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+use super::parse_body;
+use crate::config::take_query_params;
+use crate::{Body, Part, RequestProfile};
+
+/// Options taking a value that have no meaning for a profile.
+const IGNORED_WITH_VALUE: &[&str] = &[
+    "-o",
+    "--output",
+    "-m",
+    "--max-time",
+    "--connect-timeout",
+    "-w",
+    "--write-out",
+    "--retry",
+    "-x",
+    "--proxy",
+    "--cacert",
+    "--cert",
+    "--key",
+    "-T",
+    "--upload-file",
+    "-c",
+    "--cookie-jar",
+    "-D",
+    "--dump-header",
+    "-K",
+    "--config",
+    "-E",
+    "-r",
+    "--range",
+    "-U",
+    "--proxy-user",
+    "-Y",
+    "--speed-limit",
+    "-y",
+    "--speed-time",
+    "-z",
+    "--time-cond",
+    "--resolve",
+    "--connect-to",
+    "--max-redirs",
+    "--max-filesize",
+    "--limit-rate",
+    "--interface",
+    "--dns-servers",
+    "--local-port",
+    "--keepalive-time",
+    "--expect100-timeout",
+    "--retry-delay",
+    "--retry-max-time",
+    "--capath",
+    "--cert-type",
+    "--key-type",
+    "--pass",
+    "--pinnedpubkey",
+    "--ciphers",
+    "--tls-max",
+    "--noproxy",
+    "--preproxy",
+    "--proto",
+    "--proto-redir",
+    "--netrc-file",
+    "--output-dir",
+    "--stderr",
+    "--trace",
+    "--trace-ascii",
+    "--unix-socket",
+    "--abstract-unix-socket",
+];
+
+/// Characters escaped by `--data-urlencode`: all but the unreserved ones of RFC 3986.
+const URL_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Parse a curl command line, as copied from the browser devtools, into a profile.
+///
+/// Supported options are `-X`, `-H`, `-d`/`--data`/`--data-raw`/`--data-binary`,
+/// `--data-urlencode`, `-F`, `-u`, `-A`, `-b`, `-e`, `-G` and `--url`.
+/// `--compressed` and options that only affect the transfer are ignored.
+pub fn parse_curl(command: &str) -> Result<RequestProfile> {
+    let tokens = tokenize(command)?;
+    let mut args = tokens.iter().map(String::as_str).peekable();
+
+    if args.peek() == Some(&"curl") {
+        args.next();
+    }
+
+    let mut method = None;
+    let mut url = None;
+    let mut headers = HeaderMap::new();
+    let mut data: Vec<String> = vec![];
+    let mut form: Vec<Part> = vec![];
+    let mut get = false;
+
+    while let Some(arg) = args.next() {
+        let (flag, attached) = split_short_option(arg);
+        let mut value = || -> Result<String> {
+            match attached {
+                Some(v) => Ok(v.to_string()),
+                None => args
+                    .next()
+                    .map(|v| v.to_string())
+                    .ok_or_else(|| anyhow!("missing value for {}", flag)),
+            }
+        };
+
+        match flag {
+            "-X" | "--request" => method = Some(Method::from_str(&value()?.to_uppercase())?),
+            "-H" | "--header" => {
+                let header = value()?;
+                let (name, val) = header
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("invalid header: {}", header))?;
+                headers.append(
+                    HeaderName::from_str(name.trim())?,
+                    HeaderValue::from_str(val.trim())?,
+                );
+            }
+            "-d" | "--data" | "--data-ascii" => {
+                let v = value()?;
+                data.push(match v.strip_prefix('@') {
+                    // like curl, drop the line breaks of a file read by `-d @file`
+                    Some(path) => read_data_file(path)?.replace(['\r', '\n'], ""),
+                    None => v,
+                });
+            }
+            "--data-binary" => {
+                let v = value()?;
+                data.push(match v.strip_prefix('@') {
+                    Some(path) => read_data_file(path)?,
+                    None => v,
+                });
+            }
+            "--data-raw" => data.push(value()?),
+            "--data-urlencode" => data.push(url_encode_data(&value()?)?),
+            "-F" | "--form" => {
+                let v = value()?;
+                let (name, val) = v
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("invalid form field: {}", v))?;
+                form.push(parse_form_part(name, val)?);
+            }
+            "-u" | "--user" => {
+                let credentials = value()?;
+                headers.insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Basic {}", STANDARD.encode(credentials)))?,
+                );
+            }
+            "-A" | "--user-agent" => {
+                headers.insert(USER_AGENT, HeaderValue::from_str(&value()?)?);
+            }
+            "-b" | "--cookie" => {
+                headers.insert(COOKIE, HeaderValue::from_str(&value()?)?);
+            }
+            "-e" | "--referer" => {
+                headers.insert(REFERER, HeaderValue::from_str(&value()?)?);
+            }
+            "-G" | "--get" => get = true,
+            "--url" => url = Some(value()?),
+            "--compressed" => {}
+            _ if IGNORED_WITH_VALUE.contains(&flag) => {
+                value()?;
+            }
+            _ if arg.starts_with('-') => {}
+            // the first positional argument is the url, the next ones can be the values of
+            // unknown options
+            _ if url.is_none() => url = Some(arg.to_string()),
+            _ => {}
+        }
+    }
+
+    let url = url.ok_or_else(|| anyhow!("no url found in curl command"))?;
+    let mut url = Url::parse(&url)?;
+    let mut params = take_query_params(&mut url);
+
+    let has_body = !data.is_empty() || !form.is_empty();
+    let method = method.unwrap_or(match has_body && !get {
+        true => Method::POST,
+        false => Method::GET,
+    });
+
+    let data = data.join("&");
+    let body = if get {
+        for (k, v) in serde_urlencoded::from_str::<Vec<(String, String)>>(&data)? {
+            params[k] = Value::String(v);
+        }
+        None
+    } else if !form.is_empty() {
+        Some(Body::Multipart(form))
+    } else if !data.is_empty() {
+        Some(parse_body(&data, &mut headers)?)
+    } else {
+        None
+    };
+
+    let params = match params.as_object().map(Map::is_empty) {
+        Some(false) => Some(params),
+        _ => None,
+    };
+
+    Ok(RequestProfile::new(method, url, params, headers, body))
+}
+
+/// Encode a `--data-urlencode` value: `content`, `=content`, `name=content`, `@file` or
+/// `name@file`. The content is percent-encoded, the name is kept as is.
+fn url_encode_data(value: &str) -> Result<String> {
+    let (name, content) = match value.find(['=', '@']) {
+        Some(i) if value[i..].starts_with('@') => (&value[..i], read_data_file(&value[i + 1..])?),
+        Some(i) => (&value[..i], value[i + 1..].to_string()),
+        None => ("", value.to_string()),
+    };
+
+    let content = utf8_percent_encode(&content, URL_ENCODE);
+    Ok(match name {
+        "" => content.to_string(),
+        _ => format!("{}={}", name, content),
+    })
+}
+
+/// Read the file of a `@file` data value. `@-` (stdin) is rejected, as the command itself
+/// may come from stdin.
+fn read_data_file(path: &str) -> Result<String> {
+    if path == "-" {
+        return Err(anyhow!(
+            "reading data from stdin (@-) is not supported, save it to a file first"
+        ));
+    }
+    std::fs::read_to_string(path).with_context(|| format!("failed to read data file {}", path))
+}
+
+/// Parse a `-F name=value` field. `name=@path` uploads a file and accepts curl's
+/// `;type=` and `;filename=` attributes; `name=<path` (file content as text) is not supported.
+fn parse_form_part(name: &str, value: &str) -> Result<Part> {
+    if value.starts_with('<') {
+        return Err(anyhow!(
+            "reading a form field from a file is not supported: {}",
+            name
+        ));
+    }
+    let mut part = Part {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    match value.strip_prefix('@') {
+        Some(file) => {
+            let mut attrs = file.split(';');
+            part.file = attrs.next().map(Into::into);
+            for attr in attrs {
+                match attr.split_once('=') {
+                    Some(("type", v)) => part.content_type = Some(v.to_string()),
+                    Some(("filename", v)) => part.filename = Some(v.trim_matches('"').to_string()),
+                    _ => return Err(anyhow!("unsupported form field attribute: {}", attr)),
+                }
+            }
+        }
+        None => part.value = Some(value.to_string()),
+    }
+    Ok(part)
+}
+
+/// Split a short option with an attached value, e.g. `-XPOST`, into `("-X", Some("POST"))`.
+fn split_short_option(arg: &str) -> (&str, Option<&str>) {
+    const SHORT_WITH_VALUE: &[&str] = &["-X", "-H", "-d", "-F", "-u", "-A", "-b", "-e"];
+
+    if arg.len() > 2 && !arg.starts_with("--") {
+        let (flag, value) = arg.split_at(2);
+        if SHORT_WITH_VALUE.contains(&flag) {
+            return (flag, Some(value));
+        }
+    }
+
+    (arg, None)
+}
+
+/// Split a command line into words, following the quoting rules of a POSIX shell.
+/// ANSI-C quoting (`$'...'`), as emitted by the devtools, is supported too.
+fn tokenize(command: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(token) = current.take() {
+                    tokens.push(token);
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(next) => current.get_or_insert_with(String::new).push(next),
+                None => {}
+            },
+            '\'' => {
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => token.push(c),
+                        None => return Err(anyhow!("unterminated single quote")),
+                    }
+                }
+            }
+            '"' => {
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => token.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                token.push('\\');
+                                token.push(c);
+                            }
+                            None => return Err(anyhow!("unterminated double quote")),
+                        },
+                        Some(c) => token.push(c),
+                        None => return Err(anyhow!("unterminated double quote")),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => token.push('\n'),
+                            Some('t') => token.push('\t'),
+                            Some('r') => token.push('\r'),
+                            Some(c) => token.push(c),
+                            None => return Err(anyhow!("unterminated $' quote")),
+                        },
+                        Some(c) => token.push(c),
+                        None => return Err(anyhow!("unterminated $' quote")),
+                    }
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if let Some(token) = current {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::CONTENT_TYPE;
+    use serde_json::json;
+
+    #[test]
+    fn tokenize_should_follow_shell_quoting() {
+        let tokens = tokenize(
+            "curl 'https://a.com/x?y=1' \\\n  -H \"x-a: \\\"b\\\"\" --data-raw $'{\"a\":\"it\\'s\"}'",
+        )
+        .unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                "curl",
+                "https://a.com/x?y=1",
+                "-H",
+                "x-a: \"b\"",
+                "--data-raw",
+                "{\"a\":\"it's\"}"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_curl_should_work() {
+        let profile = parse_curl(
+            r#"curl 'https://api.example.com/todos?page=2' -H 'Content-Type: application/json' -H 'x-token: abc' --data-raw '{"title":"hi","done":false}' --compressed"#,
+        )
+        .unwrap();
+
+        assert_eq!(profile.method, Method::POST);
+        assert_eq!(profile.url.as_str(), "https://api.example.com/todos");
+        assert_eq!(profile.params, Some(json!({"page": 2})));
+        assert_eq!(profile.headers["x-token"], "abc");
+        assert_eq!(
+            profile.body,
+            Some(Body::Json(json!({"title": "hi", "done": false})))
+        );
+    }
+
+    #[test]
+    fn parse_curl_get_with_data_should_use_query() {
+        let profile = parse_curl(
+            "curl -G https://a.com/search -d q=rust --data-urlencode 'tag=a b' -u user:pass",
+        )
+        .unwrap();
+
+        assert_eq!(profile.method, Method::GET);
+        assert_eq!(profile.params, Some(json!({"q": "rust", "tag": "a b"})));
+        assert_eq!(profile.headers[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+        assert_eq!(profile.body, None);
+    }
+
+    #[test]
+    fn parse_curl_form_should_work() {
+        let profile = parse_curl("curl -XPUT https://a.com/x -d a=1 -d b=two").unwrap();
+
+        assert_eq!(profile.method, Method::PUT);
+        assert_eq!(
+            profile.headers[CONTENT_TYPE],
+            "application/x-www-form-urlencoded"
+        );
+        assert_eq!(
+            profile.body,
+            Some(Body::Json(json!({"a": "1", "b": "two"})))
+        );
+
+        let profile = parse_curl(
+            "curl https://a.com/x -F name=misky -F 'photo=@cat.png;type=image/png;filename=misky.png'",
+        )
+        .unwrap();
+
+        assert_eq!(profile.method, Method::POST);
+        assert_eq!(
+            profile.body,
+            Some(Body::Multipart(vec![
+                Part {
+                    name: "name".into(),
+                    value: Some("misky".into()),
+                    ..Default::default()
+                },
+                Part {
+                    name: "photo".into(),
+                    file: Some("cat.png".into()),
+                    filename: Some("misky.png".into()),
+                    content_type: Some("image/png".into()),
+                    ..Default::default()
+                },
+            ]))
+        );
+        assert!(parse_curl("curl https://a.com/x -F 'note=<note.txt'").is_err());
+    }
+
+    #[test]
+    fn parse_curl_should_skip_unknown_option_values() {
+        let profile = parse_curl(
+            "curl --cookie-jar jar.txt --resolve a.com:443:127.0.0.1 -o out --connect-timeout 5 https://a.com/x --unknown value",
+        )
+        .unwrap();
+
+        assert_eq!(profile.url.as_str(), "https://a.com/x");
+
+        let profile = parse_curl("curl https://a.com/x --url https://b.com/y").unwrap();
+        assert_eq!(profile.url.as_str(), "https://b.com/y");
+    }
+
+    #[test]
+    fn url_encode_data_should_follow_curl() {
+        assert_eq!(url_encode_data("a b&c").unwrap(), "a%20b%26c");
+        assert_eq!(url_encode_data("=a b").unwrap(), "a%20b");
+        assert_eq!(url_encode_data("q=a=b@c").unwrap(), "q=a%3Db%40c");
+
+        let path = std::env::temp_dir().join(format!("xdiff-urlencode-{}", std::process::id()));
+        std::fs::write(&path, "x y").unwrap();
+        let file = path.display();
+        assert_eq!(url_encode_data(&format!("f@{}", file)).unwrap(), "f=x%20y");
+        assert_eq!(url_encode_data(&format!("@{}", file)).unwrap(), "x%20y");
+        std::fs::remove_file(path).unwrap();
+
+        assert!(url_encode_data("f@-").is_err());
+    }
+
+    #[test]
+    fn parse_curl_data_file_should_follow_curl() {
+        let path = std::env::temp_dir().join(format!("xdiff-data-{}", std::process::id()));
+        std::fs::write(&path, "a=1\r\n&b=2\n").unwrap();
+        let file = path.display();
+
+        let cmd = "curl https://a.com/x -H 'content-type: text/plain'";
+
+        let profile = parse_curl(&format!("{} -d @{}", cmd, file)).unwrap();
+        assert_eq!(profile.body, Some(Body::Raw("a=1&b=2".into())));
+
+        let profile = parse_curl(&format!("{} --data-binary @{}", cmd, file)).unwrap();
+        assert_eq!(profile.body, Some(Body::Raw("a=1\r\n&b=2\n".into())));
+        std::fs::remove_file(path).unwrap();
+
+        let err = parse_curl("curl https://a.com/x -d @-").unwrap_err();
+        assert!(err.to_string().contains("stdin"));
+        assert!(parse_curl("curl https://a.com/x --data-binary @-").is_err());
+    }
+}
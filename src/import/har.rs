@@ -0,0 +1,399 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, Url,
+};
+use serde::Deserialize;
+use serde_json::Map;
+use std::{collections::HashMap, str::FromStr};
+
+use super::{parse_body, profile_name, string_object, unique_name, Imported, VOLATILE_HEADERS};
+use crate::config::take_query_params;
+use crate::{Body, DiffConfig, DiffProfile, Part, RequestConfig, RequestProfile, ResponseProfile};
+
+/// Request headers that are set by the client itself and never copied to a profile.
+const DROPPED_HEADERS: &[&str] = &[
+    "accept-encoding",
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "transfer-encoding",
+];
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    #[serde(default)]
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: Option<HarResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<HarPair>,
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarResponse {
+    #[serde(default)]
+    headers: Vec<HarPair>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarPair {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarPostData {
+    mime_type: Option<String>,
+    text: Option<String>,
+    #[serde(default)]
+    params: Vec<HarParam>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarParam {
+    name: String,
+    value: Option<String>,
+    file_name: Option<String>,
+}
+
+/// Which entries of a HAR file to import.
+#[derive(Debug, Clone, Default)]
+pub struct HarFilter {
+    /// Only import entries whose url matches.
+    pub url_pattern: Option<Regex>,
+
+    /// Only import entries with this method.
+    pub method: Option<Method>,
+}
+
+/// An entry converted to a profile, with the response headers worth skipping in a diff.
+struct HarProfile {
+    name: String,
+    profile: RequestProfile,
+    skip_headers: Vec<String>,
+}
+
+/// Build a request profile for each entry of a HAR 1.2 file matching the filter.
+pub fn import_har(content: &str, filter: &HarFilter) -> Result<Imported<RequestConfig>> {
+    let (profiles, warnings) = convert(content, filter)?;
+    let profiles = profiles.into_iter().map(|p| (p.name, p.profile)).collect();
+
+    Ok(Imported {
+        config: RequestConfig::new(profiles),
+        warnings,
+    })
+}
+
+/// Build a diff profile for each entry of a HAR 1.2 file matching the filter,
+/// comparing the recorded request against the same request sent to `host`.
+///
+/// `host` is either `host[:port]`, keeping the scheme of the entry, or a base url
+/// such as `http://localhost:8080`.
+pub fn import_har_diff(
+    content: &str,
+    filter: &HarFilter,
+    host: &str,
+) -> Result<Imported<DiffConfig>> {
+    let (profiles, warnings) = convert(content, filter)?;
+
+    let mut result = HashMap::new();
+    for p in profiles {
+        let mut req2 = p.profile.clone();
+        req2.url = rewrite_host(&p.profile.url, host)?;
+        let res = ResponseProfile::new(p.skip_headers, vec![]);
+        result.insert(p.name, DiffProfile::new(p.profile, req2, res));
+    }
+
+    Ok(Imported {
+        config: DiffConfig::new(result),
+        warnings,
+    })
+}
+
+fn convert(content: &str, filter: &HarFilter) -> Result<(Vec<HarProfile>, Vec<String>)> {
+    let har: Har = serde_json::from_str(content).context("invalid HAR file")?;
+    let mut profiles: Vec<HarProfile> = vec![];
+    let mut warnings = vec![];
+
+    for (idx, entry) in har.log.entries.into_iter().enumerate() {
+        let request = &entry.request;
+        if let Some(pattern) = &filter.url_pattern {
+            if !pattern.is_match(&request.url) {
+                continue;
+            }
+        }
+        if let Some(method) = &filter.method {
+            if !request.method.eq_ignore_ascii_case(method.as_str()) {
+                continue;
+            }
+        }
+
+        let profile = match convert_request(request) {
+            Ok(profile) => profile,
+            Err(e) => {
+                warnings.push(format!(
+                    "entry {} ({} {}) skipped: {}",
+                    idx, request.method, request.url, e
+                ));
+                continue;
+            }
+        };
+
+        let name = profile_name(profile.method.as_str(), profile.url.path());
+        let name = unique_name(name, &|name| profiles.iter().any(|p| p.name == name));
+
+        let mut skip_headers: Vec<String> = vec![];
+        for header in entry.response.iter().flat_map(|res| &res.headers) {
+            let header = header.name.to_lowercase();
+            if VOLATILE_HEADERS.contains(&header.as_str()) && !skip_headers.contains(&header) {
+                skip_headers.push(header);
+            }
+        }
+
+        profiles.push(HarProfile {
+            name,
+            profile,
+            skip_headers,
+        });
+    }
+
+    Ok((profiles, warnings))
+}
+
+fn convert_request(request: &HarRequest) -> Result<RequestProfile> {
+    let method = Method::from_str(&request.method.to_uppercase())?;
+    let mut url = Url::parse(&request.url)?;
+    let params = take_query_params(&mut url);
+
+    let mut headers = HeaderMap::new();
+    for header in &request.headers {
+        let name = header.name.to_lowercase();
+        if name.starts_with(':') || DROPPED_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        headers.append(
+            HeaderName::from_str(&name)?,
+            HeaderValue::from_str(&header.value)?,
+        );
+    }
+
+    let body = match &request.post_data {
+        Some(data) => convert_post_data(data, &mut headers)?,
+        None => None,
+    };
+
+    let params = match params.as_object().map(Map::is_empty) {
+        Some(false) => Some(params),
+        _ => None,
+    };
+
+    Ok(RequestProfile::new(method, url, params, headers, body))
+}
+
+fn convert_post_data(data: &HarPostData, headers: &mut HeaderMap) -> Result<Option<Body>> {
+    if let Some(mime_type) = &data.mime_type {
+        if !headers.contains_key(CONTENT_TYPE) && !mime_type.is_empty() {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_str(mime_type)?);
+        }
+    }
+
+    let multipart = data
+        .mime_type
+        .as_deref()
+        .is_some_and(|m| m.starts_with("multipart/form-data"));
+
+    if multipart || (!data.params.is_empty() && data.text.is_none()) {
+        let mut parts = vec![];
+        for param in &data.params {
+            // a HAR only records the name of an uploaded file, not its content
+            if let Some(file_name) = &param.file_name {
+                return Err(anyhow!("file upload is not supported: {}", file_name));
+            }
+            parts.push(Part {
+                name: param.name.clone(),
+                value: Some(param.value.clone().unwrap_or_default()),
+                ..Default::default()
+            });
+        }
+        if multipart {
+            // the boundary of the recorded request is meaningless for a new one
+            headers.remove(CONTENT_TYPE);
+            return Ok(Some(Body::Multipart(parts)));
+        }
+        let pairs = parts
+            .into_iter()
+            .map(|p| (p.name, p.value.unwrap_or_default()));
+        return Ok(Some(Body::Json(string_object(pairs.collect()))));
+    }
+
+    match data.text.as_deref() {
+        Some("") | None => Ok(None),
+        Some(text) => Ok(Some(parse_body(text, headers)?)),
+    }
+}
+
+/// Point a url to another host, given as `host[:port]` or as a base url.
+fn rewrite_host(url: &Url, host: &str) -> Result<Url> {
+    let base = match host.contains("://") {
+        true => Url::parse(host)?,
+        false => Url::parse(&format!("{}://{}", url.scheme(), host))?,
+    };
+
+    let mut url = url.clone();
+    url.set_scheme(base.scheme())
+        .map_err(|_| anyhow!("invalid scheme in host: {}", host))?;
+    url.set_host(base.host_str())?;
+    url.set_port(base.port())
+        .map_err(|_| anyhow!("invalid port in host: {}", host))?;
+
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HAR: &str = r#"{
+      "log": {
+        "version": "1.2",
+        "entries": [
+          {
+            "request": {
+              "method": "GET",
+              "url": "https://api.example.com/todos?page=2",
+              "headers": [
+                {"name": ":authority", "value": "api.example.com"},
+                {"name": "Accept", "value": "application/json"},
+                {"name": "Accept-Encoding", "value": "gzip"}
+              ],
+              "queryString": [{"name": "page", "value": "2"}]
+            },
+            "response": {
+              "status": 200,
+              "headers": [
+                {"name": "Date", "value": "Mon, 01 May 2023 00:00:00 GMT"},
+                {"name": "Content-Type", "value": "application/json"},
+                {"name": "ETag", "value": "\"abc\""}
+              ]
+            }
+          },
+          {
+            "request": {
+              "method": "POST",
+              "url": "https://api.example.com/todos",
+              "headers": [{"name": "Content-Type", "value": "application/json"}],
+              "postData": {"mimeType": "application/json", "text": "{\"title\":\"hi\"}"}
+            },
+            "response": {"status": 201, "headers": []}
+          },
+          {
+            "request": {
+              "method": "POST",
+              "url": "https://api.example.com/upload",
+              "headers": [],
+              "postData": {
+                "mimeType": "multipart/form-data; boundary=xyz",
+                "params": [{"name": "file", "fileName": "a.png"}]
+              }
+            }
+          },
+          {
+            "request": {
+              "method": "GET",
+              "url": "https://cdn.example.com/app.js",
+              "headers": []
+            }
+          }
+        ]
+      }
+    }"#;
+
+    #[test]
+    fn import_har_should_work() {
+        let filter = HarFilter {
+            url_pattern: Some(Regex::new("^https://api\\.").unwrap()),
+            method: None,
+        };
+        let imported = import_har(HAR, &filter).unwrap();
+        let profiles = &imported.config.profiles;
+
+        assert_eq!(profiles.len(), 2);
+
+        let get = &profiles["get_todos"];
+        assert_eq!(get.url.as_str(), "https://api.example.com/todos");
+        assert_eq!(get.params, Some(json!({"page": 2})));
+        assert_eq!(get.headers.len(), 1);
+        assert_eq!(get.headers["accept"], "application/json");
+
+        let post = &profiles["post_todos"];
+        assert_eq!(post.method, Method::POST);
+        assert_eq!(post.body, Some(Body::Json(json!({"title": "hi"}))));
+
+        assert_eq!(imported.warnings.len(), 1);
+        assert!(imported.warnings[0].contains("file upload is not supported"));
+    }
+
+    #[test]
+    fn import_har_should_filter_by_method() {
+        let filter = HarFilter {
+            url_pattern: None,
+            method: Some(Method::GET),
+        };
+        let imported = import_har(HAR, &filter).unwrap();
+
+        let mut names: Vec<_> = imported.config.profiles.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["get_app_js", "get_todos"]);
+        assert!(imported.warnings.is_empty());
+    }
+
+    #[test]
+    fn import_har_diff_should_rewrite_host() {
+        let filter = HarFilter {
+            url_pattern: Some(Regex::new("/todos").unwrap()),
+            method: Some(Method::GET),
+        };
+        let imported = import_har_diff(HAR, &filter, "http://localhost:8080").unwrap();
+        let profile = &imported.config.profiles["get_todos"];
+
+        assert_eq!(profile.req1.url.as_str(), "https://api.example.com/todos");
+        assert_eq!(profile.req2.url.as_str(), "http://localhost:8080/todos");
+        assert_eq!(profile.req2.params, profile.req1.params);
+        assert_eq!(profile.res.skip_headers, vec!["date", "etag"]);
+    }
+
+    #[test]
+    fn rewrite_host_should_keep_scheme_without_base_url() {
+        let url = Url::parse("https://api.example.com/a?b=1").unwrap();
+
+        assert_eq!(
+            rewrite_host(&url, "staging.example.com:8443")
+                .unwrap()
+                .as_str(),
+            "https://staging.example.com:8443/a?b=1"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde_json::{json, Value};
+
+use crate::config::get_content_type;
+use crate::Body;
+
+mod curl;
+mod har;
+mod openapi;
+mod postman;
+
+pub use curl::parse_curl;
+pub use har::{import_har, import_har_diff, HarFilter};
+pub use openapi::{import_openapi, import_openapi_diff};
+pub use postman::import_postman;
+
+/// A config generated from another format, with the pieces that could not be converted.
+#[derive(Debug, Clone)]
+pub struct Imported<T> {
+    pub config: T,
+    pub warnings: Vec<String>,
+}
+
+/// Response headers that usually differ between two calls, suggested as `skip_headers`.
+const VOLATILE_HEADERS: &[&str] = &[
+    "age",
+    "cf-ray",
+    "date",
+    "etag",
+    "expires",
+    "last-modified",
+    "report-to",
+    "server-timing",
+    "set-cookie",
+    "via",
+    "x-amz-cf-id",
+    "x-cache",
+    "x-request-id",
+    "x-ratelimit-remaining",
+    "x-ratelimit-reset",
+];
+
+/// Turn a request body into a profile body, according to the content type.
+/// If no content type is set, JSON objects and urlencoded forms are detected.
+fn parse_body(text: &str, headers: &mut HeaderMap) -> Result<Body> {
+    match get_content_type(headers).as_deref() {
+        Some(ct) if ct == "application/json" || ct.ends_with("+json") => {
+            match serde_json::from_str(text)? {
+                value @ Value::Object(_) => Ok(Body::Json(value)),
+                _ => Ok(Body::Raw(text.to_string())),
+            }
+        }
+        Some("application/x-www-form-urlencoded") => {
+            Ok(Body::Json(string_object(serde_urlencoded::from_str(text)?)))
+        }
+        Some(ct) if ct.ends_with("/xml") || ct.ends_with("+xml") => Ok(Body::Xml(text.into())),
+        Some("application/x-ndjson") => {
+            let lines = text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()?;
+            Ok(Body::Ndjson(lines))
+        }
+        Some(ct) if ct.starts_with("text/") => Ok(Body::Raw(text.into())),
+        Some(content_type) => Err(anyhow!("unsupported content type: {}", content_type)),
+        None => match serde_json::from_str::<Value>(text) {
+            Ok(value @ Value::Object(_)) => {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                Ok(Body::Json(value))
+            }
+            _ => {
+                headers.insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("application/x-www-form-urlencoded"),
+                );
+                Ok(Body::Json(string_object(serde_urlencoded::from_str(text)?)))
+            }
+        },
+    }
+}
+
+fn string_object(pairs: Vec<(String, String)>) -> Value {
+    let mut obj = json!({});
+    for (k, v) in pairs {
+        obj[k] = Value::String(v);
+    }
+    obj
+}
+
+/// A profile name made of the method and the path.
+fn profile_name(method: &str, path: &str) -> String {
+    let mut name = method.to_lowercase();
+    for part in path.split(|c: char| !c.is_ascii_alphanumeric()) {
+        if !part.is_empty() {
+            name.push('_');
+            name.push_str(&part.to_lowercase());
+        }
+    }
+
+    name
+}
+
+/// Add a numeric suffix to the name until it is not `taken`.
+fn unique_name(base: String, taken: &dyn Fn(&str) -> bool) -> String {
+    let mut name = base.clone();
+    let mut idx = 1;
+    while taken(&name) {
+        idx += 1;
+        name = format!("{}_{}", base, idx);
+    }
+
+    name
+}
+
+/// Convert a name such as `getTodoById` or `Get todo` to a profile name, `get_todo_by_id`.
+fn snake_case(text: &str) -> String {
+    let mut name = String::new();
+    let mut prev_lower = false;
+
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && prev_lower {
+                name.push('_');
+            }
+            prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+            name.push(c.to_ascii_lowercase());
+        } else {
+            if !name.is_empty() && !name.ends_with('_') {
+                name.push('_');
+            }
+            prev_lower = false;
+        }
+    }
+
+    name.trim_end_matches('_').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case_should_work() {
+        assert_eq!(snake_case("getTodoById"), "get_todo_by_id");
+        assert_eq!(snake_case("list-todos v2"), "list_todos_v2");
+    }
+}
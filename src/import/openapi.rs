@@ -0,0 +1,591 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, Url,
+};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, str::FromStr};
+
+use super::{profile_name, snake_case, unique_name, Imported};
+use crate::{Body, DiffConfig, DiffProfile, RequestConfig, RequestProfile, ResponseProfile};
+
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Request body media types that can be turned into a profile body, by preference.
+const BODY_TYPES: &[&str] = &[
+    "application/json",
+    "application/x-www-form-urlencoded",
+    "multipart/form-data",
+];
+
+/// Nesting limit when generating examples, to stop at recursive schemas.
+const MAX_DEPTH: usize = 8;
+
+/// An operation converted to a profile, relative to the server url.
+struct Operation {
+    name: String,
+    path: String,
+    profile: RequestProfile,
+    tags: Vec<String>,
+}
+
+/// Build a request profile for each operation of an OpenAPI 3.x document (JSON or YAML).
+///
+/// `server` selects the base url: the description, url or index of an entry of `servers`,
+/// or an absolute url. The first server is used by default.
+pub fn import_openapi(content: &str, server: Option<&str>) -> Result<Imported<RequestConfig>> {
+    let spec = parse_spec(content)?;
+    let base = select_server(&spec, server)?;
+    let (operations, warnings) = convert(&spec, &base)?;
+
+    let profiles = operations
+        .into_iter()
+        .map(|op| (op.name, op.profile))
+        .collect();
+
+    Ok(Imported {
+        config: RequestConfig::new(profiles),
+        warnings,
+    })
+}
+
+/// Build a diff profile for each operation of an OpenAPI 3.x document, comparing
+/// `server1` against `server2`. Servers are selected as in [`import_openapi`], and
+/// the tags of the operations are kept as profile tags.
+pub fn import_openapi_diff(
+    content: &str,
+    server1: &str,
+    server2: &str,
+) -> Result<Imported<DiffConfig>> {
+    let spec = parse_spec(content)?;
+    let base1 = select_server(&spec, Some(server1))?;
+    let base2 = select_server(&spec, Some(server2))?;
+    let (operations, warnings) = convert(&spec, &base1)?;
+
+    let mut profiles = HashMap::new();
+    for op in operations {
+        let mut req2 = op.profile.clone();
+        req2.url = join_url(&base2, &op.path)?;
+
+        let mut profile = DiffProfile::new(op.profile, req2, ResponseProfile::default());
+        profile.tags = op.tags;
+        profiles.insert(op.name, profile);
+    }
+
+    Ok(Imported {
+        config: DiffConfig::new(profiles),
+        warnings,
+    })
+}
+
+fn parse_spec(content: &str) -> Result<Value> {
+    // YAML is a superset of JSON, so both formats go through the same parser
+    let spec: serde_yaml::Value =
+        serde_yaml::from_str(content).context("invalid OpenAPI document")?;
+    let spec = yaml_to_json(spec)?;
+
+    // an unquoted `openapi: 3.0` is a YAML number
+    let version = match &spec["openapi"] {
+        Value::String(version) => Some(version.clone()),
+        Value::Number(version) => Some(version.to_string()),
+        _ => None,
+    };
+
+    match version {
+        Some(version) if version.starts_with("3.") => Ok(spec),
+        Some(version) => Err(anyhow!("unsupported OpenAPI version: {}", version)),
+        None => Err(anyhow!(
+            "not an OpenAPI 3 document: missing `openapi` field"
+        )),
+    }
+}
+
+/// Convert a YAML document to JSON, turning keys such as response codes into strings.
+fn yaml_to_json(value: serde_yaml::Value) -> Result<Value> {
+    use serde_yaml::Value as Yaml;
+
+    Ok(match value {
+        Yaml::Mapping(mapping) => {
+            let mut obj = Map::new();
+            for (key, value) in mapping {
+                let key = match key {
+                    Yaml::String(s) => s,
+                    Yaml::Number(n) => n.to_string(),
+                    Yaml::Bool(b) => b.to_string(),
+                    key => return Err(anyhow!("unsupported mapping key: {:?}", key)),
+                };
+                obj.insert(key, yaml_to_json(value)?);
+            }
+            Value::Object(obj)
+        }
+        Yaml::Sequence(seq) => {
+            Value::Array(seq.into_iter().map(yaml_to_json).collect::<Result<_>>()?)
+        }
+        Yaml::Tagged(tagged) => yaml_to_json(tagged.value)?,
+        value => serde_json::to_value(value)?,
+    })
+}
+
+/// Resolve the base url of the selected server, with its variables set to their defaults.
+fn select_server(spec: &Value, name: Option<&str>) -> Result<String> {
+    if let Some(name) = name.filter(|n| n.contains("://")) {
+        return Ok(name.trim_end_matches('/').to_string());
+    }
+
+    let servers = spec["servers"].as_array().cloned().unwrap_or_default();
+    let server = match name {
+        None => servers.first(),
+        Some(name) => servers.iter().enumerate().find_map(|(idx, s)| {
+            let matched = s["description"]
+                .as_str()
+                .is_some_and(|d| d.eq_ignore_ascii_case(name))
+                || s["url"].as_str() == Some(name)
+                || name.parse::<usize>() == Ok(idx);
+            matched.then_some(s)
+        }),
+    };
+
+    let server = match (server, name) {
+        (Some(server), _) => server,
+        (None, Some(name)) => return Err(anyhow!("server not found in document: {}", name)),
+        (None, None) => return Err(anyhow!("no server in document, pass a base url instead")),
+    };
+
+    let mut url = server["url"]
+        .as_str()
+        .ok_or_else(|| anyhow!("server without url"))?
+        .to_string();
+    if let Some(variables) = server["variables"].as_object() {
+        for (key, variable) in variables {
+            let default = variable["default"].as_str().unwrap_or_default();
+            url = url.replace(&format!("{{{}}}", key), default);
+        }
+    }
+
+    if !url.contains("://") {
+        return Err(anyhow!(
+            "server url {} is relative, pass a base url instead",
+            url
+        ));
+    }
+
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+fn convert(spec: &Value, base: &str) -> Result<(Vec<Operation>, Vec<String>)> {
+    let mut operations: Vec<Operation> = vec![];
+    let mut warnings = vec![];
+
+    let paths = spec["paths"].as_object().cloned().unwrap_or_default();
+    for (path, item) in &paths {
+        let item = resolve(spec, item)?;
+        let shared = item["parameters"].as_array().cloned().unwrap_or_default();
+
+        for method in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+
+            match convert_operation(spec, base, path, method, operation, &shared) {
+                Ok((relative, profile)) => {
+                    let name = match operation["operationId"].as_str() {
+                        Some(id) => snake_case(id),
+                        None => profile_name(method, path),
+                    };
+                    let name =
+                        unique_name(name, &|name| operations.iter().any(|op| op.name == name));
+                    let tags = operation["tags"]
+                        .as_array()
+                        .map(|tags| {
+                            tags.iter()
+                                .filter_map(|t| t.as_str().map(String::from))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+
+                    operations.push(Operation {
+                        name,
+                        path: relative,
+                        profile,
+                        tags,
+                    });
+                }
+                Err(e) => warnings.push(format!(
+                    "operation {} {} skipped: {}",
+                    method.to_uppercase(),
+                    path,
+                    e
+                )),
+            }
+        }
+    }
+
+    Ok((operations, warnings))
+}
+
+/// Convert an operation into a profile, returning the path relative to the server
+/// with the path parameters filled in.
+fn convert_operation(
+    spec: &Value,
+    base: &str,
+    path: &str,
+    method: &str,
+    operation: &Value,
+    shared: &[Value],
+) -> Result<(String, RequestProfile)> {
+    let method = Method::from_str(&method.to_uppercase())?;
+
+    // operation parameters override the ones of the path item, by name and location
+    let mut parameters: Vec<&Value> = vec![];
+    let own = operation["parameters"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    for param in shared.iter().chain(own.iter()) {
+        let param = resolve(spec, param)?;
+        parameters.retain(|p| p["name"] != param["name"] || p["in"] != param["in"]);
+        parameters.push(param);
+    }
+
+    let mut relative = path.to_string();
+    let mut params = Map::new();
+    let mut headers = HeaderMap::new();
+
+    for param in parameters {
+        let name = param["name"]
+            .as_str()
+            .ok_or_else(|| anyhow!("parameter without name"))?;
+        let value = parameter_example(spec, param)?;
+
+        match param["in"].as_str() {
+            Some("path") => {
+                relative = relative.replace(&format!("{{{}}}", name), &plain_string(&value));
+            }
+            Some("query") => {
+                params.insert(name.to_string(), value);
+            }
+            Some("header") => {
+                headers.insert(
+                    HeaderName::from_str(name)?,
+                    HeaderValue::from_str(&plain_string(&value))?,
+                );
+            }
+            Some(location) => {
+                return Err(anyhow!("unsupported parameter location: {}", location));
+            }
+            None => return Err(anyhow!("parameter without location: {}", name)),
+        }
+    }
+
+    let body = match operation.get("requestBody") {
+        Some(body) => {
+            let body = resolve(spec, body)?;
+            let content = body["content"].as_object().cloned().unwrap_or_default();
+            let media = BODY_TYPES
+                .iter()
+                .find_map(|t| content.get(*t).map(|m| (t, m)));
+
+            match (media, content.keys().next()) {
+                (Some((content_type, media)), _) => {
+                    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+                    Some(Body::Json(media_example(spec, media)?))
+                }
+                (None, Some(content_type)) => {
+                    return Err(anyhow!("unsupported content type: {}", content_type))
+                }
+                (None, None) => None,
+            }
+        }
+        None => None,
+    };
+
+    let params = match params.is_empty() {
+        true => None,
+        false => Some(Value::Object(params)),
+    };
+    let url = join_url(base, &relative)?;
+
+    Ok((
+        relative,
+        RequestProfile::new(method, url, params, headers, body),
+    ))
+}
+
+fn join_url(base: &str, path: &str) -> Result<Url> {
+    Url::parse(&format!("{}{}", base, path))
+        .with_context(|| format!("invalid url: {}{}", base, path))
+}
+
+/// Follow local `$ref`s, such as `#/components/schemas/Todo`.
+fn resolve<'a>(spec: &'a Value, mut value: &'a Value) -> Result<&'a Value> {
+    for _ in 0..MAX_DEPTH {
+        let Some(reference) = value["$ref"].as_str() else {
+            return Ok(value);
+        };
+        let pointer = reference
+            .strip_prefix('#')
+            .ok_or_else(|| anyhow!("external reference is not supported: {}", reference))?;
+        value = spec
+            .pointer(pointer)
+            .ok_or_else(|| anyhow!("unresolved reference: {}", reference))?;
+    }
+
+    Err(anyhow!("too many nested references"))
+}
+
+fn parameter_example(spec: &Value, param: &Value) -> Result<Value> {
+    if let Some(example) = explicit_example(spec, param)? {
+        return Ok(example);
+    }
+
+    schema_example(spec, &param["schema"], 0)
+}
+
+fn media_example(spec: &Value, media: &Value) -> Result<Value> {
+    if let Some(example) = explicit_example(spec, media)? {
+        return Ok(example);
+    }
+
+    schema_example(spec, &media["schema"], 0)
+}
+
+/// The `example`, or the first of the `examples`, of a parameter or media type.
+fn explicit_example(spec: &Value, value: &Value) -> Result<Option<Value>> {
+    if let Some(example) = value.get("example") {
+        return Ok(Some(example.clone()));
+    }
+
+    match value["examples"]
+        .as_object()
+        .and_then(|e| e.values().next())
+    {
+        Some(example) => Ok(resolve(spec, example)?.get("value").cloned()),
+        None => Ok(None),
+    }
+}
+
+/// Generate a value matching the schema, preferring the examples and defaults it declares.
+fn schema_example(spec: &Value, schema: &Value, depth: usize) -> Result<Value> {
+    if depth > MAX_DEPTH {
+        return Ok(Value::Null);
+    }
+
+    let schema = resolve(spec, schema)?;
+    for key in ["example", "default"] {
+        if let Some(value) = schema.get(key) {
+            return Ok(value.clone());
+        }
+    }
+    if let Some(value) = schema["enum"].as_array().and_then(|e| e.first()) {
+        return Ok(value.clone());
+    }
+
+    if let Some(all) = schema["allOf"].as_array() {
+        let mut merged = json!({});
+        for schema in all {
+            if let Value::Object(obj) = schema_example(spec, schema, depth + 1)? {
+                merged.as_object_mut().unwrap().extend(obj);
+            }
+        }
+        return Ok(merged);
+    }
+    for key in ["oneOf", "anyOf"] {
+        if let Some(first) = schema[key].as_array().and_then(|s| s.first()) {
+            return schema_example(spec, first, depth + 1);
+        }
+    }
+
+    let kind = match &schema["type"] {
+        // OpenAPI 3.1 allows a list of types, such as `[string, "null"]`
+        Value::Array(types) => types
+            .iter()
+            .find_map(|t| t.as_str().filter(|t| *t != "null")),
+        value => value.as_str(),
+    };
+
+    let value = match kind {
+        Some("object") | None if schema.get("properties").is_some() => {
+            let mut obj = Map::new();
+            if let Some(properties) = schema["properties"].as_object() {
+                for (key, property) in properties {
+                    obj.insert(key.clone(), schema_example(spec, property, depth + 1)?);
+                }
+            }
+            Value::Object(obj)
+        }
+        Some("object") => json!({}),
+        Some("array") => json!([schema_example(spec, &schema["items"], depth + 1)?]),
+        Some("string") => match schema["format"].as_str() {
+            Some("date-time") => json!("2023-01-01T00:00:00Z"),
+            Some("date") => json!("2023-01-01"),
+            Some("uuid") => json!("00000000-0000-0000-0000-000000000000"),
+            Some("email") => json!("user@example.com"),
+            Some("uri") => json!("https://example.com"),
+            _ => json!("string"),
+        },
+        Some("integer") | Some("number") => json!(0),
+        Some("boolean") => json!(false),
+        _ => Value::Null,
+    };
+
+    Ok(value)
+}
+
+/// A value as it appears in a url or a header, without the quotes of JSON strings.
+fn plain_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r##"
+openapi: 3.0.3
+info:
+  title: Todo
+  version: "1.0"
+servers:
+  - url: https://{env}.example.com/v1
+    description: staging
+    variables:
+      env:
+        default: staging
+  - url: https://api.example.com/v1
+    description: prod
+paths:
+  /todos:
+    get:
+      operationId: listTodos
+      responses:
+        200:
+          description: ok
+  /todos/{id}:
+    parameters:
+      - $ref: "#/components/parameters/TodoId"
+    get:
+      operationId: getTodoById
+      tags: [todo]
+      parameters:
+        - name: fields
+          in: query
+          schema:
+            type: string
+            enum: [title, done]
+        - name: x-trace
+          in: header
+          example: abc
+    put:
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/Todo"
+  /upload:
+    post:
+      requestBody:
+        content:
+          application/octet-stream: {}
+components:
+  parameters:
+    TodoId:
+      name: id
+      in: path
+      required: true
+      schema:
+        type: integer
+        example: 42
+  schemas:
+    Todo:
+      type: object
+      properties:
+        title:
+          type: string
+        done:
+          type: boolean
+        due:
+          type: string
+          format: date
+        tags:
+          type: array
+          items:
+            type: string
+"##;
+
+    #[test]
+    fn import_openapi_should_work() {
+        let imported = import_openapi(SPEC, None).unwrap();
+        let profiles = &imported.config.profiles;
+
+        assert_eq!(profiles.len(), 3);
+        assert_eq!(
+            profiles["list_todos"].url.as_str(),
+            "https://staging.example.com/v1/todos"
+        );
+
+        let get = &profiles["get_todo_by_id"];
+        assert_eq!(get.method, Method::GET);
+        assert_eq!(get.url.as_str(), "https://staging.example.com/v1/todos/42");
+        assert_eq!(get.params, Some(json!({"fields": "title"})));
+        assert_eq!(get.headers["x-trace"], "abc");
+
+        let put = &profiles["put_todos_id"];
+        assert_eq!(put.headers[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            put.body,
+            Some(Body::Json(
+                json!({"title": "string", "done": false, "due": "2023-01-01", "tags": ["string"]})
+            ))
+        );
+
+        assert_eq!(imported.warnings.len(), 1);
+        assert!(imported.warnings[0].contains("application/octet-stream"));
+    }
+
+    #[test]
+    fn import_openapi_diff_should_pair_servers() {
+        let imported = import_openapi_diff(SPEC, "staging", "prod").unwrap();
+        let profile = &imported.config.profiles["get_todo_by_id"];
+
+        assert_eq!(
+            profile.req1.url.as_str(),
+            "https://staging.example.com/v1/todos/42"
+        );
+        assert_eq!(
+            profile.req2.url.as_str(),
+            "https://api.example.com/v1/todos/42"
+        );
+        assert_eq!(profile.req2.params, profile.req1.params);
+        assert_eq!(profile.tags, vec!["todo"]);
+    }
+
+    #[test]
+    fn select_server_should_work() {
+        let spec = parse_spec(SPEC).unwrap();
+
+        assert_eq!(
+            select_server(&spec, Some("1")).unwrap(),
+            "https://api.example.com/v1"
+        );
+        assert_eq!(
+            select_server(&spec, Some("http://localhost:8080/")).unwrap(),
+            "http://localhost:8080"
+        );
+        assert!(select_server(&spec, Some("dev")).is_err());
+        assert_eq!(
+            parse_spec("openapi: 2.0\n").unwrap_err().to_string(),
+            "unsupported OpenAPI version: 2.0"
+        );
+        assert!(parse_spec("openapi: 3.0\npaths: {}\n").is_ok());
+        assert!(parse_spec("openapi: 3.1\npaths: {}\n").is_ok());
+        assert_eq!(
+            parse_spec("swagger: '2.0'\n").unwrap_err().to_string(),
+            "not an OpenAPI 3 document: missing `openapi` field"
+        );
+    }
+}
@@ -0,0 +1,729 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, Url,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, str::FromStr};
+
+use super::{parse_body, snake_case, string_object, unique_name, Imported};
+use crate::config::take_query_params;
+use crate::{
+    ApiKeyLocation, Auth, Body, OAuth2ClientCredentials, Part, RequestConfig, RequestProfile,
+};
+
+#[derive(Debug, Deserialize)]
+struct Collection {
+    #[serde(default)]
+    item: Vec<Item>,
+    auth: Option<AuthBlock>,
+    #[serde(default)]
+    event: Vec<Event>,
+    #[serde(default)]
+    variable: Vec<Variable>,
+}
+
+/// A request, or a folder of items.
+#[derive(Debug, Deserialize)]
+struct Item {
+    #[serde(default)]
+    name: String,
+    request: Option<Request>,
+    #[serde(default)]
+    item: Vec<Item>,
+    auth: Option<AuthBlock>,
+    #[serde(default)]
+    event: Vec<Event>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    method: Option<String>,
+    url: Option<RequestUrl>,
+    #[serde(default)]
+    header: Vec<Pair>,
+    body: Option<RequestBody>,
+    auth: Option<AuthBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RequestUrl {
+    Raw(String),
+    Parts {
+        raw: Option<String>,
+        protocol: Option<String>,
+        #[serde(default)]
+        host: Vec<String>,
+        #[serde(default)]
+        path: Vec<String>,
+        #[serde(default)]
+        query: Vec<Pair>,
+        #[serde(default)]
+        variable: Vec<Variable>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct Pair {
+    key: String,
+    value: Option<String>,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// Path of the uploaded file of a `file` form field.
+    src: Option<Value>,
+    #[serde(rename = "contentType")]
+    content_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Variable {
+    key: String,
+    value: Option<Value>,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestBody {
+    mode: Option<String>,
+    raw: Option<String>,
+    #[serde(default)]
+    urlencoded: Vec<Pair>,
+    #[serde(default)]
+    formdata: Vec<Pair>,
+    graphql: Option<GraphQl>,
+    options: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQl {
+    query: String,
+    variables: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(flatten)]
+    params: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    listen: String,
+    script: Option<Value>,
+}
+
+/// An environment exported from Postman, whose values override the collection variables.
+#[derive(Debug, Deserialize)]
+struct Environment {
+    #[serde(default)]
+    values: Vec<Variable>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// State shared while walking the folders of a collection.
+struct Walker<'a> {
+    vars: &'a HashMap<String, String>,
+    profiles: HashMap<String, RequestProfile>,
+    warnings: Vec<String>,
+}
+
+/// Build a request profile for each request of a Postman v2.1 collection.
+///
+/// Folders become prefixes of the profile names. Collection and environment variables
+/// become config `vars`, and `{{name}}` references to them are kept in headers, params,
+/// bodies and `auth:` blocks; urls are resolved on import. Basic, bearer, api key and
+/// OAuth2 client credentials auth are carried over. Everything else, such as scripts,
+/// is reported as a warning.
+pub fn import_postman(
+    collection: &str,
+    environment: Option<&str>,
+) -> Result<Imported<RequestConfig>> {
+    let collection: Collection =
+        serde_json::from_str(collection).context("invalid Postman collection")?;
+
+    let mut vars = variables(&collection.variable);
+    if let Some(environment) = environment {
+        let environment: Environment =
+            serde_json::from_str(environment).context("invalid Postman environment")?;
+        vars.extend(variables(&environment.values));
+    }
+    resolve_nested_vars(&mut vars);
+
+    let mut ctx = Walker {
+        vars: &vars,
+        profiles: HashMap::new(),
+        warnings: vec![],
+    };
+
+    check_events(&collection.event, "collection", &mut ctx.warnings);
+    walk(&collection.item, "", collection.auth.as_ref(), &mut ctx);
+
+    let Walker {
+        profiles,
+        mut warnings,
+        ..
+    } = ctx;
+
+    let mut config = RequestConfig::new(profiles);
+    config.vars = vars;
+
+    for name in undefined_vars(&config)? {
+        warnings.push(format!(
+            "variable `{}` is not defined, add it to `vars`",
+            name
+        ));
+    }
+
+    Ok(Imported { config, warnings })
+}
+
+fn walk(items: &[Item], prefix: &str, auth: Option<&AuthBlock>, ctx: &mut Walker) {
+    for item in items {
+        let name = match (prefix, snake_case(&item.name)) {
+            (prefix, name) if name.is_empty() => format!("{}request", prefix),
+            (prefix, name) => format!("{}{}", prefix, name),
+        };
+        let label = if item.name.is_empty() {
+            &name
+        } else {
+            &item.name
+        };
+        let auth = inherit(item.auth.as_ref(), auth);
+
+        check_events(&item.event, &format!("`{}`", label), &mut ctx.warnings);
+
+        match &item.request {
+            Some(request) => {
+                let auth = inherit(request.auth.as_ref(), auth);
+                match convert_request(request, auth, ctx) {
+                    Ok(profile) => {
+                        let profiles = &ctx.profiles;
+                        let name = unique_name(name, &|name| profiles.contains_key(name));
+                        ctx.profiles.insert(name, profile);
+                    }
+                    Err(e) => ctx
+                        .warnings
+                        .push(format!("request `{}` skipped: {}", label, e)),
+                }
+            }
+            None => walk(&item.item, &format!("{}_", name), auth, ctx),
+        }
+    }
+}
+
+/// The auth of an item, or the one of its parent if it has none or inherits it.
+fn inherit<'a>(own: Option<&'a AuthBlock>, parent: Option<&'a AuthBlock>) -> Option<&'a AuthBlock> {
+    match own {
+        Some(auth) if auth.kind != "inherit" => Some(auth),
+        _ => parent,
+    }
+}
+
+fn convert_request(
+    request: &Request,
+    auth: Option<&AuthBlock>,
+    ctx: &mut Walker,
+) -> Result<RequestProfile> {
+    let method = request.method.as_deref().unwrap_or("GET").to_uppercase();
+    let method = Method::from_str(&method)?;
+
+    let url = request.url.as_ref().ok_or_else(|| anyhow!("no url"))?;
+    let mut url = resolve_url(url, ctx.vars)?;
+    let params = take_query_params(&mut url);
+
+    let mut headers = HeaderMap::new();
+    for header in request.header.iter().filter(|h| !h.disabled) {
+        headers.append(
+            HeaderName::from_str(&header.key)?,
+            HeaderValue::from_str(header.value.as_deref().unwrap_or_default())?,
+        );
+    }
+
+    let auth = match auth {
+        Some(auth) => convert_auth(auth, ctx)?,
+        None => None,
+    };
+
+    let body = match &request.body {
+        Some(body) => convert_body(body, &mut headers)?,
+        None => None,
+    };
+
+    let params = match params.as_object().map(Map::is_empty) {
+        Some(false) => Some(params),
+        _ => None,
+    };
+
+    let mut profile = RequestProfile::new(method, url, params, headers, body);
+    profile.auth = auth;
+
+    Ok(profile)
+}
+
+/// Build the url of a request, with the variables and the `:name` path variables resolved.
+fn resolve_url(url: &RequestUrl, vars: &HashMap<String, String>) -> Result<Url> {
+    let (raw, path_vars) = match url {
+        RequestUrl::Raw(raw) => (raw.clone(), &[][..]),
+        RequestUrl::Parts {
+            raw: Some(raw),
+            variable,
+            ..
+        } => (raw.clone(), &variable[..]),
+        RequestUrl::Parts {
+            raw: None,
+            protocol,
+            host,
+            path,
+            query,
+            variable,
+        } => {
+            let mut raw = format!(
+                "{}://{}/{}",
+                protocol.as_deref().unwrap_or("http"),
+                host.join("."),
+                path.join("/")
+            );
+            let query: Vec<_> = query.iter().filter(|q| !q.disabled).collect();
+            for (idx, pair) in query.iter().enumerate() {
+                raw.push(if idx == 0 { '?' } else { '&' });
+                raw.push_str(&pair.key);
+                raw.push('=');
+                raw.push_str(pair.value.as_deref().unwrap_or_default());
+            }
+            (raw, &variable[..])
+        }
+    };
+
+    let mut raw = substitute(&raw, vars)?;
+    for var in path_vars {
+        let value = var.value.as_ref().map(plain_string).unwrap_or_default();
+        let value = substitute(&value, vars)?;
+        raw = raw.replace(&format!(":{}", var.key), &value);
+    }
+
+    // Postman sends urls without a scheme over http
+    if !raw.contains("://") {
+        raw = format!("http://{}", raw);
+    }
+
+    Url::parse(&raw).with_context(|| format!("invalid url: {}", raw))
+}
+
+/// Convert the auth of a request to an `auth:` block, keeping the variable references.
+fn convert_auth(auth: &AuthBlock, ctx: &mut Walker) -> Result<Option<Auth>> {
+    let get = |key: &str| -> String {
+        auth.params
+            .get(&auth.kind)
+            .and_then(Value::as_array)
+            .and_then(|params| params.iter().find(|p| p["key"] == key))
+            .map(|p| plain_string(&p["value"]))
+            .unwrap_or_default()
+    };
+
+    let auth = match auth.kind.as_str() {
+        "noauth" => None,
+        "basic" => Some(Auth::Basic {
+            username: get("username"),
+            password: get("password"),
+        }),
+        "bearer" => Some(Auth::Bearer {
+            token: get("token"),
+        }),
+        "apikey" => Some(Auth::ApiKey {
+            name: get("key"),
+            value: get("value"),
+            location: match get("in").as_str() {
+                "query" => ApiKeyLocation::Query,
+                _ => ApiKeyLocation::Header,
+            },
+        }),
+        "oauth2" if get("grant_type") == "client_credentials" => {
+            // the token url is parsed on import, like the request urls
+            let token_url = substitute(&get("accessTokenUrl"), ctx.vars)?;
+            let scope = Some(get("scope")).filter(|s| !s.is_empty());
+            Some(Auth::Oauth2ClientCredentials(OAuth2ClientCredentials {
+                token_url: Url::parse(&token_url)
+                    .with_context(|| format!("invalid token url: {}", token_url))?,
+                client_id: get("clientId"),
+                client_secret: get("clientSecret"),
+                scope,
+                audience: None,
+                credentials_in_body: get("client_authentication") == "body",
+            }))
+        }
+        kind => {
+            ctx.warnings
+                .push(format!("auth type `{}` is not supported, skipped", kind));
+            None
+        }
+    };
+
+    Ok(auth)
+}
+
+fn convert_body(body: &RequestBody, headers: &mut HeaderMap) -> Result<Option<Body>> {
+    match body.mode.as_deref() {
+        Some("raw") => {
+            let raw = body.raw.as_deref().unwrap_or_default();
+            if raw.trim().is_empty() {
+                return Ok(None);
+            }
+            let language = body
+                .options
+                .as_ref()
+                .and_then(|o| o["raw"]["language"].as_str());
+            let content_type = match language {
+                Some("json") => "application/json",
+                Some("xml") => "application/xml",
+                _ => "text/plain",
+            };
+            if language.is_some() && !headers.contains_key(CONTENT_TYPE) {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            }
+            Ok(Some(parse_body(raw, headers)?))
+        }
+        Some("urlencoded") => {
+            headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/x-www-form-urlencoded"),
+            );
+            Ok(Some(Body::Json(string_object(enabled_pairs(
+                &body.urlencoded,
+            )?))))
+        }
+        Some("formdata") => Ok(Some(Body::Multipart(form_parts(&body.formdata)?))),
+        Some("graphql") => {
+            let graphql = body
+                .graphql
+                .as_ref()
+                .ok_or_else(|| anyhow!("graphql body without query"))?;
+            let variables = match graphql.variables.as_deref().map(str::trim) {
+                Some("") | None => json!({}),
+                Some(variables) => serde_json::from_str(variables)?,
+            };
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            Ok(Some(Body::Json(
+                json!({"query": graphql.query, "variables": variables}),
+            )))
+        }
+        Some(mode) => Err(anyhow!("unsupported body mode: {}", mode)),
+        None => Ok(None),
+    }
+}
+
+fn enabled_pairs(pairs: &[Pair]) -> Result<Vec<(String, String)>> {
+    let mut result = vec![];
+    for pair in pairs.iter().filter(|p| !p.disabled) {
+        result.push((pair.key.clone(), pair.value.clone().unwrap_or_default()));
+    }
+
+    Ok(result)
+}
+
+fn form_parts(pairs: &[Pair]) -> Result<Vec<Part>> {
+    let mut parts = vec![];
+    for pair in pairs.iter().filter(|p| !p.disabled) {
+        let mut part = Part {
+            name: pair.key.clone(),
+            content_type: pair.content_type.clone(),
+            ..Default::default()
+        };
+        match pair.kind.as_deref() {
+            Some("file") => match &pair.src {
+                Some(Value::String(src)) => part.file = Some(src.into()),
+                _ => return Err(anyhow!("form field {} needs a single file", pair.key)),
+            },
+            _ => part.value = Some(pair.value.clone().unwrap_or_default()),
+        }
+        parts.push(part);
+    }
+
+    Ok(parts)
+}
+
+fn check_events(events: &[Event], owner: &str, warnings: &mut Vec<String>) {
+    for event in events {
+        let empty = match &event.script {
+            Some(script) => match &script["exec"] {
+                Value::Array(lines) => lines.iter().all(|l| l.as_str() == Some("")),
+                Value::String(s) => s.trim().is_empty(),
+                _ => true,
+            },
+            None => true,
+        };
+        if empty {
+            continue;
+        }
+
+        let kind = match event.listen.as_str() {
+            "prerequest" => "pre-request script",
+            "test" => "test script",
+            _ => "script",
+        };
+        warnings.push(format!("{} of {} is not supported, skipped", kind, owner));
+    }
+}
+
+fn variables(vars: &[Variable]) -> HashMap<String, String> {
+    vars.iter()
+        .filter(|v| v.enabled)
+        .map(|v| {
+            let value = v.value.as_ref().map(plain_string).unwrap_or_default();
+            (v.key.clone(), value)
+        })
+        .collect()
+}
+
+/// Resolve variables referencing other variables, as config `vars` can't.
+fn resolve_nested_vars(vars: &mut HashMap<String, String>) {
+    for _ in 0..8 {
+        let snapshot = vars.clone();
+        let mut changed = false;
+        for value in vars.values_mut() {
+            if let Ok(resolved) = substitute(value, &snapshot) {
+                changed |= resolved != *value;
+                *value = resolved;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Replace the `{{name}}` references to known variables.
+fn substitute(s: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut missing = None;
+    let result = var_regex().replace_all(s, |caps: &regex::Captures| {
+        let name = &caps[1];
+        match vars.get(name) {
+            Some(value) => value.clone(),
+            None => {
+                missing.get_or_insert_with(|| name.to_string());
+                caps[0].to_string()
+            }
+        }
+    });
+
+    match missing {
+        Some(name) if name.starts_with('$') => Err(anyhow!(
+            "dynamic variable `{{{{{}}}}}` is not supported",
+            name
+        )),
+        Some(name) => Err(anyhow!("undefined variable `{{{{{}}}}}`", name)),
+        None => Ok(result.into_owned()),
+    }
+}
+
+/// The variables referenced by the profiles but not defined.
+fn undefined_vars(config: &RequestConfig) -> Result<Vec<String>> {
+    let text = serde_json::to_string(&config.profiles)?;
+    let mut names: Vec<String> = var_regex()
+        .captures_iter(&text)
+        .map(|caps| caps[1].to_string())
+        .filter(|name| !config.vars.contains_key(name))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    Ok(names)
+}
+
+fn var_regex() -> Regex {
+    Regex::new(r"\{\{\s*([^{}\s]+)\s*\}\}").unwrap()
+}
+
+fn plain_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLLECTION: &str = r#"{
+      "info": {
+        "name": "Todo",
+        "schema": "https://schema.getpostman.com/json/collection/v2.1.0/collection.json"
+      },
+      "auth": {
+        "type": "bearer",
+        "bearer": [{"key": "token", "value": "{{token}}", "type": "string"}]
+      },
+      "variable": [
+        {"key": "baseUrl", "value": "https://{{host}}/v1"},
+        {"key": "host", "value": "api.example.com"},
+        {"key": "token", "value": "secret"}
+      ],
+      "item": [
+        {
+          "name": "Todos",
+          "event": [{"listen": "prerequest", "script": {"exec": ["pm.variables.set('a', 1)"]}}],
+          "item": [
+            {
+              "name": "Get todo",
+              "request": {
+                "method": "GET",
+                "header": [
+                  {"key": "x-trace", "value": "{{trace}}"},
+                  {"key": "x-off", "value": "1", "disabled": true}
+                ],
+                "url": {
+                  "raw": "{{baseUrl}}/todos/:id?fields=title",
+                  "host": ["{{baseUrl}}"],
+                  "path": ["todos", ":id"],
+                  "variable": [{"key": "id", "value": "42"}]
+                }
+              }
+            },
+            {
+              "name": "Create todo",
+              "request": {
+                "method": "POST",
+                "auth": {
+                  "type": "apikey",
+                  "apikey": [
+                    {"key": "key", "value": "api_key"},
+                    {"key": "value", "value": "{{token}}"},
+                    {"key": "in", "value": "query"}
+                  ]
+                },
+                "body": {
+                  "mode": "raw",
+                  "raw": "{\"title\": \"{{title}}\"}",
+                  "options": {"raw": {"language": "json"}}
+                },
+                "url": "{{baseUrl}}/todos"
+              }
+            }
+          ]
+        },
+        {
+          "name": "Login",
+          "request": {
+            "method": "POST",
+            "auth": {
+              "type": "basic",
+              "basic": [
+                {"key": "username", "value": "user"},
+                {"key": "password", "value": "pass"}
+              ]
+            },
+            "body": {
+              "mode": "urlencoded",
+              "urlencoded": [{"key": "remember", "value": "true"}]
+            },
+            "url": "{{baseUrl}}/login"
+          }
+        },
+        {
+          "name": "Upload",
+          "request": {
+            "method": "POST",
+            "body": {"mode": "file", "file": {"src": "a.png"}},
+            "url": "{{baseUrl}}/upload"
+          }
+        }
+      ]
+    }"#;
+
+    #[test]
+    fn import_postman_should_work() {
+        let imported = import_postman(COLLECTION, None).unwrap();
+        let profiles = &imported.config.profiles;
+
+        let mut names: Vec<_> = profiles.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["login", "todos_create_todo", "todos_get_todo"]);
+
+        let get = &profiles["todos_get_todo"];
+        assert_eq!(get.url.as_str(), "https://api.example.com/v1/todos/42");
+        assert_eq!(get.params, Some(json!({"fields": "title"})));
+        assert_eq!(get.headers["x-trace"], "{{trace}}");
+        assert_eq!(get.headers.len(), 1);
+        assert_eq!(
+            get.auth,
+            Some(Auth::Bearer {
+                token: "{{token}}".into()
+            })
+        );
+
+        let create = &profiles["todos_create_todo"];
+        assert_eq!(create.body, Some(Body::Json(json!({"title": "{{title}}"}))));
+        assert_eq!(
+            create.auth,
+            Some(Auth::ApiKey {
+                name: "api_key".into(),
+                value: "{{token}}".into(),
+                location: ApiKeyLocation::Query
+            })
+        );
+
+        let login = &profiles["login"];
+        assert_eq!(
+            login.auth,
+            Some(Auth::Basic {
+                username: "user".into(),
+                password: "pass".into()
+            })
+        );
+        assert_eq!(login.body, Some(Body::Json(json!({"remember": "true"}))));
+
+        assert_eq!(
+            imported.config.vars["baseUrl"],
+            "https://api.example.com/v1"
+        );
+        assert_eq!(
+            imported.warnings,
+            vec![
+                "pre-request script of `Todos` is not supported, skipped",
+                "request `Upload` skipped: unsupported body mode: file",
+                "variable `title` is not defined, add it to `vars`",
+                "variable `trace` is not defined, add it to `vars`",
+            ]
+        );
+    }
+
+    #[test]
+    fn import_postman_environment_should_override_variables() {
+        let environment = r#"{"name": "staging", "values": [
+            {"key": "host", "value": "staging.example.com", "enabled": true},
+            {"key": "trace", "value": "t", "enabled": true},
+            {"key": "title", "value": "x", "enabled": false}
+        ]}"#;
+        let imported = import_postman(COLLECTION, Some(environment)).unwrap();
+
+        assert_eq!(
+            imported.config.profiles["login"].url.as_str(),
+            "https://staging.example.com/v1/login"
+        );
+        assert_eq!(imported.config.vars["trace"], "t");
+        assert!(!imported.config.vars.contains_key("title"));
+    }
+
+    #[test]
+    fn substitute_should_reject_unknown_variables() {
+        let vars = HashMap::from([("a".to_string(), "1".to_string())]);
+
+        assert_eq!(substitute("x{{ a }}y", &vars).unwrap(), "x1y");
+        assert!(substitute("{{b}}", &vars).is_err());
+        assert!(substitute("{{$guid}}", &vars)
+            .unwrap_err()
+            .to_string()
+            .contains("dynamic variable"));
+    }
+}
@@ -0,0 +1,579 @@
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A path into a JSON document.
+///
+/// Two notations are accepted:
+/// - JSONPath style: `data.items[*].updated_at`, `$.data['key.with.dots']`, `[0].id`
+/// - JSON Pointer with wildcards: `/data/items/*/updated_at`
+///
+/// `*` matches every member of an object or every element of an array.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// An object member. A numeric key also matches the array element at that index.
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl JsonPath {
+    pub fn new(segments: Vec<Segment>) -> Self {
+        Self { segments }
+    }
+
+    /// A new path pointing to a child of this one.
+    pub fn join(&self, segment: Segment) -> Self {
+        let mut segments = self.segments.clone();
+        segments.push(segment);
+        Self { segments }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns true if the path points to the document root.
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Returns true if this path, which may contain wildcards, matches a concrete path.
+    pub fn matches(&self, path: &JsonPath) -> bool {
+        self.segments.len() == path.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(&path.segments)
+                .all(|(pattern, segment)| match (pattern, segment) {
+                    (Segment::Wildcard, _) => true,
+                    (Segment::Key(k), Segment::Index(i)) | (Segment::Index(i), Segment::Key(k)) => {
+                        *k == i.to_string()
+                    }
+                    _ => pattern == segment,
+                })
+    }
+
+    /// All values matched by the path.
+    pub fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![value];
+
+        for segment in &self.segments {
+            current = current
+                .into_iter()
+                .flat_map(|v| segment.children(v))
+                .collect();
+        }
+
+        current
+    }
+
+    /// Call `f` on every value matched by the path.
+    pub fn update<F>(&self, value: &mut Value, f: F) -> Result<()>
+    where
+        F: FnMut(&mut Value) -> Result<()>,
+    {
+        let mut current = vec![value];
+
+        for segment in &self.segments {
+            current = current
+                .into_iter()
+                .flat_map(|v| segment.children_mut(v))
+                .collect();
+        }
+
+        current.into_iter().try_for_each(f)
+    }
+
+    /// Remove every value matched by the path. Returns the number of removed values.
+    pub fn remove(&self, value: &mut Value) -> usize {
+        match self.segments.split_last() {
+            Some((last, parents)) => remove_in(value, parents, last),
+            None => 0,
+        }
+    }
+
+    /// Remove every value matched by any of the paths. Array elements are removed from
+    /// the highest index down, so that a removal does not shift the elements the next
+    /// paths point to.
+    pub fn remove_all(paths: &[JsonPath], value: &mut Value) -> usize {
+        let mut paths: Vec<&JsonPath> = paths.iter().collect();
+        paths.sort_by(|a, b| {
+            let ordering = a
+                .segments
+                .iter()
+                .zip(&b.segments)
+                .map(|(a, b)| a.position_cmp(b))
+                .find(|o| o.is_ne());
+            ordering
+                .unwrap_or_else(|| a.segments.len().cmp(&b.segments.len()))
+                .reverse()
+        });
+
+        paths.into_iter().map(|path| path.remove(value)).sum()
+    }
+
+    /// Set the value at the path, creating the missing objects and arrays on the way.
+    /// An index equal to the length of an array appends to it.
+    pub fn set(&self, root: &mut Value, value: Value) -> Result<()> {
+        *self.entry(root)? = value;
+        Ok(())
+    }
+
+    /// Append a value to the array at the path, creating the array if missing.
+    pub fn push(&self, root: &mut Value, value: Value) -> Result<()> {
+        match self.entry(root)? {
+            Value::Array(arr) => arr.push(value),
+            v @ Value::Null => *v = Value::Array(vec![value]),
+            v => return Err(anyhow!("cannot append to `{}`: it is {}", self, kind(v))),
+        }
+        Ok(())
+    }
+
+    /// The value at the path, created as `null` if missing.
+    fn entry<'a>(&self, root: &'a mut Value) -> Result<&'a mut Value> {
+        let mut current = root;
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            if current.is_null() {
+                *current = match segment {
+                    Segment::Index(_) => Value::Array(vec![]),
+                    _ => Value::Object(Default::default()),
+                };
+            }
+
+            let parent = || JsonPath::new(self.segments[..i].to_vec());
+            current = match (segment, current) {
+                (Segment::Wildcard, _) => {
+                    return Err(anyhow!("cannot set `{}`: wildcards are not allowed", self))
+                }
+                (Segment::Key(k), Value::Object(obj)) => {
+                    obj.entry(k.clone()).or_insert(Value::Null)
+                }
+                (segment, Value::Array(arr)) => {
+                    let index = match segment {
+                        Segment::Index(i) => *i,
+                        Segment::Key(k) => k.parse().map_err(|_| {
+                            anyhow!("cannot set `{}`: `{}` is an array", self, parent())
+                        })?,
+                        Segment::Wildcard => unreachable!(),
+                    };
+                    if index == arr.len() {
+                        arr.push(Value::Null);
+                    }
+                    let len = arr.len();
+                    arr.get_mut(index).ok_or_else(|| {
+                        anyhow!(
+                            "cannot set `{}`: `{}` has only {} elements",
+                            self,
+                            parent(),
+                            len
+                        )
+                    })?
+                }
+                (_, v) => {
+                    return Err(anyhow!(
+                        "cannot set `{}`: `{}` is {}",
+                        self,
+                        parent(),
+                        kind(v)
+                    ))
+                }
+            };
+        }
+
+        Ok(current)
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+impl Segment {
+    fn children<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        match (self, value) {
+            (Segment::Wildcard, Value::Object(obj)) => obj.values().collect(),
+            (Segment::Wildcard, Value::Array(arr)) => arr.iter().collect(),
+            (Segment::Key(k), Value::Object(obj)) => obj.get(k).into_iter().collect(),
+            (Segment::Key(k), Value::Array(arr)) => k
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| arr.get(i))
+                .into_iter()
+                .collect(),
+            (Segment::Index(i), Value::Array(arr)) => arr.get(*i).into_iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    fn children_mut<'a>(&self, value: &'a mut Value) -> Vec<&'a mut Value> {
+        match (self, value) {
+            (Segment::Wildcard, Value::Object(obj)) => obj.values_mut().collect(),
+            (Segment::Wildcard, Value::Array(arr)) => arr.iter_mut().collect(),
+            (Segment::Key(k), Value::Object(obj)) => obj.get_mut(k).into_iter().collect(),
+            (Segment::Key(k), Value::Array(arr)) => match k.parse::<usize>() {
+                Ok(i) => arr.get_mut(i).into_iter().collect(),
+                Err(_) => vec![],
+            },
+            (Segment::Index(i), Value::Array(arr)) => arr.get_mut(*i).into_iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    fn index(&self) -> Option<usize> {
+        match self {
+            Segment::Index(i) => Some(*i),
+            Segment::Key(k) => k.parse().ok(),
+            Segment::Wildcard => None,
+        }
+    }
+
+    /// Order of segments where indices compare by value.
+    fn position_cmp(&self, other: &Segment) -> Ordering {
+        match (self.index(), other.index()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self.to_string().cmp(&other.to_string()),
+        }
+    }
+
+    fn remove_from(&self, value: &mut Value) -> usize {
+        match (self, value) {
+            (Segment::Wildcard, Value::Object(obj)) => {
+                let len = obj.len();
+                obj.clear();
+                len
+            }
+            (Segment::Wildcard, Value::Array(arr)) => {
+                let len = arr.len();
+                arr.clear();
+                len
+            }
+            (Segment::Key(k), Value::Object(obj)) => obj.remove(k).map_or(0, |_| 1),
+            (Segment::Key(k), Value::Array(arr)) => match k.parse::<usize>() {
+                Ok(i) if i < arr.len() => {
+                    arr.remove(i);
+                    1
+                }
+                _ => 0,
+            },
+            (Segment::Index(i), Value::Array(arr)) if *i < arr.len() => {
+                arr.remove(*i);
+                1
+            }
+            _ => 0,
+        }
+    }
+}
+
+fn remove_in(value: &mut Value, parents: &[Segment], last: &Segment) -> usize {
+    match parents.split_first() {
+        Some((first, rest)) => first
+            .children_mut(value)
+            .into_iter()
+            .map(|child| remove_in(child, rest, last))
+            .sum(),
+        None => last.remove_from(value),
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if s.starts_with('/') {
+            return Ok(parse_pointer(s));
+        }
+
+        parse_path(s).map_err(|e| anyhow!("invalid json path `{}`: {}", s, e))
+    }
+}
+
+fn parse_pointer(s: &str) -> JsonPath {
+    let segments = s[1..]
+        .split('/')
+        .map(|token| match token {
+            "*" => Segment::Wildcard,
+            _ => Segment::Key(token.replace("~1", "/").replace("~0", "~")),
+        })
+        .collect();
+
+    JsonPath { segments }
+}
+
+fn parse_path(s: &str) -> Result<JsonPath> {
+    let rest = s.strip_prefix('$').unwrap_or(s);
+    let mut chars = rest.chars().peekable();
+    let mut segments = vec![];
+    let mut expect_key = !rest.starts_with(['.', '[']) && !rest.is_empty();
+
+    while let Some(c) = chars.peek().copied() {
+        match c {
+            '.' if expect_key => return Err(anyhow!("empty key")),
+            '.' => {
+                chars.next();
+                expect_key = true;
+            }
+            '[' => {
+                chars.next();
+                let mut inner = String::new();
+                let mut quote = None;
+                let mut quoted = false;
+                loop {
+                    match chars.next() {
+                        Some(c) if Some(c) == quote => quote = None,
+                        Some(c @ ('\'' | '"')) if !quoted && inner.is_empty() => {
+                            quote = Some(c);
+                            quoted = true;
+                        }
+                        Some(']') if quote.is_none() => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(anyhow!("unclosed `[`")),
+                    }
+                }
+
+                let segment = match inner.as_str() {
+                    _ if quoted => Segment::Key(inner),
+                    "*" => Segment::Wildcard,
+                    _ => Segment::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| anyhow!("`{}` is not an array index", inner))?,
+                    ),
+                };
+                segments.push(segment);
+                expect_key = false;
+            }
+            _ if expect_key => {
+                let mut key = String::new();
+                while let Some(c) = chars.peek().copied() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    key.push(c);
+                    chars.next();
+                }
+
+                segments.push(match key.as_str() {
+                    "*" => Segment::Wildcard,
+                    "" => return Err(anyhow!("empty key")),
+                    _ => Segment::Key(key),
+                });
+                expect_key = false;
+            }
+            c => return Err(anyhow!("unexpected `{}`", c)),
+        }
+    }
+
+    if expect_key {
+        return Err(anyhow!("path must not end with `.`"));
+    }
+
+    Ok(JsonPath { segments })
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "$")?;
+        for segment in &self.segments {
+            write!(f, "{}", segment)?;
+        }
+        std::fmt::Result::Ok(())
+    }
+}
+
+impl Serialize for JsonPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Segment::Key(k) if k.contains('"') => write!(f, "['{}']", k),
+            Segment::Key(k) if k == "*" || k.is_empty() || k.contains(['.', '[', ']', '\'']) => {
+                write!(f, "[\"{}\"]", k)
+            }
+            Segment::Key(k) => write!(f, ".{}", k),
+            Segment::Index(i) => write!(f, "[{}]", i),
+            Segment::Wildcard => write!(f, "[*]"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_json_path_should_work() {
+        let path: JsonPath = "data.items[*].updated_at".parse().unwrap();
+        assert_eq!(
+            path.segments(),
+            &[
+                Segment::Key("data".into()),
+                Segment::Key("items".into()),
+                Segment::Wildcard,
+                Segment::Key("updated_at".into()),
+            ]
+        );
+
+        let path: JsonPath = "$.data['a.b'][0]".parse().unwrap();
+        assert_eq!(
+            path.segments(),
+            &[
+                Segment::Key("data".into()),
+                Segment::Key("a.b".into()),
+                Segment::Index(0)
+            ]
+        );
+
+        let path: JsonPath = "/data/items/*/a~1b".parse().unwrap();
+        assert_eq!(
+            path.segments(),
+            &[
+                Segment::Key("data".into()),
+                Segment::Key("items".into()),
+                Segment::Wildcard,
+                Segment::Key("a/b".into()),
+            ]
+        );
+
+        assert!("$".parse::<JsonPath>().unwrap().is_root());
+    }
+
+    #[test]
+    fn parse_invalid_json_path_should_fail() {
+        assert!("data.".parse::<JsonPath>().is_err());
+        assert!("data[0".parse::<JsonPath>().is_err());
+        assert!("data..id".parse::<JsonPath>().is_err());
+        assert!("data[x]".parse::<JsonPath>().is_err());
+    }
+
+    #[test]
+    fn json_path_select_should_work() {
+        let value = json!({"data": {"items": [{"id": 1}, {"id": 2}]}});
+        let path: JsonPath = "data.items[*].id".parse().unwrap();
+
+        assert_eq!(path.select(&value), vec![&json!(1), &json!(2)]);
+    }
+
+    #[test]
+    fn json_path_remove_should_work() {
+        let mut value = json!({
+            "id": 1,
+            "data": {"items": [{"id": 1, "updated_at": "x"}, {"id": 2, "updated_at": "y"}]}
+        });
+        let path: JsonPath = "data.items[*].updated_at".parse().unwrap();
+
+        assert_eq!(path.remove(&mut value), 2);
+        assert_eq!(
+            value,
+            json!({"id": 1, "data": {"items": [{"id": 1}, {"id": 2}]}})
+        );
+
+        let mut value = json!([{"id": 1, "name": "a"}, {"id": 2, "name": "b"}]);
+        let path: JsonPath = "[*].id".parse().unwrap();
+
+        assert_eq!(path.remove(&mut value), 2);
+        assert_eq!(value, json!([{"name": "a"}, {"name": "b"}]));
+
+        let mut value = json!({"items": [0, 1, 2, 3], "a": [[0, 1], [2, 3]]});
+        let paths: Vec<JsonPath> = ["items[0]", "items.2", "items[1]", "a[0][0]", "a[1]"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+
+        assert_eq!(JsonPath::remove_all(&paths, &mut value), 5);
+        assert_eq!(value, json!({"items": [3], "a": [[1]]}));
+    }
+
+    #[test]
+    fn json_path_set_and_push_should_work() {
+        let mut value = json!({"items": [{"qty": 1}]});
+        let set = |value: &mut Value, path: &str, v: Value| {
+            path.parse::<JsonPath>().unwrap().set(value, v)
+        };
+
+        set(&mut value, "user.tags[0]", json!("a")).unwrap();
+        set(&mut value, "items.0.qty", json!(2)).unwrap();
+        set(&mut value, "items[1]", json!({"qty": 3})).unwrap();
+        "user.tags"
+            .parse::<JsonPath>()
+            .unwrap()
+            .push(&mut value, json!("b"))
+            .unwrap();
+        assert_eq!(
+            value,
+            json!({"items": [{"qty": 2}, {"qty": 3}], "user": {"tags": ["a", "b"]}})
+        );
+
+        let err = |path: &str| {
+            set(&mut value.clone(), path, json!(0))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err("items[5]"),
+            "cannot set `$.items[5]`: `$.items` has only 2 elements"
+        );
+        assert_eq!(
+            err("items.qty"),
+            "cannot set `$.items.qty`: `$.items` is an array"
+        );
+        assert_eq!(
+            err("items[0].qty.x"),
+            "cannot set `$.items[0].qty.x`: `$.items[0].qty` is a number"
+        );
+        assert!(err("items[*].qty").contains("wildcards"));
+    }
+
+    #[test]
+    fn json_path_matches_should_work() {
+        let pattern: JsonPath = "items[*].price".parse().unwrap();
+
+        assert!(pattern.matches(&"items[3].price".parse().unwrap()));
+        assert!(pattern.matches(&"items.0.price".parse().unwrap()));
+        assert!(!pattern.matches(&"items[3].name".parse().unwrap()));
+        assert!(!pattern.matches(&"items[3].price.value".parse().unwrap()));
+        assert!("a[1]"
+            .parse::<JsonPath>()
+            .unwrap()
+            .matches(&"a.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn json_path_display_should_round_trip() {
+        let path: JsonPath = "data['a.b'][*].c[1]".parse().unwrap();
+
+        assert_eq!(path.to_string(), "$.data[\"a.b\"][*].c[1]");
+        assert_eq!(path.to_string().parse::<JsonPath>().unwrap(), path);
+
+        let path = JsonPath::new(vec![
+            Segment::Key("*".into()),
+            Segment::Wildcard,
+            Segment::Key("say \"hi\"".into()),
+        ]);
+        assert_eq!(path.to_string(), "$[\"*\"][*]['say \"hi\"']");
+        assert_eq!(path.to_string().parse::<JsonPath>().unwrap(), path);
+    }
+}
@@ -1,32 +1,38 @@
+mod auth;
 pub mod cli;
+mod client;
 mod config;
+pub mod diff;
+pub mod export;
+pub mod import;
+pub mod json_path;
+pub mod session;
+mod sign;
+pub mod snapshot;
 mod utils;
 
-use cli::KeyValType;
+pub use auth::{ApiKeyLocation, Auth, OAuth2ClientCredentials};
+use cli::ArgValue;
+pub use client::{ClientOptions, HttpClient, RetryPolicy, TransportOptions};
 pub use config::{
-    get_body_text, get_header_text, get_status_text, DiffConfig, DiffProfile, LoadConfig,
-    RequestConfig, RequestProfile, ResponseProfile, ValidateConfig,
+    format_body_text, get_body_text, get_header_text, get_status_text, Assertion, Body, Capture,
+    DiffConfig, DiffMode, DiffProfile, Expect, HeaderExpect, JsonExpect, JsonType, LoadConfig,
+    Mask, MaskPreset, MaskTarget, Part, Pattern, RequestConfig, RequestProfile, ResponseExt,
+    ResponseProfile, ResponseText, StatusExpect, ValidateConfig,
 };
-pub use utils::{diff_text, highlight_text, process_error_output};
+pub use diff::{
+    diff_text, render_terminal, render_unified, CompareOnly, CompareRule, DiffResult, DiffSummary,
+    PatchOperation,
+};
+pub use json_path::JsonPath;
+pub use sign::{AwsSigV4, HmacAlgorithm, HmacSigning, SignatureEncoding, Signing};
+pub use utils::{error_chain, highlight_text, process_error_output};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ExtraArgs {
     pub headers: Vec<(String, String)>,
-    pub query: Vec<(String, String)>,
-    pub body: Vec<(String, String)>,
-}
-
-impl IntoIterator for ExtraArgs {
-    type Item = (KeyValType, Vec<(String, String)>);
-    type IntoIter = std::array::IntoIter<(KeyValType, Vec<(String, String)>), 3>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        IntoIterator::into_iter([
-            (KeyValType::Header, self.headers),
-            (KeyValType::Query, self.query),
-            (KeyValType::Body, self.body),
-        ])
-    }
+    pub query: Vec<(String, ArgValue)>,
+    pub body: Vec<(String, ArgValue)>,
 }
 
 impl ExtraArgs {
@@ -37,7 +43,7 @@ impl ExtraArgs {
         }
     }
 
-    pub fn new_with_query(query: Vec<(String, String)>) -> Self {
+    pub fn new_with_query(query: Vec<(String, ArgValue)>) -> Self {
         Self {
             query,
             ..Default::default()
@@ -0,0 +1,476 @@
+use anyhow::{Context, Result};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use reqwest::{
+    cookie::CookieStore,
+    header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+use crate::config::is_default;
+use crate::snapshot::FILE_NAME;
+
+/// Default directory holding the sessions.
+pub const DEFAULT_SESSION_DIR: &str = ".xdiff/sessions";
+
+/// Cookies kept between runs, sent back to the servers that set them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CookieJar {
+    #[serde(default)]
+    pub cookies: Vec<Cookie>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+
+    /// Unix time after which the cookie is dropped. Kept until cleared when not set.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub expires: Option<u64>,
+
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub secure: bool,
+
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub http_only: bool,
+
+    /// Only sent to the host that set it, and not to its subdomains.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub host_only: bool,
+}
+
+/// A jar shared with the http client, which updates it on every response, including
+/// the redirects it follows.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SharedJar(pub Arc<Mutex<CookieJar>>);
+
+/// Cookie jars, stored as one yaml file per named session.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl CookieJar {
+    /// Add the cookies matching the url to the `cookie` header. Cookies already in
+    /// the header win over the ones of the jar.
+    pub fn add_to(&self, url: &Url, headers: &mut HeaderMap) -> Result<()> {
+        self.add_to_at(url, headers, now())
+    }
+
+    /// Update the jar with the `set-cookie` headers of a response.
+    pub fn store(&mut self, url: &Url, headers: &HeaderMap) {
+        self.store_at(url, headers, now())
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    fn add_to_at(&self, url: &Url, headers: &mut HeaderMap, now: u64) -> Result<()> {
+        let mut pairs: Vec<String> = headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .map(|pair| pair.trim().to_string())
+            .filter(|pair| !pair.is_empty())
+            .collect();
+        let explicit: Vec<String> = pairs
+            .iter()
+            .map(|pair| pair.split('=').next().unwrap_or_default().to_string())
+            .collect();
+
+        let mut matching: Vec<_> = self
+            .cookies
+            .iter()
+            .filter(|c| c.matches(url, now) && !explicit.contains(&c.name))
+            .collect();
+        // more specific paths first, as browsers do
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        pairs.extend(matching.iter().map(|c| format!("{}={}", c.name, c.value)));
+
+        if !pairs.is_empty() {
+            headers.insert(COOKIE, HeaderValue::from_str(&pairs.join("; "))?);
+        }
+
+        Ok(())
+    }
+
+    fn store_at(&mut self, url: &Url, headers: &HeaderMap, now: u64) {
+        self.store_values(url, &mut headers.get_all(SET_COOKIE).iter(), now)
+    }
+
+    fn store_values(
+        &mut self,
+        url: &Url,
+        values: &mut dyn Iterator<Item = &HeaderValue>,
+        now: u64,
+    ) {
+        for value in values {
+            let Some(cookie) = value
+                .to_str()
+                .ok()
+                .and_then(|v| parse_set_cookie(v, url, now))
+            else {
+                continue;
+            };
+
+            self.cookies.retain(|c| {
+                !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+            });
+            if !cookie.is_expired(now) {
+                self.cookies.push(cookie);
+            }
+        }
+
+        self.cookies.retain(|c| !c.is_expired(now));
+    }
+}
+
+impl CookieStore for SharedJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let mut jar = self.0.lock().expect("cookie jar poisoned");
+        jar.store_values(url, cookie_headers, now());
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        let jar = self.0.lock().expect("cookie jar poisoned");
+        jar.add_to(url, &mut headers).ok()?;
+        headers.remove(COOKIE)
+    }
+}
+
+impl Cookie {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|at| at <= now)
+    }
+
+    fn matches(&self, url: &Url, now: u64) -> bool {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let domain_match = match self.host_only {
+            true => host == self.domain,
+            false => domain_matches(&host, &self.domain),
+        };
+
+        domain_match
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+            && !self.is_expired(now)
+    }
+}
+
+/// Parse a `set-cookie` header, rejecting cookies for another domain.
+fn parse_set_cookie(header: &str, url: &Url, now: u64) -> Option<Cookie> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let host = url.host_str()?.to_ascii_lowercase();
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: value.trim().trim_matches('"').to_string(),
+        domain: host.clone(),
+        path: default_path(url),
+        host_only: true,
+        ..Default::default()
+    };
+
+    let mut max_age = None;
+    let mut expires = None;
+    for attr in parts {
+        let (key, value) = attr.split_once('=').unwrap_or((attr, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if !domain_matches(&host, &domain) {
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" => expires = parse_http_date(value),
+            "secure" => cookie.secure = true,
+            "httponly" => cookie.http_only = true,
+            _ => {}
+        }
+    }
+
+    // max-age wins over expires, and a non positive one deletes the cookie
+    cookie.expires = match max_age {
+        Some(secs) if secs <= 0 => Some(0),
+        Some(secs) => Some(now.saturating_add(secs as u64)),
+        None => expires,
+    };
+
+    Some(cookie)
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || path
+            .strip_prefix(cookie_path)
+            .is_some_and(|rest| cookie_path.ends_with('/') || rest.starts_with('/'))
+}
+
+/// The directory of the request path, as defined by RFC 6265.
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".into(),
+        Some(idx) => url.path()[..idx].to_string(),
+    }
+}
+
+/// Parse dates like `Wed, 21 Oct 2015 07:28:00 GMT` or `Wednesday, 21-Oct-15 07:28:00 GMT`.
+fn parse_http_date(text: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let (mut day, mut month, mut year, mut time) = (None, None, None, None);
+    for token in text.split([' ', ',', '-']).filter(|t| !t.is_empty()) {
+        let lower = token.to_ascii_lowercase();
+        if token.contains(':') {
+            let parts: Vec<u64> = token.split(':').filter_map(|p| p.parse().ok()).collect();
+            if let [h, m, s] = parts[..] {
+                time = Some(h * 3600 + m * 60 + s);
+            }
+        } else if let Some(idx) = MONTHS.iter().position(|m| lower.starts_with(m)) {
+            month = Some(idx as i64 + 1);
+        } else if let Ok(number) = token.parse::<i64>() {
+            match (token.len(), day) {
+                (1 | 2, None) => day = Some(number),
+                (2, Some(_)) if number < 70 => year = Some(2000 + number),
+                (2, Some(_)) => year = Some(1900 + number),
+                _ => year = Some(number),
+            }
+        }
+    }
+
+    let days = days_from_civil(year?, month?, day?);
+    Some(u64::try_from(days * 86400).ok()? + time?)
+}
+
+/// Days since the epoch of a date, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the cookie jar of a session. Names are percent-encoded like the snapshot
+    /// names, so that distinct sessions get distinct files.
+    pub fn path(&self, name: &str) -> PathBuf {
+        let file_name = utf8_percent_encode(name, FILE_NAME);
+        self.dir.join(format!("{}.yaml", file_name))
+    }
+
+    /// Names of the stored sessions, sorted.
+    pub async fn list(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e.into()),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "yaml") {
+                if let Some(stem) = path.file_stem() {
+                    let stem = stem.to_string_lossy();
+                    names.push(percent_decode_str(&stem).decode_utf8_lossy().into_owned());
+                }
+            }
+        }
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Load the cookie jar of a session, empty if the session does not exist yet.
+    pub async fn load(&self, name: &str) -> Result<CookieJar> {
+        let path = self.path(name);
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("invalid session file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CookieJar::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, name: &str, jar: &CookieJar) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        fs::write(self.path(name), serde_yaml::to_string(jar)?).await?;
+
+        Ok(())
+    }
+
+    /// Delete a session, returning whether it existed.
+    pub async fn clear(&self, name: &str) -> Result<bool> {
+        match fs::remove_file(self.path(name)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_SESSION_DIR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_cookies(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(SET_COOKIE, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn cookie_header(jar: &CookieJar, url: &str, now: u64) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        jar.add_to_at(&url.parse().unwrap(), &mut headers, now)
+            .unwrap();
+        headers.get(COOKIE).map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn http_date_should_parse() {
+        assert_eq!(
+            parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(1445412480)
+        );
+        assert_eq!(
+            parse_http_date("Wednesday, 21-Oct-15 07:28:00 GMT"),
+            Some(1445412480)
+        );
+        assert_eq!(parse_http_date("tomorrow"), None);
+    }
+
+    #[test]
+    fn cookie_jar_should_match_domain_path_and_expiry() {
+        let url: Url = "https://api.example.com/auth/login".parse().unwrap();
+        let mut jar = CookieJar::default();
+        jar.store_at(
+            &url,
+            &set_cookies(&[
+                "sid=abc; Path=/; HttpOnly",
+                "pref=dark; Domain=.example.com; Path=/; Max-Age=60",
+                "step=2",
+                "token=t; Secure",
+                "evil=1; Domain=other.com",
+            ]),
+            1000,
+        );
+        assert_eq!(jar.len(), 4);
+
+        assert_eq!(
+            cookie_header(&jar, "https://api.example.com/auth/me", 1000).as_deref(),
+            Some("step=2; token=t; sid=abc; pref=dark")
+        );
+        assert_eq!(
+            cookie_header(&jar, "http://www.example.com/", 1000).as_deref(),
+            Some("pref=dark")
+        );
+        assert_eq!(cookie_header(&jar, "http://www.example.com/", 1060), None);
+
+        // replaced, then deleted
+        jar.store_at(&url, &set_cookies(&["sid=def; Path=/"]), 1000);
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("step=9"));
+        jar.add_to_at(
+            &"https://api.example.com/auth/".parse().unwrap(),
+            &mut headers,
+            1000,
+        )
+        .unwrap();
+        assert_eq!(headers[COOKIE], "step=9; token=t; pref=dark; sid=def");
+
+        jar.store_at(&url, &set_cookies(&["sid=; Path=/; Max-Age=0"]), 1000);
+        assert_eq!(
+            cookie_header(&jar, "https://api.example.com/", 1000).as_deref(),
+            Some("pref=dark")
+        );
+    }
+
+    #[tokio::test]
+    async fn session_store_should_round_trip() {
+        let dir = std::env::temp_dir().join(format!("xdiff-sessions-{}", std::process::id()));
+        let store = SessionStore::new(&dir);
+        assert!(store.list().await.unwrap().is_empty());
+        assert!(store.load("dev").await.unwrap().is_empty());
+
+        let mut jar = CookieJar::default();
+        jar.store(
+            &"http://localhost/".parse().unwrap(),
+            &set_cookies(&["sid=abc"]),
+        );
+        store.save("dev", &jar).await.unwrap();
+
+        assert_eq!(store.load("dev").await.unwrap(), jar);
+        assert_eq!(store.list().await.unwrap(), vec!["dev".to_string()]);
+        assert!(store.clear("dev").await.unwrap());
+        assert!(!store.clear("dev").await.unwrap());
+
+        store.save("a/b", &jar).await.unwrap();
+        store.save("a_b", &jar).await.unwrap();
+        assert_ne!(store.path("a/b"), store.path("a_b"));
+        assert_eq!(
+            store.list().await.unwrap(),
+            vec!["a/b".to_string(), "a_b".to_string()]
+        );
+
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+}
@@ -0,0 +1,546 @@
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION},
+    Request,
+};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::is_default;
+
+/// Signature of a request, from the `sign:` block of a profile.
+///
+/// The request is signed last, once the query, headers, body and auth are final.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Signing {
+    /// AWS Signature Version 4, in the `authorization` header.
+    AwsSigv4(AwsSigV4),
+
+    /// An HMAC of a templated string, in a header.
+    Hmac(HmacSigning),
+}
+
+/// Missing fields are read from the usual `AWS_*` env vars.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AwsSigV4 {
+    pub service: String,
+
+    /// Defaults to `AWS_REGION`, then `AWS_DEFAULT_REGION`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub region: Option<String>,
+
+    /// Defaults to `AWS_ACCESS_KEY_ID`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub access_key_id: Option<String>,
+
+    /// Defaults to `AWS_SECRET_ACCESS_KEY`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub secret_access_key: Option<String>,
+
+    /// Defaults to `AWS_SESSION_TOKEN`, sent as `x-amz-security-token` when set.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub session_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HmacSigning {
+    pub secret: String,
+
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub algorithm: HmacAlgorithm,
+
+    /// Header holding the signature, `x-signature` by default.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub header: Option<String>,
+
+    /// Text put before the signature in the header, e.g. `sha256=`.
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub prefix: String,
+
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub encoding: SignatureEncoding,
+
+    /// The signed string. Placeholders: `{method}`, `{host}`, `{path}`, `{query}`,
+    /// `{timestamp}`, `{body}`, `{body_sha256}` and `{header:<name>}`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub template: Option<String>,
+
+    /// Header receiving the unix timestamp used for `{timestamp}`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub timestamp_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HmacAlgorithm {
+    Sha1,
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+const DEFAULT_SIGNATURE_HEADER: &str = "x-signature";
+const DEFAULT_TEMPLATE: &str = "{method}\n{path}\n{query}\n{body_sha256}";
+
+impl Signing {
+    pub fn apply(&self, req: &mut Request) -> Result<()> {
+        self.apply_at(req, SystemTime::now())
+    }
+
+    fn apply_at(&self, req: &mut Request, now: SystemTime) -> Result<()> {
+        match self {
+            Signing::AwsSigv4(aws) => aws.sign(req, now),
+            Signing::Hmac(hmac) => hmac.sign(req, now),
+        }
+    }
+}
+
+impl AwsSigV4 {
+    fn sign(&self, req: &mut Request, now: SystemTime) -> Result<()> {
+        let region = setting(
+            &self.region,
+            &["AWS_REGION", "AWS_DEFAULT_REGION"],
+            "region",
+        )?;
+        let access_key_id = setting(&self.access_key_id, &["AWS_ACCESS_KEY_ID"], "access_key_id")?;
+        let secret = setting(
+            &self.secret_access_key,
+            &["AWS_SECRET_ACCESS_KEY"],
+            "secret_access_key",
+        )?;
+        let session_token = self
+            .session_token
+            .clone()
+            .or_else(|| env::var("AWS_SESSION_TOKEN").ok());
+
+        let (date, time) = utc_date_time(now);
+        let amz_date = format!("{}T{}Z", date, time);
+        let payload_hash = hex(digest::digest(&digest::SHA256, body_bytes(req)?).as_ref());
+
+        let headers = req.headers_mut();
+        headers.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+        if self.service == "s3" {
+            headers.insert(
+                "x-amz-content-sha256",
+                HeaderValue::from_str(&payload_hash)?,
+            );
+        }
+        if let Some(token) = session_token {
+            let mut value = HeaderValue::from_str(&token)?;
+            value.set_sensitive(true);
+            headers.insert("x-amz-security-token", value);
+        }
+        // a previous signature or credentials would otherwise be signed too
+        headers.remove(AUTHORIZATION);
+
+        let (canonical_headers, signed_headers) = canonical_headers(req)?;
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            req.method(),
+            canonical_uri(req, self.service != "s3"),
+            canonical_query(req),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+        );
+
+        let mut key = format!("AWS4{}", secret).into_bytes();
+        for part in [&date, &region, &self.service, "aws4_request"] {
+            key = hmac_sign(hmac::HMAC_SHA256, &key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sign(
+            hmac::HMAC_SHA256,
+            &key,
+            string_to_sign.as_bytes(),
+        ));
+
+        let mut value = HeaderValue::from_str(&format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            access_key_id, scope, signed_headers, signature
+        ))?;
+        value.set_sensitive(true);
+        req.headers_mut().insert(AUTHORIZATION, value);
+
+        Ok(())
+    }
+}
+
+impl HmacSigning {
+    fn sign(&self, req: &mut Request, now: SystemTime) -> Result<()> {
+        let timestamp = now.duration_since(UNIX_EPOCH)?.as_secs().to_string();
+        if let Some(name) = &self.timestamp_header {
+            req.headers_mut().insert(
+                HeaderName::from_str(name)?,
+                HeaderValue::from_str(&timestamp)?,
+            );
+        }
+
+        let template = self.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        let message = render_template(template, req, &timestamp)?;
+
+        let algorithm = match self.algorithm {
+            HmacAlgorithm::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            HmacAlgorithm::Sha256 => hmac::HMAC_SHA256,
+            HmacAlgorithm::Sha384 => hmac::HMAC_SHA384,
+            HmacAlgorithm::Sha512 => hmac::HMAC_SHA512,
+        };
+        let signature = hmac_sign(algorithm, self.secret.as_bytes(), message.as_bytes());
+        let signature = match self.encoding {
+            SignatureEncoding::Hex => hex(&signature),
+            SignatureEncoding::Base64 => STANDARD.encode(signature),
+        };
+
+        let header = self.header.as_deref().unwrap_or(DEFAULT_SIGNATURE_HEADER);
+        let mut value = HeaderValue::from_str(&format!("{}{}", self.prefix, signature))?;
+        value.set_sensitive(true);
+        req.headers_mut()
+            .insert(HeaderName::from_str(header)?, value);
+
+        Ok(())
+    }
+}
+
+fn render_template(template: &str, req: &Request, timestamp: &str) -> Result<String> {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("unclosed placeholder in signing template: {}", template))?
+            + start;
+
+        let name = &rest[start + 1..end];
+        match name {
+            "method" => output.push_str(req.method().as_str()),
+            "host" => output.push_str(&host(req)),
+            "path" => output.push_str(req.url().path()),
+            "query" => output.push_str(req.url().query().unwrap_or_default()),
+            "timestamp" => output.push_str(timestamp),
+            "body" => output.push_str(&String::from_utf8_lossy(body_bytes(req)?)),
+            "body_sha256" => {
+                output.push_str(&hex(
+                    digest::digest(&digest::SHA256, body_bytes(req)?).as_ref()
+                ))
+            }
+            _ => match name.strip_prefix("header:") {
+                Some(header) => {
+                    if let Some(value) = req.headers().get(header) {
+                        output.push_str(value.to_str()?);
+                    }
+                }
+                None => {
+                    return Err(anyhow!(
+                        "unknown placeholder in signing template: {{{}}}",
+                        name
+                    ))
+                }
+            },
+        }
+
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn setting(value: &Option<String>, vars: &[&str], name: &str) -> Result<String> {
+    value
+        .clone()
+        .or_else(|| vars.iter().find_map(|var| env::var(var).ok()))
+        .with_context(|| {
+            format!(
+                "aws_sigv4 {} is not set, nor is {}",
+                name,
+                vars.join(" or ")
+            )
+        })
+}
+
+fn body_bytes(req: &Request) -> Result<&[u8]> {
+    match req.body() {
+        None => Ok(&[]),
+        Some(body) => body
+            .as_bytes()
+            .ok_or_else(|| anyhow!("a streamed body cannot be signed")),
+    }
+}
+
+fn host(req: &Request) -> String {
+    let url = req.url();
+    let host = url.host_str().unwrap_or_default();
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+/// Lowercase names with their trimmed values, sorted, plus the `;` separated list of names.
+fn canonical_headers(req: &Request) -> Result<(String, String)> {
+    let mut headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    headers.insert("host".into(), vec![host(req)]);
+
+    for (name, value) in req.headers() {
+        let value = value
+            .to_str()?
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        headers
+            .entry(name.as_str().to_string())
+            .or_default()
+            .push(value);
+    }
+
+    let mut canonical = String::new();
+    for (name, values) in &headers {
+        writeln!(canonical, "{}:{}", name, values.join(","))?;
+    }
+    let signed = headers.keys().cloned().collect::<Vec<_>>().join(";");
+
+    Ok((canonical, signed))
+}
+
+/// Each path segment is encoded once more, except for S3.
+fn canonical_uri(req: &Request, double_encode: bool) -> String {
+    let path = req.url().path();
+    if path.is_empty() {
+        return "/".into();
+    }
+
+    path.split('/')
+        .map(|segment| {
+            let decoded = percent_decode(segment);
+            let encoded = aws_encode(&decoded);
+            if double_encode {
+                aws_encode(encoded.as_bytes())
+            } else {
+                encoded
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn canonical_query(req: &Request) -> String {
+    let mut pairs: Vec<_> = req
+        .url()
+        .query_pairs()
+        .map(|(k, v)| (aws_encode(k.as_bytes()), aws_encode(v.as_bytes())))
+        .collect();
+    pairs.sort();
+
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn aws_encode(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            output.push(b as char);
+        } else {
+            let _ = write!(output, "%{:02X}", b);
+        }
+    }
+    output
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(b) => {
+                output.push(b);
+                i += 3;
+            }
+            None => {
+                output.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+fn hmac_sign(algorithm: hmac::Algorithm, key: &[u8], message: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(algorithm, key);
+    hmac::sign(&key, message).as_ref().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut output, b| {
+        let _ = write!(output, "{:02x}", b);
+        output
+    })
+}
+
+/// `(YYYYMMDD, HHMMSS)` in UTC.
+fn utc_date_time(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        format!("{:04}{:02}{:02}", year, month, day),
+        format!("{:02}{:02}{:02}", secs / 3600, secs % 3600 / 60, secs % 60),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use std::time::Duration;
+
+    // 2015-08-30T12:36:00Z, the date of the AWS test suite
+    fn test_time() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1440938160)
+    }
+
+    fn aws(service: &str) -> Signing {
+        Signing::AwsSigv4(AwsSigV4 {
+            service: service.into(),
+            region: Some("us-east-1".into()),
+            access_key_id: Some("AKIDEXAMPLE".into()),
+            secret_access_key: Some("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into()),
+            session_token: None,
+        })
+    }
+
+    #[test]
+    fn utc_date_time_should_work() {
+        assert_eq!(
+            utc_date_time(test_time()),
+            ("20150830".into(), "123600".into())
+        );
+        assert_eq!(
+            utc_date_time(UNIX_EPOCH + Duration::from_secs(951782400)),
+            ("20000229".into(), "000000".into())
+        );
+    }
+
+    #[test]
+    fn aws_sigv4_should_match_test_suite() {
+        let mut req = Client::new()
+            .get("https://example.amazonaws.com/")
+            .build()
+            .unwrap();
+        aws("service").apply_at(&mut req, test_time()).unwrap();
+        assert_eq!(req.headers()["x-amz-date"], "20150830T123600Z");
+        assert_eq!(
+            req.headers()[AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+
+        let mut req = Client::new()
+            .get("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")
+            .header(
+                "content-type",
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )
+            .build()
+            .unwrap();
+        aws("iam").apply_at(&mut req, test_time()).unwrap();
+        assert!(req.headers()[AUTHORIZATION].to_str().unwrap().ends_with(
+            "SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        ));
+    }
+
+    #[test]
+    fn canonical_request_parts_should_be_normalized() {
+        let req = Client::new()
+            .get("https://a.com/a b/%7Ec?b=2&a=x%20y&a=1")
+            .build()
+            .unwrap();
+        assert_eq!(canonical_uri(&req, true), "/a%2520b/~c");
+        assert_eq!(canonical_uri(&req, false), "/a%20b/~c");
+        assert_eq!(canonical_query(&req), "a=1&a=x%20y&b=2");
+    }
+
+    #[test]
+    fn hmac_should_sign_templated_string() {
+        let signing: Signing = serde_yaml::from_str(
+            r#"
+type: hmac
+secret: key
+encoding: base64
+header: x-hub-signature
+prefix: "sha256="
+template: "{method} {path}?{query}\n{timestamp}\n{header:x-id}\n{body}"
+timestamp_header: x-timestamp
+"#,
+        )
+        .unwrap();
+
+        let mut req = Client::new()
+            .post("https://a.com/hook?a=1")
+            .header("x-id", "42")
+            .body(r#"{"a":1}"#)
+            .build()
+            .unwrap();
+        signing.apply_at(&mut req, test_time()).unwrap();
+
+        let message = "POST /hook?a=1\n1440938160\n42\n{\"a\":1}";
+        let expected = STANDARD.encode(hmac_sign(hmac::HMAC_SHA256, b"key", message.as_bytes()));
+        assert_eq!(req.headers()["x-timestamp"], "1440938160");
+        assert_eq!(
+            req.headers()["x-hub-signature"],
+            format!("sha256={}", expected).as_str()
+        );
+
+        let req = Client::new().get("https://a.com/").build().unwrap();
+        assert!(render_template("{nope}", &req, "0").is_err());
+    }
+}
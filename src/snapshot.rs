@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::ResponseText;
+
+/// Default directory holding the recorded snapshots.
+pub const DEFAULT_SNAPSHOT_DIR: &str = ".xdiff/snapshots";
+
+/// Characters escaped in the file names of the snapshots.
+pub(crate) const FILE_NAME: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.');
+
+/// Recorded responses, stored as one yaml file per profile.
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the snapshot file of a profile. Other characters than letters, digits,
+    /// `-`, `_` and `.` are percent-encoded, so that distinct names get distinct files.
+    pub fn path(&self, name: &str) -> PathBuf {
+        let file_name = utf8_percent_encode(name, FILE_NAME);
+        self.dir.join(format!("{}.yaml", file_name))
+    }
+
+    pub async fn load(&self, name: &str) -> Result<ResponseText> {
+        let path = self.path(name);
+        let content = fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("no snapshot recorded at {}", path.display()))?;
+
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    pub async fn save(&self, name: &str, text: &ResponseText) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        fs::write(self.path(name), serde_yaml::to_string(text)?).await?;
+
+        Ok(())
+    }
+}
+
+impl Default for SnapshotStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_SNAPSHOT_DIR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_path_should_be_encoded() {
+        let store = SnapshotStore::new("snapshots");
+
+        assert_eq!(
+            store.path("api/todo v1"),
+            PathBuf::from("snapshots/api%2Ftodo%20v1.yaml")
+        );
+        assert_eq!(
+            store.path("api_todo-v1.2"),
+            PathBuf::from("snapshots/api_todo-v1.2.yaml")
+        );
+        assert_ne!(store.path("a b"), store.path("a_b"));
+        assert_ne!(store.path("a%20b"), store.path("a b"));
+    }
+
+    #[tokio::test]
+    async fn snapshot_store_should_round_trip() {
+        let dir = std::env::temp_dir().join(format!("xdiff-snapshots-{}", std::process::id()));
+        let store = SnapshotStore::new(&dir);
+        let text = ResponseText {
+            status: "HTTP/1.1 200 OK".into(),
+            headers: "content-type:\"application/json\"\n\n".into(),
+            body: "{\n  \"id\": 1\n}".into(),
+        };
+
+        store.save("todo", &text).await.unwrap();
+
+        assert_eq!(store.load("todo").await.unwrap(), text);
+        assert!(store.load("missing").await.is_err());
+
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+}
@@ -1,67 +1,13 @@
 use anyhow::{Ok, Result};
 
-use console::{style, Style};
-use similar::{ChangeTag, TextDiff};
-use std::fmt::{self, Write as _};
+use console::Style;
+use std::fmt::Write as _;
 use std::io::Write as _;
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style as HHStyle, ThemeSet};
 use syntect::parsing::SyntaxSet;
 use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 
-struct Line(Option<usize>);
-
-impl fmt::Display for Line {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.0 {
-            Some(idx) => write!(f, "{:<4}", idx + 1),
-            None => write!(f, "    "),
-        }
-    }
-}
-
-pub fn diff_text(text1: &str, text2: &str) -> Result<String> {
-    let mut output = String::new();
-    let diff = TextDiff::from_lines(text1, text2);
-
-    for (idx, group) in diff.grouped_ops(3).iter().enumerate() {
-        if idx > 0 {
-            write!(&mut output, "{:-^1$}", "-", 80)?;
-        }
-
-        for op in group {
-            for change in diff.iter_inline_changes(op) {
-                let (sign, s) = match change.tag() {
-                    ChangeTag::Delete => ("-", Style::new().red()),
-                    ChangeTag::Insert => ("+", Style::new().green()),
-                    ChangeTag::Equal => (" ", Style::new().dim()),
-                };
-                write!(
-                    &mut output,
-                    "{}{} |{}",
-                    style(Line(change.old_index())).dim(),
-                    style(Line(change.new_index())).dim(),
-                    s.apply_to(sign).bold(),
-                )?;
-
-                for (emphasized, value) in change.iter_strings_lossy() {
-                    if emphasized {
-                        write!(&mut output, "{}", s.apply_to(value).underlined().on_black())?;
-                    } else {
-                        write!(&mut output, "{}", s.apply_to(value))?;
-                    }
-                }
-
-                if change.missing_newline() {
-                    writeln!(&mut output)?;
-                }
-            }
-        }
-    }
-
-    Ok(output)
-}
-
 pub fn highlight_text(text: &str, extension: &str, theme: Option<&str>) -> Result<String> {
     let ps = SyntaxSet::load_defaults_newlines();
     let ts = ThemeSet::load_defaults();
@@ -72,7 +18,7 @@ pub fn highlight_text(text: &str, extension: &str, theme: Option<&str>) -> Resul
 
     let mut h = HighlightLines::new(
         syntax.unwrap(),
-        &ts.themes[theme.unwrap_or_else(|| "base16-ocean.dark")],
+        &ts.themes[theme.unwrap_or("base16-ocean.dark")],
     );
 
     let mut output = String::new();
@@ -87,6 +33,24 @@ pub fn highlight_text(text: &str, extension: &str, theme: Option<&str>) -> Resul
     Ok(output)
 }
 
+/// An error followed by its causes, on one line. Causes already printed by the error
+/// before them, as reqwest does, are not repeated.
+pub fn error_chain(error: &anyhow::Error) -> String {
+    let mut message = String::new();
+    for cause in error.chain() {
+        let text = cause.to_string();
+        if message.contains(&text) {
+            continue;
+        }
+        if !message.is_empty() {
+            message.push_str(": ");
+        }
+        message.push_str(&text);
+    }
+
+    message
+}
+
 pub fn process_error_output(error: Result<()>) -> Result<()> {
     if let Err(e) = error {
         let stderr = std::io::stderr();
@@ -102,3 +66,22 @@ pub fn process_error_output(error: Result<()>) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn error_chain_should_skip_repeated_causes() {
+        let error = Err::<(), _>(anyhow!("connection refused"))
+            .context("error sending request: connection refused")
+            .context("failed to send req1")
+            .unwrap_err();
+
+        assert_eq!(
+            error_chain(&error),
+            "failed to send req1: error sending request: connection refused"
+        );
+    }
+}